@@ -5,7 +5,8 @@
 use anyhow::Result;
 use criterion::{criterion_group, criterion_main, Criterion};
 use cshannon::{
-    run, Args, Command, CompressArgs, DecompressArgs, EncodingScheme, TokenizationScheme,
+    run, Args, Charset, Command, CompressArgs, DecompressArgs, EncodingScheme, TokenizationScheme,
+    Transform,
 };
 use env_logger;
 use std::fs;
@@ -63,6 +64,8 @@ fn roundtrip(tokenization_scheme: TokenizationScheme, encoding_scheme: EncodingS
         command: Command::Compress(CompressArgs {
             tokenization_scheme,
             encoding_scheme,
+            transform: Transform::None,
+            charset: Charset::Utf8,
         }),
         input_file: &input_file.as_path(),
         output_file: &compressed_file.as_path(),
@@ -0,0 +1,25 @@
+// Copyright 2020 Prathmesh Prabhu
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+mod util;
+
+fuzz_target!(|data: &[u8]| {
+    // Intent is to check tokens that are not fixed width so we use the simplest
+    // encoding.
+    util::roundtrip("byte_pair", "balanced_tree", data);
+});
@@ -19,9 +19,10 @@ use libfuzzer_sys::fuzz_target;
 mod util;
 
 fuzz_target!(|data: &[u8]| {
-    if let Ok(_) = std::str::from_utf8(data) {
-        // Intent is to check tokens that are not fixed width so we use the simplest
-        // encoding.
-        util::roundtrip("grapheme", "balanced_tree", data);
-    }
+    // Intent is to check tokens that are not fixed width so we use the simplest
+    // encoding.
+    //
+    // Input need not be valid utf-8: invalid sequences are tokenized as raw
+    // bytes.
+    util::roundtrip("grapheme", "balanced_tree", data);
 });
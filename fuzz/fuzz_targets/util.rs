@@ -14,13 +14,33 @@
 
 use anyhow::Result;
 use cshannon::{
-    run, Args, Command, CompressArgs, DecompressArgs, EncodingScheme, TokenizationScheme,
+    run, Args, Charset, Command, CompressArgs, DecompressArgs, EncodingScheme, Endianness,
+    FloatWidth, IntegerWidth, Predictor, TokenizationScheme, Transform,
 };
 use std::fs;
 use std::sync::Once;
 use tempfile;
 
 pub fn roundtrip(tokenizer: &str, encoding: &str, data: &[u8]) {
+    roundtrip_with_transform(tokenizer, encoding, Transform::None, data);
+}
+
+pub fn roundtrip_with_transform(
+    tokenizer: &str,
+    encoding: &str,
+    transform: Transform,
+    data: &[u8],
+) {
+    roundtrip_with_options(tokenizer, encoding, transform, Charset::Utf8, data);
+}
+
+pub fn roundtrip_with_options(
+    tokenizer: &str,
+    encoding: &str,
+    transform: Transform,
+    charset: Charset,
+    data: &[u8],
+) {
     init_logs_for_test();
     let work_dir = tempfile::tempdir().unwrap();
     let input_file = work_dir.path().join("input.txt");
@@ -32,6 +52,8 @@ pub fn roundtrip(tokenizer: &str, encoding: &str, data: &[u8]) {
         command: Command::Compress(CompressArgs {
             tokenization_scheme: to_tokenization_scheme(tokenizer),
             encoding_scheme: to_encoding_scheme(encoding),
+            transform,
+            charset,
         }),
         input_file: &input_file.as_path(),
         output_file: &compressed_file.as_path(),
@@ -73,7 +95,49 @@ fn to_encoding_scheme(encoding: &str) -> EncodingScheme {
 fn to_tokenization_scheme(tokenization: &str) -> TokenizationScheme {
     match tokenization {
         "byte" => TokenizationScheme::Byte,
+        "code_point" => TokenizationScheme::CodePoint,
+        "csv_delta" => TokenizationScheme::Csv { delta: true },
+        "fasta" => TokenizationScheme::Fasta { k: 4 },
         "grapheme" => TokenizationScheme::Grapheme,
+        "bits12" => TokenizationScheme::Bits { k: 12 },
+        "bigram" => TokenizationScheme::NGram { n: 2 },
+        "trigram" => TokenizationScheme::NGram { n: 3 },
+        "byte_pair" => TokenizationScheme::BytePair {
+            vocabulary_size: 512,
+        },
+        "segment" => TokenizationScheme::Segment { rounds: 3 },
+        "log_template" => TokenizationScheme::LogTemplate,
+        "lz77" => TokenizationScheme::Lz77,
+        "float_f64" => TokenizationScheme::Float {
+            width: FloatWidth::F64,
+            endianness: Endianness::Little,
+        },
+        "json" => TokenizationScheme::Json,
+        "line" => TokenizationScheme::Line { min_count: 2 },
+        "lzw" => TokenizationScheme::Lzw { max_bits: 16 },
+        "lzw9" => TokenizationScheme::Lzw { max_bits: 9 },
+        "pnm_med" => TokenizationScheme::Pnm {
+            predictor: Predictor::Med,
+        },
+        "rules" => TokenizationScheme::Rules {
+            patterns: vec![
+                r"[A-Za-z_]\w*".to_owned(),
+                "[0-9]+".to_owned(),
+                r"\s+".to_owned(),
+            ],
+        },
+        "run_length" => TokenizationScheme::RunLength,
+        "strided_auto" => TokenizationScheme::Strided { stride: 0 },
+        "u16_delta_zigzag" => TokenizationScheme::Integer {
+            width: IntegerWidth::U16,
+            endianness: Endianness::Little,
+            delta: true,
+            zigzag: true,
+        },
+        "wav_lpc_rice" => TokenizationScheme::Wav {
+            lpc_order: 8,
+            rice: true,
+        },
         "word" => TokenizationScheme::Word,
         _ => panic!("Unsupported tokenization scheme {}", tokenization),
     }
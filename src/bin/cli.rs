@@ -18,7 +18,10 @@ use std::path::PathBuf;
 
 use anyhow::Result;
 use clap::{Parser, Subcommand};
-use cshannon::{Command, CompressArgs, DecompressArgs, EncodingScheme, TokenizationScheme};
+use cshannon::{
+    Charset, Command, CompressArgs, DecompressArgs, EncodingScheme, Endianness, FloatWidth,
+    IntegerWidth, Predictor, TokenizationScheme, Transform, UnixCompressArgs,
+};
 use env_logger::Env;
 
 #[derive(Parser)]
@@ -44,8 +47,86 @@ enum Commands {
         #[arg(short, long)]
         tokenization: TokenizationSchemeArg,
         /// Encoding to use.
-        #[arg(short, long)]
-        encoding: EncodingSchemeArg,
+        #[arg(short, long, required_unless_present = "encoding_id")]
+        encoding: Option<EncodingSchemeArg>,
+        /// ID of an encoding registered with `cshannon::register_encoding`, to
+        /// use instead of --encoding.
+        #[arg(long, conflicts_with = "encoding")]
+        encoding_id: Option<String>,
+        /// Number of bytes in each token for the n-gram tokenizer.
+        #[arg(short, long, default_value_t = 2)]
+        n_gram_length: u8,
+        /// Maximum number of distinct tokens for the byte-pair tokenizer.
+        #[arg(short, long, default_value_t = 512)]
+        vocabulary_size: u32,
+        /// Number of refinement rounds for the segment tokenizer.
+        #[arg(short, long, default_value_t = 3)]
+        rounds: u8,
+        /// Maximum code width (in bits) for the LZW tokenizer.
+        #[arg(short = 'b', long, default_value_t = 16)]
+        max_bits: u8,
+        /// Number of bits in each token for the bits tokenizer.
+        #[arg(short = 'k', long, default_value_t = 4)]
+        symbol_bits: u8,
+        /// Number of nucleotides in each token for the FASTA tokenizer.
+        #[arg(long, default_value_t = 4)]
+        kmer_length: u8,
+        /// Minimum number of occurrences of a line for the line tokenizer to
+        /// keep it whole.
+        #[arg(short, long, default_value_t = 2)]
+        min_count: u32,
+        /// Regular expression rules for the rules tokenizer, in order of
+        /// priority. May be repeated.
+        #[arg(short, long = "pattern", default_values = [r"[A-Za-z_]\w*", "[0-9]+", r"\s+"])]
+        patterns: Vec<String>,
+        /// Type of each integer for the integer tokenizer.
+        #[arg(long, default_value = "u32")]
+        width: IntegerWidthArg,
+        /// Byte order of each number for the integer and float tokenizers.
+        #[arg(long, default_value = "little")]
+        endianness: EndiannessArg,
+        /// Type of each number for the float tokenizer.
+        #[arg(long, default_value = "f64")]
+        float_width: FloatWidthArg,
+        /// Code differences between consecutive integers, for the integer
+        /// and CSV tokenizers.
+        #[arg(long)]
+        delta: bool,
+        /// Zigzag-code (signed) integers.
+        #[arg(long)]
+        zigzag: bool,
+        /// Method to predict each sample for the PNM tokenizer.
+        #[arg(long, default_value = "med")]
+        predictor: PredictorArg,
+        /// Order of the linear predictor for the WAV tokenizer. With 0, fixed
+        /// polynomial predictors are used instead.
+        #[arg(long, default_value_t = 0)]
+        lpc_order: u8,
+        /// Code residuals with Rice codes, for the WAV tokenizer.
+        #[arg(long)]
+        rice: bool,
+        /// Number of interleaved channels for the strided tokenizer. With 0,
+        /// the stride is detected from the input.
+        #[arg(long, default_value_t = 0)]
+        stride: u16,
+        /// Transform to apply to the input ahead of tokenization.
+        #[arg(long, default_value = "none")]
+        transform: TransformArg,
+        /// Number of bytes in each block for the BWT transform.
+        #[arg(long, default_value_t = 900_000)]
+        block_size: u32,
+        /// Character set of the input. Only text tokenizers (see
+        /// `TokenizationScheme::is_text`) decode the input to utf-8 ahead of
+        /// the transform and tokenization; other tokenizers read the input as
+        /// is and ignore this option.
+        #[arg(long, default_value = "auto")]
+        charset: CharsetArg,
+    },
+    /// Compress a file into the .Z format of compress(1).
+    UnixCompress {
+        /// Maximum code width (in bits).
+        #[arg(short = 'b', long, default_value_t = 16)]
+        max_bits: u8,
     },
     /// Decompress a file.
     Decompress,
@@ -54,8 +135,74 @@ enum Commands {
 #[derive(clap::ValueEnum, Clone, Debug)]
 enum TokenizationSchemeArg {
     Byte,
+    CodePoint,
     Word,
     Grapheme,
+    NGram,
+    BytePair,
+    Segment,
+    Lz77,
+    Lzw,
+    RunLength,
+    Integer,
+    Bits,
+    Line,
+    Rules,
+    Csv,
+    Json,
+    LogTemplate,
+    Fasta,
+    Pnm,
+    Wav,
+    Float,
+    Strided,
+}
+
+#[derive(clap::ValueEnum, Clone, Debug)]
+enum PredictorArg {
+    Left,
+    Up,
+    Average,
+    Paeth,
+    Med,
+}
+
+#[derive(clap::ValueEnum, Clone, Debug)]
+enum IntegerWidthArg {
+    U8,
+    U16,
+    U32,
+    I32,
+    U64,
+}
+
+#[derive(clap::ValueEnum, Clone, Debug)]
+enum FloatWidthArg {
+    F32,
+    F64,
+}
+
+#[derive(clap::ValueEnum, Clone, Debug)]
+enum EndiannessArg {
+    Little,
+    Big,
+}
+
+#[derive(clap::ValueEnum, Clone, Debug)]
+enum TransformArg {
+    None,
+    Bwt,
+    Bocu,
+}
+
+#[derive(clap::ValueEnum, Clone, Debug)]
+enum CharsetArg {
+    Auto,
+    Utf8,
+    Utf16le,
+    Utf16be,
+    Latin1,
+    ShiftJis,
 }
 
 #[derive(clap::ValueEnum, Clone, Debug)]
@@ -72,10 +219,80 @@ fn main() -> Result<()> {
     let command = match &cli.command {
         Commands::Compress {
             encoding,
+            encoding_id,
             tokenization,
+            n_gram_length,
+            vocabulary_size,
+            rounds,
+            max_bits,
+            symbol_bits,
+            kmer_length,
+            min_count,
+            patterns,
+            width,
+            endianness,
+            float_width,
+            delta,
+            zigzag,
+            predictor,
+            lpc_order,
+            rice,
+            stride,
+            transform,
+            block_size,
+            charset,
         } => Command::Compress(CompressArgs {
-            encoding_scheme: to_encoding_scheme(&encoding),
-            tokenization_scheme: to_tokenization_scheme(tokenization),
+            encoding_scheme: to_encoding_scheme(encoding, encoding_id),
+            tokenization_scheme: match tokenization {
+                TokenizationSchemeArg::Byte => TokenizationScheme::Byte,
+                TokenizationSchemeArg::CodePoint => TokenizationScheme::CodePoint,
+                TokenizationSchemeArg::Grapheme => TokenizationScheme::Grapheme,
+                TokenizationSchemeArg::Word => TokenizationScheme::Word,
+                TokenizationSchemeArg::NGram => TokenizationScheme::NGram { n: *n_gram_length },
+                TokenizationSchemeArg::BytePair => TokenizationScheme::BytePair {
+                    vocabulary_size: *vocabulary_size,
+                },
+                TokenizationSchemeArg::Segment => TokenizationScheme::Segment { rounds: *rounds },
+                TokenizationSchemeArg::Lz77 => TokenizationScheme::Lz77,
+                TokenizationSchemeArg::Lzw => TokenizationScheme::Lzw {
+                    max_bits: *max_bits,
+                },
+                TokenizationSchemeArg::RunLength => TokenizationScheme::RunLength,
+                TokenizationSchemeArg::Integer => TokenizationScheme::Integer {
+                    width: to_integer_width(width),
+                    endianness: to_endianness(endianness),
+                    delta: *delta,
+                    zigzag: *zigzag,
+                },
+                TokenizationSchemeArg::Bits => TokenizationScheme::Bits { k: *symbol_bits },
+                TokenizationSchemeArg::Line => TokenizationScheme::Line {
+                    min_count: *min_count,
+                },
+                TokenizationSchemeArg::Rules => TokenizationScheme::Rules {
+                    patterns: patterns.clone(),
+                },
+                TokenizationSchemeArg::Csv => TokenizationScheme::Csv { delta: *delta },
+                TokenizationSchemeArg::Json => TokenizationScheme::Json,
+                TokenizationSchemeArg::LogTemplate => TokenizationScheme::LogTemplate,
+                TokenizationSchemeArg::Fasta => TokenizationScheme::Fasta { k: *kmer_length },
+                TokenizationSchemeArg::Pnm => TokenizationScheme::Pnm {
+                    predictor: to_predictor(predictor),
+                },
+                TokenizationSchemeArg::Wav => TokenizationScheme::Wav {
+                    lpc_order: *lpc_order,
+                    rice: *rice,
+                },
+                TokenizationSchemeArg::Float => TokenizationScheme::Float {
+                    width: to_float_width(float_width),
+                    endianness: to_endianness(endianness),
+                },
+                TokenizationSchemeArg::Strided => TokenizationScheme::Strided { stride: *stride },
+            },
+            transform: to_transform(transform, *block_size),
+            charset: to_charset(charset),
+        }),
+        Commands::UnixCompress { max_bits } => Command::UnixCompress(UnixCompressArgs {
+            max_bits: *max_bits,
         }),
         Commands::Decompress => Command::Decompress(DecompressArgs {}),
     };
@@ -91,19 +308,70 @@ fn main() -> Result<()> {
 }
 
 // Migration kludge
-fn to_encoding_scheme(encoding: &EncodingSchemeArg) -> EncodingScheme {
-    match encoding {
-        EncodingSchemeArg::BalancedTree => EncodingScheme::BalancedTree,
-        EncodingSchemeArg::Fano => EncodingScheme::Fano,
-        EncodingSchemeArg::Shannon => EncodingScheme::Shannon,
-        EncodingSchemeArg::Huffman => EncodingScheme::Huffman,
+fn to_encoding_scheme(
+    encoding: &Option<EncodingSchemeArg>,
+    encoding_id: &Option<String>,
+) -> EncodingScheme {
+    match (encoding, encoding_id) {
+        (_, Some(id)) => EncodingScheme::Custom { id: id.clone() },
+        (Some(EncodingSchemeArg::BalancedTree), None) => EncodingScheme::BalancedTree,
+        (Some(EncodingSchemeArg::Fano), None) => EncodingScheme::Fano,
+        (Some(EncodingSchemeArg::Shannon), None) => EncodingScheme::Shannon,
+        (Some(EncodingSchemeArg::Huffman), None) => EncodingScheme::Huffman,
+        // Safe because --encoding is required unless --encoding-id is given.
+        (None, None) => unreachable!(),
+    }
+}
+
+fn to_integer_width(width: &IntegerWidthArg) -> IntegerWidth {
+    match width {
+        IntegerWidthArg::U8 => IntegerWidth::U8,
+        IntegerWidthArg::U16 => IntegerWidth::U16,
+        IntegerWidthArg::U32 => IntegerWidth::U32,
+        IntegerWidthArg::I32 => IntegerWidth::I32,
+        IntegerWidthArg::U64 => IntegerWidth::U64,
+    }
+}
+
+fn to_float_width(width: &FloatWidthArg) -> FloatWidth {
+    match width {
+        FloatWidthArg::F32 => FloatWidth::F32,
+        FloatWidthArg::F64 => FloatWidth::F64,
+    }
+}
+
+fn to_endianness(endianness: &EndiannessArg) -> Endianness {
+    match endianness {
+        EndiannessArg::Little => Endianness::Little,
+        EndiannessArg::Big => Endianness::Big,
+    }
+}
+
+fn to_predictor(predictor: &PredictorArg) -> Predictor {
+    match predictor {
+        PredictorArg::Left => Predictor::Left,
+        PredictorArg::Up => Predictor::Up,
+        PredictorArg::Average => Predictor::Average,
+        PredictorArg::Paeth => Predictor::Paeth,
+        PredictorArg::Med => Predictor::Med,
+    }
+}
+
+fn to_charset(charset: &CharsetArg) -> Charset {
+    match charset {
+        CharsetArg::Auto => Charset::Auto,
+        CharsetArg::Utf8 => Charset::Utf8,
+        CharsetArg::Utf16le => Charset::Utf16Le,
+        CharsetArg::Utf16be => Charset::Utf16Be,
+        CharsetArg::Latin1 => Charset::Latin1,
+        CharsetArg::ShiftJis => Charset::ShiftJis,
     }
 }
 
-fn to_tokenization_scheme(tokenization: &TokenizationSchemeArg) -> TokenizationScheme {
-    match tokenization {
-        TokenizationSchemeArg::Byte => TokenizationScheme::Byte,
-        TokenizationSchemeArg::Grapheme => TokenizationScheme::Grapheme,
-        TokenizationSchemeArg::Word => TokenizationScheme::Word,
+fn to_transform(transform: &TransformArg, block_size: u32) -> Transform {
+    match transform {
+        TransformArg::None => Transform::None,
+        TransformArg::Bwt => Transform::Bwt { block_size },
+        TransformArg::Bocu => Transform::Bocu,
     }
 }
@@ -31,6 +31,8 @@ fn main() {
         command: cshannon::Command::Compress(cshannon::CompressArgs {
             encoding_scheme: cshannon::EncodingScheme::BalancedTree,
             tokenization_scheme: cshannon::TokenizationScheme::Grapheme,
+            transform: cshannon::Transform::None,
+            charset: cshannon::Charset::Utf8,
         }),
         input_file: &Path::new("/tmp/non-existent-input-file"),
         output_file: &Path::new("/tmp/non-existent-output-file"),
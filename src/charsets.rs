@@ -0,0 +1,239 @@
+// Copyright 2020 Prathmesh Prabhu
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! charsets module implements decoding of source text in character sets other
+//! than utf-8.
+//!
+//! The text tokenization schemes (e.g. graphemes and words) assume utf-8
+//! text. For these schemes, text in another [`Charset`] is decoded to utf-8
+//! ahead of the transform and tokenization, and the charset is included in the
+//! compressed output so that decompression encodes the text back. Other
+//! schemes compress the source text as is.
+//!
+//! Decoding is only used when encoding the decoded text gives back exactly the
+//! source text (e.g. not for UTF-16 text with unpaired surrogates, or invalid
+//! Shift-JIS text). Other text is compressed as is, and recorded as utf-8.
+
+use anyhow::{anyhow, Result};
+use encoding_rs::SHIFT_JIS;
+use std::convert::TryFrom;
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// Character sets of the source text supported by this library.
+#[derive(Copy, Clone, Debug, Default, Eq, Hash, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Charset {
+    /// Pick the charset from the source text with [`Charset::detect()`].
+    ///
+    /// The detected charset is included in the compressed output.
+    Auto,
+    /// UTF-8, i.e. no decoding.
+    #[default]
+    Utf8,
+    /// Little endian UTF-16, with or without a byte order mark.
+    Utf16Le,
+    /// Big endian UTF-16, with or without a byte order mark.
+    Utf16Be,
+    /// ISO 8859-1, where each byte is the code point of the same value.
+    Latin1,
+    /// [Shift-JIS], as specified in the WHATWG Encoding Standard.
+    ///
+    /// [Shift-JIS]: https://encoding.spec.whatwg.org/#shift_jis
+    ShiftJis,
+}
+
+impl Charset {
+    /// Guess the charset of `data`.
+    ///
+    /// Text that starts with a UTF-16 byte order mark is UTF-16. Otherwise,
+    /// valid utf-8 text is utf-8, text that decodes exactly as Shift-JIS is
+    /// Shift-JIS, and any other text is Latin-1.
+    ///
+    /// Data that is not plausibly text, i.e. that contains control characters
+    /// other than whitespace (e.g. NUL bytes), is utf-8 so that it is
+    /// compressed as is.
+    pub fn detect(data: &[u8]) -> Self {
+        if data.starts_with(&[0xff, 0xfe]) && Charset::Utf16Le.decode(data).is_some() {
+            return Charset::Utf16Le;
+        }
+        if data.starts_with(&[0xfe, 0xff]) && Charset::Utf16Be.decode(data).is_some() {
+            return Charset::Utf16Be;
+        }
+        let binary = data.iter().any(|b| *b < 0x20 && !b"\t\n\x0c\r".contains(b));
+        if binary || std::str::from_utf8(data).is_ok() {
+            return Charset::Utf8;
+        }
+        if Charset::ShiftJis.decode(data).is_some() {
+            return Charset::ShiftJis;
+        }
+        Charset::Latin1
+    }
+
+    /// Decode `data` to utf-8 text.
+    ///
+    /// Returns `None` unless [`Self::encode()`] gives back exactly `data`.
+    /// Returns `data` as is for [`Charset::Utf8`] and [`Charset::Auto`].
+    pub fn decode(&self, data: &[u8]) -> Option<Vec<u8>> {
+        let text = match self {
+            Charset::Auto | Charset::Utf8 => return Some(data.to_vec()),
+            Charset::Utf16Le | Charset::Utf16Be => {
+                if !data.len().is_multiple_of(2) {
+                    return None;
+                }
+                let units = data.chunks_exact(2).map(|u| match self {
+                    Charset::Utf16Le => u16::from_le_bytes([u[0], u[1]]),
+                    _ => u16::from_be_bytes([u[0], u[1]]),
+                });
+                char::decode_utf16(units)
+                    .collect::<Result<String, _>>()
+                    .ok()?
+            }
+            Charset::Latin1 => data.iter().map(|b| *b as char).collect(),
+            Charset::ShiftJis => SHIFT_JIS
+                .decode_without_bom_handling_and_without_replacement(data)?
+                .into_owned(),
+        };
+        match self.encode(text.as_bytes()) {
+            Ok(encoded) if encoded == data => Some(text.into_bytes()),
+            _ => None,
+        }
+    }
+
+    /// Encode utf-8 `text` decoded with [`Self::decode()`].
+    pub fn encode(&self, text: &[u8]) -> Result<Vec<u8>> {
+        if let Charset::Auto | Charset::Utf8 = self {
+            return Ok(text.to_vec());
+        }
+        let text = std::str::from_utf8(text)?;
+        match self {
+            Charset::Auto | Charset::Utf8 => unreachable!(),
+            Charset::Utf16Le => Ok(text.encode_utf16().flat_map(u16::to_le_bytes).collect()),
+            Charset::Utf16Be => Ok(text.encode_utf16().flat_map(u16::to_be_bytes).collect()),
+            Charset::Latin1 => text
+                .chars()
+                .map(|c| u8::try_from(c).map_err(|_| anyhow!("{:?} is not in Latin-1", c)))
+                .collect(),
+            Charset::ShiftJis => {
+                let (encoded, _, had_errors) = SHIFT_JIS.encode(text);
+                if had_errors {
+                    return Err(anyhow!("Text is not in Shift-JIS"));
+                }
+                Ok(encoded.into_owned())
+            }
+        }
+    }
+}
+
+pub fn pack_charset<W: std::io::Write>(charset: Charset, mut w: W) -> Result<()> {
+    let marker = match charset {
+        Charset::Auto => return Err(anyhow!("Charset must be detected before packing")),
+        Charset::Utf8 => 0u8,
+        Charset::Utf16Le => 1u8,
+        Charset::Utf16Be => 2u8,
+        Charset::Latin1 => 3u8,
+        Charset::ShiftJis => 4u8,
+    };
+    w.write_all(&[marker])?;
+    Ok(())
+}
+
+pub fn unpack_charset<R: std::io::Read>(mut r: R) -> Result<Charset> {
+    let mut buf = [0u8];
+    r.read_exact(&mut buf)?;
+    match buf[0] {
+        0u8 => Ok(Charset::Utf8),
+        1u8 => Ok(Charset::Utf16Le),
+        2u8 => Ok(Charset::Utf16Be),
+        3u8 => Ok(Charset::Latin1),
+        4u8 => Ok(Charset::ShiftJis),
+        m => Err(anyhow!("Unknown charset marker {}", m)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TEXT: &str = "\
+Ah! well a-day! what evil looks
+Had I from old and young!
+Instead of the cross, the Albatross
+About my neck was hung.
+";
+
+    fn utf16le(text: &str) -> Vec<u8> {
+        "\u{feff}"
+            .encode_utf16()
+            .chain(text.encode_utf16())
+            .flat_map(u16::to_le_bytes)
+            .collect()
+    }
+
+    #[test]
+    fn roundtrip() {
+        let text = format!("{}ひらがな 漢字 ｶﾀｶﾅ\n", TEXT);
+        for charset in [
+            Charset::Utf8,
+            Charset::Utf16Le,
+            Charset::Utf16Be,
+            Charset::ShiftJis,
+        ] {
+            let data = charset.encode(text.as_bytes()).unwrap();
+            assert_eq!(charset.decode(&data).unwrap(), text.as_bytes());
+        }
+        let data = Charset::Latin1.encode("café".as_bytes()).unwrap();
+        assert_eq!(data, b"caf\xe9");
+        assert_eq!(Charset::Latin1.decode(&data).unwrap(), "café".as_bytes());
+    }
+
+    #[test]
+    fn inexact_decoding() {
+        for (charset, data) in [
+            (Charset::Utf16Le, &b"\xff\xfea"[..]),
+            (Charset::Utf16Le, b"\x00\xd8a\x00"),
+            (Charset::ShiftJis, b"\x82"),
+            (Charset::ShiftJis, b"\xa0"),
+        ] {
+            assert!(charset.decode(data).is_none(), "{:?} {:?}", charset, data);
+        }
+        assert!(Charset::Latin1.encode("漢".as_bytes()).is_err());
+    }
+
+    #[test]
+    fn detect() {
+        let sjis = Charset::ShiftJis.encode("ひらがな".as_bytes()).unwrap();
+        for (data, charset) in [
+            (TEXT.as_bytes().to_vec(), Charset::Utf8),
+            (utf16le(TEXT), Charset::Utf16Le),
+            (sjis, Charset::ShiftJis),
+            (b"caf\xe9 \xff".to_vec(), Charset::Latin1),
+            (b"caf\xe9\x00\xff".to_vec(), Charset::Utf8),
+            (b"\x1b\x82\xa0".to_vec(), Charset::Utf8),
+        ] {
+            assert_eq!(Charset::detect(&data), charset);
+        }
+    }
+
+    #[test]
+    fn pack_unpack() {
+        for charset in [Charset::Utf8, Charset::Latin1, Charset::ShiftJis] {
+            let mut buf = Vec::new();
+            pack_charset(charset, &mut buf).unwrap();
+            assert_eq!(unpack_charset(&buf[..]).unwrap(), charset);
+        }
+        assert!(pack_charset(Charset::Auto, Vec::new()).is_err());
+    }
+}
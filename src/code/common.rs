@@ -12,7 +12,7 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use anyhow::Result;
+pub use crate::util::{pack_u64, unpack_u64};
 
 pub static BIT_HOLE_MASKS: [u8; 8] = [
     0b1000_0000,
@@ -24,13 +24,3 @@ pub static BIT_HOLE_MASKS: [u8; 8] = [
     0b0000_0010,
     0b0000_0001,
 ];
-
-pub fn pack_u64(s: u64) -> Vec<u8> {
-    s.to_be_bytes().to_vec()
-}
-
-pub fn unpack_u64<R: std::io::Read>(mut r: R) -> Result<u64> {
-    let mut buf: [u8; 8] = [0; 8];
-    r.read_exact(&mut buf)?;
-    Ok(u64::from_be_bytes(buf))
-}
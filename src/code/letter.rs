@@ -15,8 +15,8 @@
 use super::common::{pack_u64, unpack_u64, BIT_HOLE_MASKS};
 use anyhow::{anyhow, Result};
 use log::trace;
-use std::convert::TryInto;
 use std::fmt;
+use std::io::Read;
 
 /// An indivisible code point with the [prefix property].
 ///
@@ -93,6 +93,11 @@ impl Letter {
         Self::new(bytes, 8 * bytes.len() as u64)
     }
 
+    /// Number of bits in this Letter.
+    pub fn bit_count(&self) -> u64 {
+        self.bit_count
+    }
+
     /// Extend this Letter with a `0` bit.
     pub fn push0(&mut self) {
         self.push(false);
@@ -154,7 +159,6 @@ impl Default for Letter {
 pub trait Peephole {
     fn validate(&self) -> Result<()>;
     fn data<'a>(&'a self) -> &'a Vec<u8>;
-    fn bit_count(&self) -> u64;
     fn pack<W: std::io::Write>(self, w: W) -> Result<()>;
     fn unpack<R: std::io::Read>(r: R) -> Result<Self>
     where
@@ -174,10 +178,6 @@ impl Peephole for Letter {
         &self.data
     }
 
-    fn bit_count(&self) -> u64 {
-        self.bit_count
-    }
-
     fn pack<W: std::io::Write>(self, mut w: W) -> Result<()> {
         trace!("pack: |{}|", &self);
         w.write_all(&pack_u64(self.bit_count))?;
@@ -187,9 +187,18 @@ impl Peephole for Letter {
 
     fn unpack<R: std::io::Read>(mut r: R) -> Result<Self> {
         let bit_count = unpack_u64(&mut r)?;
-        let byte_count = (bit_count + 7) / 8;
-        let mut data = vec![0u8; byte_count.try_into().unwrap()];
-        r.read_exact(&mut data)?;
+        let byte_count = bit_count.div_ceil(8);
+        // Read through `take()` so that a corrupted `bit_count` returns an
+        // error instead of exhausting memory.
+        let mut data = Vec::new();
+        r.take(byte_count).read_to_end(&mut data)?;
+        if data.len() as u64 != byte_count {
+            return Err(anyhow!(
+                "Expected {} bytes of letter data, found {}",
+                byte_count,
+                data.len()
+            ));
+        }
         let l = Self { bit_count, data };
         trace!("unpack: |{}|", &l);
         Ok(l)
@@ -267,4 +276,19 @@ mod tests {
         }
         assert_eq!(l, Letter::new(&[0xFF, 0b1110_0000], 11));
     }
+
+    #[test]
+    fn pack_unpack() {
+        let l = Letter::new(&[0xFF, 0b1110_0000], 11);
+        let mut buf = Vec::new();
+        l.clone().pack(&mut buf).unwrap();
+        assert_eq!(Letter::unpack(&buf[..]).unwrap(), l);
+    }
+
+    #[test]
+    fn unpack_corrupted_bit_count() {
+        let mut buf = pack_u64(u64::MAX).to_vec();
+        buf.push(0xFF);
+        assert!(Letter::unpack(&buf[..]).is_err());
+    }
 }
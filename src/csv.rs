@@ -0,0 +1,564 @@
+// Copyright 2020 Prathmesh Prabhu
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! csv module implements a columnar front end to the entropy coders for
+//! [CSV] text.
+//!
+//! The source text is parsed into records of fields. The values in each
+//! column are compressed as a separate token stream with its own
+//! [`Encoding`](crate::encoding::Encoding), because the values in a column
+//! are far more alike than the values in the file as a whole. The streams
+//! are:
+//!
+//! - shapes: one token per field recording whether the field is quoted, and
+//!   one token per record for its line terminator (`\n`, `\r\n` or none at the
+//!   end of the text).
+//! - header: the values of the first record, usually the column names.
+//! - one stream per column with the values of the remaining records. When all
+//!   values in a column are integers, they are coded as numbers (optionally
+//!   as the difference from the previous value in the column) instead of text.
+//!
+//! Each column is coded in whichever of two forms is smaller. Text is coded
+//! as whole values, or byte by byte (with a separate stream of value lengths).
+//! Numbers are coded as whole values, or as buckets followed by raw extra bits
+//! as in [`integers`](crate::tokens::integers). Whole values suit columns with
+//! few distinct values, while the other forms keep the dictionary small when
+//! most values are distinct (e.g. ids, timestamps or free text).
+//!
+//! Quoted values are stored unescaped, and are quoted again on decompression,
+//! so the output is byte-for-byte identical to the source text. Text that is
+//! not well-formed CSV (e.g. with an unterminated quoted field) is compressed
+//! as a single stream of bytes instead.
+//!
+//! [CSV]: https://www.rfc-editor.org/rfc/rfc4180
+
+use crate::encoding::EncodingScheme;
+use crate::stream;
+use crate::tokens::bytes::Byte;
+use crate::tokens::integers::{pack_buckets, unpack_buckets, Integer};
+use crate::tokens::rules::Lexeme;
+use crate::util::{pack_u64, unpack_u64};
+use anyhow::{anyhow, Result};
+use std::convert::TryFrom;
+
+// Markers for the mode of the compressed text.
+const RAW: u8 = 0;
+const COLUMNS: u8 = 1;
+
+// Markers for the kind of a column.
+const TEXT_COLUMN: u8 = 0;
+const NUMBER_COLUMN: u8 = 1;
+const BYTES_COLUMN: u8 = 2;
+const BUCKETS_COLUMN: u8 = 3;
+
+// Tokens in the shapes stream.
+const PLAIN_FIELD: u8 = 0;
+const QUOTED_FIELD: u8 = 1;
+const LF: u8 = 2;
+const CRLF: u8 = 3;
+const END: u8 = 4;
+
+/// A single field of a CSV record.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Field {
+    /// Whether the field is enclosed in double quotes.
+    pub quoted: bool,
+    /// The (unescaped) value of the field.
+    pub value: Vec<u8>,
+}
+
+/// A single record (line) of CSV text.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Record {
+    pub fields: Vec<Field>,
+    // The line terminator, as a token of the shapes stream.
+    terminator: u8,
+}
+
+/// Parse `data` into [`Record`]s.
+///
+/// Returns `None` if `data` is not well-formed CSV, i.e. a quoted field is not
+/// terminated, or is followed by anything other than a separator or the end
+/// of a line.
+pub fn parse(data: &[u8]) -> Option<Vec<Record>> {
+    let mut records = Vec::new();
+    let mut i = 0;
+    while i < data.len() {
+        let mut fields = Vec::new();
+        let terminator = loop {
+            let (field, next) = parse_field(data, i)?;
+            fields.push(field);
+            i = next;
+            match (data.get(i), data.get(i + 1)) {
+                (Some(b','), _) => i += 1,
+                (Some(b'\n'), _) => {
+                    i += 1;
+                    break LF;
+                }
+                (Some(b'\r'), Some(b'\n')) => {
+                    i += 2;
+                    break CRLF;
+                }
+                (None, _) => break END,
+                _ => return None,
+            }
+        };
+        records.push(Record { fields, terminator });
+    }
+    Some(records)
+}
+
+// Parse the field starting at `data[i]`. Returns the field and the position
+// just past it.
+fn parse_field(data: &[u8], mut i: usize) -> Option<(Field, usize)> {
+    if data.get(i) != Some(&b'"') {
+        let start = i;
+        while i < data.len()
+            && data[i] != b','
+            && data[i] != b'\n'
+            && !(data[i] == b'\r' && data.get(i + 1) == Some(&b'\n'))
+        {
+            i += 1;
+        }
+        let field = Field {
+            quoted: false,
+            value: data[start..i].to_vec(),
+        };
+        return Some((field, i));
+    }
+
+    let mut value = Vec::new();
+    i += 1;
+    loop {
+        match (data.get(i), data.get(i + 1)) {
+            (Some(b'"'), Some(b'"')) => {
+                value.push(b'"');
+                i += 2;
+            }
+            (Some(b'"'), _) => {
+                let field = Field {
+                    quoted: true,
+                    value,
+                };
+                return Some((field, i + 1));
+            }
+            (Some(b), _) => {
+                value.push(*b);
+                i += 1;
+            }
+            (None, _) => return None,
+        }
+    }
+}
+
+fn write_field(field: &Field, out: &mut Vec<u8>) {
+    if !field.quoted {
+        out.extend_from_slice(&field.value);
+        return;
+    }
+    out.push(b'"');
+    for b in &field.value {
+        if *b == b'"' {
+            out.push(b'"');
+        }
+        out.push(*b);
+    }
+    out.push(b'"');
+}
+
+// The value of a field, if it is an integer written without redundant signs
+// or leading zeros (so that it can be written back exactly).
+fn as_number(value: &[u8]) -> Option<i64> {
+    let s = std::str::from_utf8(value).ok()?;
+    s.parse::<i64>().ok().filter(|n| n.to_string() == s)
+}
+
+fn zigzag(n: i64) -> u64 {
+    ((n << 1) ^ (n >> 63)) as u64
+}
+
+fn unzigzag(n: u64) -> i64 {
+    (n >> 1) as i64 ^ -((n & 1) as i64)
+}
+
+// Compress the values of a column, in the smallest of the forms that apply to
+// it. The output starts with the marker of the chosen form.
+fn pack_column(
+    column: Vec<Vec<u8>>,
+    encoding_scheme: &EncodingScheme,
+    delta: bool,
+) -> Result<Vec<u8>> {
+    let numbers: Option<Vec<i64>> = column.iter().map(|v| as_number(v)).collect();
+    let forms = match numbers {
+        Some(numbers) => {
+            let mut previous = 0i64;
+            let mut values = Vec::with_capacity(numbers.len());
+            for n in numbers {
+                let value = if delta { n.wrapping_sub(previous) } else { n };
+                previous = n;
+                values.push(zigzag(value));
+            }
+            let tokens: Vec<Integer> = values
+                .iter()
+                .map(|v| Integer::Value {
+                    value: *v,
+                    width: 8,
+                })
+                .collect();
+            let mut whole = vec![NUMBER_COLUMN];
+            stream::pack(&tokens, encoding_scheme, &mut whole)?;
+            let mut buckets = vec![BUCKETS_COLUMN];
+            pack_buckets(&values, encoding_scheme, &mut buckets)?;
+            vec![whole, buckets]
+        }
+        None => {
+            let lengths: Vec<Integer> = column
+                .iter()
+                .map(|v| Integer::Value {
+                    value: v.len() as u64,
+                    width: 8,
+                })
+                .collect();
+            let bytes: Vec<Byte> = column.iter().flatten().map(|b| Byte::from(*b)).collect();
+            let mut by_byte = vec![BYTES_COLUMN];
+            stream::pack(&lengths, encoding_scheme, &mut by_byte)?;
+            stream::pack(&bytes, encoding_scheme, &mut by_byte)?;
+            let tokens: Vec<Lexeme> = column.into_iter().map(Lexeme::from).collect();
+            let mut whole = vec![TEXT_COLUMN];
+            stream::pack(&tokens, encoding_scheme, &mut whole)?;
+            vec![whole, by_byte]
+        }
+    };
+    Ok(forms.into_iter().min_by_key(|f| f.len()).unwrap())
+}
+
+// Decompress the values of a column compressed with [`pack_column()`].
+fn unpack_column<R: std::io::Read>(mut r: R, delta: bool) -> Result<Vec<Vec<u8>>> {
+    let mut kind = [0u8];
+    r.read_exact(&mut kind)?;
+    let numbers: Vec<u64> = match kind[0] {
+        TEXT_COLUMN => {
+            let tokens: Vec<Lexeme> = stream::unpack(&mut r)?;
+            return Ok(tokens.into_iter().map(Vec::from).collect());
+        }
+        BYTES_COLUMN => {
+            let lengths: Vec<Integer> = stream::unpack(&mut r)?;
+            let bytes: Vec<Byte> = stream::unpack(&mut r)?;
+            let mut bytes = bytes.into_iter().map(u8::from);
+            let mut values = Vec::with_capacity(lengths.len());
+            for l in lengths {
+                let length = match l {
+                    Integer::Value { value, .. } => usize::try_from(value)?,
+                    Integer::Tail(_) => return Err(anyhow!("Unexpected tail in CSV column")),
+                };
+                let value: Vec<u8> = bytes.by_ref().take(length).collect();
+                if value.len() != length {
+                    return Err(anyhow!("Ran out of bytes for CSV column"));
+                }
+                values.push(value);
+            }
+            return Ok(values);
+        }
+        NUMBER_COLUMN => {
+            let tokens: Vec<Integer> = stream::unpack(&mut r)?;
+            tokens
+                .into_iter()
+                .map(|t| match t {
+                    Integer::Value { value, .. } => Ok(value),
+                    Integer::Tail(_) => Err(anyhow!("Unexpected tail in CSV column")),
+                })
+                .collect::<Result<_>>()?
+        }
+        BUCKETS_COLUMN => unpack_buckets(&mut r, u64::MAX)?,
+        k => return Err(anyhow!("Unknown CSV column kind {}", k)),
+    };
+    let mut previous = 0i64;
+    let mut values = Vec::with_capacity(numbers.len());
+    for value in numbers {
+        let value = unzigzag(value);
+        let n = if delta {
+            previous.wrapping_add(value)
+        } else {
+            value
+        };
+        previous = n;
+        values.push(n.to_string().into_bytes());
+    }
+    Ok(values)
+}
+
+/// Compress `data` with a columnar CSV front end.
+///
+/// Each token stream is compressed with an encoding generated with
+/// `encoding_scheme`. With `delta`, integer columns are coded as the
+/// difference between consecutive values. The output can be decompressed with
+/// [`unpack()`].
+pub fn pack<W: std::io::Write>(
+    data: &[u8],
+    encoding_scheme: &EncodingScheme,
+    delta: bool,
+    mut w: W,
+) -> Result<()> {
+    let records = match parse(data) {
+        Some(records) => records,
+        None => {
+            w.write_all(&[RAW])?;
+            let bytes: Vec<Byte> = data.iter().map(|b| Byte::from(*b)).collect();
+            stream::pack(&bytes, encoding_scheme, &mut w)?;
+            w.flush()?;
+            return Ok(());
+        }
+    };
+
+    let mut shapes = Vec::new();
+    let mut columns: Vec<Vec<Vec<u8>>> = Vec::new();
+    for r in &records {
+        for f in &r.fields {
+            shapes.push(Byte::from(if f.quoted {
+                QUOTED_FIELD
+            } else {
+                PLAIN_FIELD
+            }));
+        }
+        shapes.push(Byte::from(r.terminator));
+    }
+    for r in records.iter().skip(1) {
+        for (i, f) in r.fields.iter().enumerate() {
+            if columns.len() <= i {
+                columns.push(Vec::new());
+            }
+            columns[i].push(f.value.clone());
+        }
+    }
+    let header: Vec<Lexeme> = match records.first() {
+        Some(r) => r.fields.iter().map(|f| f.value.clone().into()).collect(),
+        None => Vec::new(),
+    };
+
+    w.write_all(&[COLUMNS])?;
+    stream::pack(&shapes, encoding_scheme, &mut w)?;
+    stream::pack(&header, encoding_scheme, &mut w)?;
+    w.write_all(&pack_u64(columns.len() as u64))?;
+    for column in columns {
+        w.write_all(&pack_column(column, encoding_scheme, delta)?)?;
+    }
+    w.flush()?;
+    Ok(())
+}
+
+/// Decompress data compressed with [`pack()`].
+///
+/// `delta` must be the same as used with [`pack()`].
+pub fn unpack<R: std::io::Read, W: std::io::Write>(mut r: R, delta: bool, mut w: W) -> Result<()> {
+    let mut mode = [0u8];
+    r.read_exact(&mut mode)?;
+    match mode[0] {
+        RAW => {
+            let bytes: Vec<Byte> = stream::unpack(&mut r)?;
+            let data: Vec<u8> = bytes.into_iter().map(u8::from).collect();
+            w.write_all(&data)?;
+            w.flush()?;
+            return Ok(());
+        }
+        COLUMNS => (),
+        m => return Err(anyhow!("Unknown CSV mode {}", m)),
+    }
+
+    let shapes: Vec<Byte> = stream::unpack(&mut r)?;
+    let header: Vec<Lexeme> = stream::unpack(&mut r)?;
+    let column_count = usize::try_from(unpack_u64(&mut r)?)?;
+    let mut columns = Vec::new();
+    for _ in 0..column_count {
+        columns.push(unpack_column(&mut r, delta)?.into_iter());
+    }
+
+    let mut header = header.into_iter().map(Vec::from);
+    let mut out = Vec::new();
+    let mut first = true;
+    let mut column = 0;
+    for s in shapes {
+        let quoted = match u8::from(s) {
+            PLAIN_FIELD => false,
+            QUOTED_FIELD => true,
+            LF | CRLF | END => {
+                out.extend_from_slice(match u8::from(s) {
+                    LF => &b"\n"[..],
+                    CRLF => &b"\r\n"[..],
+                    _ => &b""[..],
+                });
+                first = false;
+                column = 0;
+                continue;
+            }
+            t => return Err(anyhow!("Unknown CSV shape {}", t)),
+        };
+        let value = if first {
+            header.next()
+        } else {
+            columns.get_mut(column).and_then(|c| c.next())
+        };
+        let value = value.ok_or_else(|| anyhow!("Ran out of values for column {}", column))?;
+        if column > 0 {
+            out.push(b',');
+        }
+        write_field(&Field { quoted, value }, &mut out);
+        column += 1;
+    }
+    w.write_all(&out)?;
+    w.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    const TEXT: &str = "\
+line,text,words
+1,\"Ah! well a-day! what evil looks\",7
+2,\"Had I from old and young!\",6
+3,\"Instead of the cross, the Albatross\",7
+4,About my neck was hung.,5
+";
+
+    fn roundtrip(text: &[u8], delta: bool) -> Vec<u8> {
+        let mut buf = Vec::new();
+        pack(text, &EncodingScheme::Huffman, delta, &mut buf).unwrap();
+        let mut got = Vec::new();
+        unpack(Cursor::new(buf), delta, &mut got).unwrap();
+        got
+    }
+
+    #[test]
+    fn roundtrip_columns() {
+        for delta in [false, true] {
+            assert_eq!(roundtrip(TEXT.as_bytes(), delta), TEXT.as_bytes());
+        }
+    }
+
+    #[test]
+    fn roundtrip_shapes() {
+        for text in [
+            &b""[..],
+            b"\n",
+            b"a,b",
+            b"a,\r\nb\r",
+            b"\"\"\"quoted\"\"\",\"\n\",-0,007,-12\n,,\n9223372036854775807\n-9223372036854775808\n",
+            b"ragged\na,b,c\nd\n",
+        ] {
+            assert_eq!(roundtrip(text, true), text);
+        }
+    }
+
+    #[test]
+    fn roundtrip_malformed() {
+        for text in [&b"\"unterminated"[..], b"\"a\"b,c\n"] {
+            assert!(parse(text).is_none());
+            assert_eq!(roundtrip(text, false), text);
+        }
+    }
+
+    #[test]
+    fn parse_fields() {
+        let records = parse(b"a,\"b,\"\"c\"\"\"\r\n").unwrap();
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].terminator, CRLF);
+        assert_eq!(
+            records[0].fields,
+            vec![
+                Field {
+                    quoted: false,
+                    value: b"a".to_vec()
+                },
+                Field {
+                    quoted: true,
+                    value: b"b,\"c\"".to_vec()
+                },
+            ]
+        );
+    }
+
+    // Rows of an order log, with mostly distinct ids, timestamps, prices and
+    // emails.
+    fn orders() -> Vec<u8> {
+        const CITIES: [&str; 4] = ["Berlin", "Paris", "London", "Madrid"];
+        const WORDS: [&str; 6] = ["order", "shipped", "late", "returned", "happy", "customer"];
+        // A linear congruential generator, so that the sample is the same on
+        // every run.
+        let mut seed = 1u64;
+        let mut random = |n: u64| {
+            seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1);
+            (seed >> 33) % n
+        };
+        let mut text = String::from("id,timestamp,city,price,quantity,email,comment\n");
+        let mut t = 0;
+        for i in 0..2000 {
+            t += 1 + random(120);
+            let words: Vec<&str> = (0..3 + random(6))
+                .map(|_| WORDS[random(6) as usize])
+                .collect();
+            text.push_str(&format!(
+                "{},2023-11-{:02}T{:02}:{:02}:{:02},{},{}.{:02},{},user{}@example.com,\"{}\"\n",
+                100000 + i,
+                1 + t / 86400,
+                t / 3600 % 24,
+                t / 60 % 60,
+                t % 60,
+                CITIES[random(4) as usize],
+                1 + random(500),
+                random(100),
+                1 + random(10),
+                random(5000),
+                words.join(" "),
+            ));
+        }
+        text.into_bytes()
+    }
+
+    #[test]
+    fn smaller_than_bytes() {
+        let text = orders();
+        let bytes: Vec<Byte> = text.iter().map(|b| Byte::from(*b)).collect();
+        let mut by_byte = Vec::new();
+        stream::pack(&bytes, &EncodingScheme::Huffman, &mut by_byte).unwrap();
+        for delta in [false, true] {
+            let mut buf = Vec::new();
+            pack(&text, &EncodingScheme::Huffman, delta, &mut buf).unwrap();
+            assert!(
+                buf.len() < by_byte.len(),
+                "{} bytes with delta {}, {} bytes by byte",
+                buf.len(),
+                delta,
+                by_byte.len()
+            );
+            let mut got = Vec::new();
+            unpack(Cursor::new(buf), delta, &mut got).unwrap();
+            assert_eq!(got, text);
+        }
+    }
+
+    #[test]
+    fn numbers() {
+        assert_eq!(as_number(b"-12"), Some(-12));
+        assert_eq!(as_number(b"0"), Some(0));
+        assert_eq!(as_number(b"007"), None);
+        assert_eq!(as_number(b"+1"), None);
+        assert_eq!(as_number(b"-0"), None);
+        assert_eq!(as_number(b""), None);
+        for n in [0, 1, -1, i64::MAX, i64::MIN] {
+            assert_eq!(unzigzag(zigzag(n)), n);
+        }
+    }
+}
@@ -18,15 +18,18 @@
 //! An [`Encoding`] can be generated from a [`Model`](crate::model::Model) by
 //! calling the `new()` function defined in one of the sub-modules:
 //! [balanced_tree], [shannon], [fano], or [huffman].
+//!
+//! Code construction algorithms provided outside this crate implement the
+//! [`EncoderFactory`] trait instead, and are looked up in the
+//! [registry](crate::registry) by ID.
 
 use crate::code::{Alphabet, Letter};
 use crate::model::Model;
-use crate::tokens::{Token, TokenPacker, Tokenizer};
+use crate::tokens::Token;
+use crate::util::{pack_u64, unpack_bytes, unpack_u64};
 use anyhow::{anyhow, Result};
 use log::{debug, log_enabled, Level};
 use std::collections::HashMap;
-use std::convert::TryFrom;
-use std::io::Cursor;
 
 #[cfg(feature = "serde")]
 use serde::{Deserialize, Serialize};
@@ -37,7 +40,7 @@ pub mod huffman;
 pub mod shannon;
 
 /// Encoding schems (i.e. the compression algorithms) supported by this library.
-#[derive(Copy, Clone, Debug, Eq, Hash, PartialEq)]
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum EncodingScheme {
     /// Create a new balanced tree encoding.
@@ -76,6 +79,14 @@ pub enum EncodingScheme {
     ///
     /// [Huffman encoding]: https://en.wikipedia.org/wiki/Huffman_coding
     Huffman,
+    /// Create an encoding with an [`EncoderFactory`] registered at runtime
+    /// with [`register_encoding()`](crate::register_encoding).
+    ///
+    /// The `id` is included in the compressed output.
+    Custom {
+        /// The ID the factory is registered under.
+        id: String,
+    },
 }
 
 pub fn new_encoder<T: Token>(
@@ -87,10 +98,75 @@ pub fn new_encoder<T: Token>(
         EncodingScheme::Fano => fano::new::<T>,
         EncodingScheme::Huffman => huffman::new::<T>,
         EncodingScheme::Shannon => shannon::new::<T>,
+        EncodingScheme::Custom { id } => {
+            return from_factory(&*crate::registry::encoder(id)?, model);
+        }
     };
     constructor(model)
 }
 
+/// Creates the [`Letter`]s of an [`Encoding`] from the statistics of a
+/// [`Model`].
+///
+/// Implement this trait to plug a code construction algorithm into this
+/// library, and register it with
+/// [`register_encoding()`](crate::register_encoding). The factory only sees
+/// token frequencies, so that it works with every tokenization scheme.
+pub trait EncoderFactory: Send + Sync {
+    /// Return one [`Letter`] for each of the `frequencies`, in the same order.
+    ///
+    /// `frequencies` are the number of occurrences of each distinct token in
+    /// the source text, highest first. The returned letters must form a
+    /// [prefix code], and no letter may consist of only `0` bits.
+    ///
+    /// [prefix code]: https://en.wikipedia.org/wiki/Prefix_code
+    fn letters(&self, frequencies: &[u64]) -> Result<Vec<Letter>>;
+}
+
+fn from_factory<T: Token>(factory: &dyn EncoderFactory, model: Model<T>) -> Result<Encoding<T>> {
+    let tokens = model.tokens_sorted();
+    let frequencies: Vec<u64> = tokens.iter().map(|t| model.frequency(t)).collect();
+    let letters = factory.letters(&frequencies)?;
+    if letters.len() != tokens.len() {
+        return Err(anyhow!(
+            "Encoder returned {} letters for {} tokens",
+            letters.len(),
+            tokens.len()
+        ));
+    }
+    Encoding::new(tokens.into_iter().zip(letters).collect())
+}
+
+pub fn pack_encoding_scheme<W: std::io::Write>(scheme: &EncodingScheme, mut w: W) -> Result<()> {
+    match scheme {
+        EncodingScheme::BalancedTree => w.write_all(&[1u8])?,
+        EncodingScheme::Fano => w.write_all(&[2u8])?,
+        EncodingScheme::Shannon => w.write_all(&[3u8])?,
+        EncodingScheme::Huffman => w.write_all(&[4u8])?,
+        EncodingScheme::Custom { id } => {
+            w.write_all(&[5u8])?;
+            w.write_all(&pack_u64(id.len() as u64))?;
+            w.write_all(id.as_bytes())?;
+        }
+    }
+    Ok(())
+}
+
+pub fn unpack_encoding_scheme<R: std::io::Read>(mut r: R) -> Result<EncodingScheme> {
+    let mut buf = [0u8];
+    r.read_exact(&mut buf)?;
+    match buf[0] {
+        1u8 => Ok(EncodingScheme::BalancedTree),
+        2u8 => Ok(EncodingScheme::Fano),
+        3u8 => Ok(EncodingScheme::Shannon),
+        4u8 => Ok(EncodingScheme::Huffman),
+        5u8 => Ok(EncodingScheme::Custom {
+            id: String::from_utf8(unpack_bytes(&mut r)?)?,
+        }),
+        m => Err(anyhow!("Unknown encoding scheme marker {}", m)),
+    }
+}
+
 /// Maps a [`Token`] to a [`Letter`].
 #[derive(Clone, Debug, Default, Eq, PartialEq)]
 pub struct Encoding<T: Token> {
@@ -131,9 +207,10 @@ impl<T: Token> Encoding<T> {
 
     pub fn pack<W: std::io::Write>(&self, mut w: W) -> Result<()> {
         let tokens = self.tokens();
-        let size = tokens.iter().fold(0, |sum, t| sum + t.bit_count()) / 8;
-        w.write_all(&pack_u64(size as u64))?;
-        T::Packer::pack(tokens.into_iter(), &mut w)?;
+        w.write_all(&pack_u64(tokens.len() as u64))?;
+        for t in tokens.iter() {
+            t.pack(&mut w)?;
+        }
 
         self.alphabet().clone().pack(w)?;
 
@@ -141,12 +218,11 @@ impl<T: Token> Encoding<T> {
     }
 
     pub fn unpack<R: std::io::Read>(mut r: R) -> Result<Self> {
-        let size = unpack_u64(&mut r)?;
-        let safe_size = usize::try_from(size)?;
-        let mut buf = vec![0u8; safe_size];
-        r.read_exact(&mut buf)?;
-        let tokens: Result<Vec<T>> = T::Tokenizer::tokenize(Cursor::new(buf)).unwrap().collect();
-        let tokens = tokens?;
+        let count = unpack_u64(&mut r)?;
+        let mut tokens = Vec::new();
+        for _ in 0..count {
+            tokens.push(T::unpack(&mut r)?);
+        }
 
         let alphabet = crate::code::Alphabet::unpack(r)?;
         let letters = alphabet.letters().into_iter().cloned();
@@ -194,22 +270,11 @@ fn log_encoder_ring<T: Token>(m: &HashMap<T, Letter>) {
     }
 }
 
-// TODO: dedup with code::common::pack_u64()
-fn pack_u64(s: u64) -> Vec<u8> {
-    s.to_be_bytes().to_vec()
-}
-
-// TODO: dedup with code::common::unpack_u64()
-fn unpack_u64<R: std::io::Read>(mut r: R) -> Result<u64> {
-    let mut buf: [u8; 8] = [0; 8];
-    r.read_exact(&mut buf)?;
-    Ok(u64::from_be_bytes(buf))
-}
-
 #[cfg(test)]
 mod roundtrip_with_len_tests {
 
     use super::*;
+    use crate::tokens::string_parts::StringPart;
     use crate::tokens::{bytes::Byte, graphemes::Grapheme};
     use std::io::{Cursor, Read};
     #[test]
@@ -304,4 +369,31 @@ mod roundtrip_with_len_tests {
         assert_eq!(r.read_to_end(&mut buf).unwrap(), 1);
         assert_eq!(buf, vec![0b1111_1111u8]);
     }
+
+    #[test]
+    fn raw_graphemes_are_not_merged() {
+        // Concatenated, these raw bytes form the valid utf-8 sequence for '€'.
+        let map = (vec![
+            (
+                Grapheme::from(StringPart::Raw(0xe2)),
+                Letter::from_bytes(&vec![0u8, 1u8]),
+            ),
+            (
+                Grapheme::from(StringPart::Raw(0x82)),
+                Letter::from_bytes(&vec![0u8, 0u8, 1u8]),
+            ),
+            (
+                Grapheme::from(StringPart::Raw(0xac)),
+                Letter::from_bytes(&vec![0u8, 0u8, 0u8, 1u8]),
+            ),
+        ])
+        .into_iter()
+        .collect();
+        let encoding: Encoding<Grapheme> = Encoding::new(map).unwrap();
+
+        let mut buf = Vec::<u8>::new();
+        assert!(encoding.pack(&mut buf).is_ok());
+        let got: Encoding<Grapheme> = Encoding::unpack(&mut Cursor::new(&mut buf)).unwrap();
+        assert_eq!(got.map(), encoding.map());
+    }
 }
@@ -0,0 +1,426 @@
+// Copyright 2020 Prathmesh Prabhu
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! fasta module implements a front end to the entropy coders for nucleotide
+//! sequences in the [FASTA] and [FASTQ] formats.
+//!
+//! Each line of the source text is classified as a header (`>` or `@`
+//! lines, and the `+` lines of FASTQ), a sequence line, or a FASTQ quality
+//! line. The sequence lines are then split further, so that the bulk of the
+//! text is a stream of 2-bit nucleotides:
+//!
+//! - case: the lengths of alternating runs of upper and lower case (soft
+//!   masked) bases, starting with upper case.
+//! - exceptions: runs of symbols other than `ACGT` (e.g. `N`), as the number
+//!   of nucleotides before the run, the symbol and the length of the run.
+//! - nucleotides: all other bases, as [k-mers] of `k` 2-bit nucleotides.
+//!
+//! Each of these, along with the kind of each line, the length of each
+//! sequence line, the text of the header lines and the quality lines, is
+//! compressed as a separate token stream with its own
+//! [`Encoding`](crate::encoding::Encoding). Any text is accepted, but text
+//! other than nucleotide sequences compresses poorly.
+//!
+//! [FASTA]: https://en.wikipedia.org/wiki/FASTA_format
+//! [FASTQ]: https://en.wikipedia.org/wiki/FASTQ_format
+//! [k-mers]: https://en.wikipedia.org/wiki/K-mer
+
+use crate::code::Letter;
+use crate::encoding::EncodingScheme;
+use crate::stream;
+use crate::tokens::bits::Bits;
+use crate::tokens::bytes::Byte;
+use crate::tokens::integers::Integer;
+use anyhow::{anyhow, Result};
+use std::convert::TryFrom;
+
+/// The maximum number of nucleotides in a k-mer.
+pub const MAX_K: u8 = 16;
+
+// Kinds of lines.
+const HEADER: u8 = b'>';
+const SEQUENCE: u8 = b'S';
+const PLUS: u8 = b'+';
+const QUALITY: u8 = b'Q';
+
+const NUCLEOTIDES: &[u8; 4] = b"ACGT";
+
+fn nucleotide(b: u8) -> Option<u8> {
+    NUCLEOTIDES.iter().position(|n| *n == b).map(|i| i as u8)
+}
+
+fn number(n: usize) -> Integer {
+    Integer::Value {
+        value: n as u64,
+        width: 8,
+    }
+}
+
+fn from_number(i: Integer) -> Result<usize> {
+    match i {
+        Integer::Value { value, .. } => Ok(usize::try_from(value)?),
+        Integer::Tail(_) => Err(anyhow!("Unexpected tail in sequence")),
+    }
+}
+
+// Where the next line falls in the layout of the file.
+enum State {
+    // Outside a FASTQ record.
+    Fasta,
+    // In the sequence of a FASTQ record, after `bases` bases.
+    FastqSequence { bases: usize },
+    // In the quality lines of a FASTQ record, with `remaining` scores to go.
+    FastqQuality { remaining: usize },
+}
+
+/// Split a sequence (without line breaks) into streams.
+#[derive(Debug, Default)]
+struct Splitter {
+    case: Vec<usize>,
+    lower: bool,
+    exception_gaps: Vec<usize>,
+    exception_symbols: Vec<u8>,
+    exception_lengths: Vec<usize>,
+    // The number of nucleotides since the last exception.
+    gap: usize,
+    nucleotides: Vec<u8>,
+}
+
+impl Splitter {
+    fn push(&mut self, b: u8) {
+        if self.case.is_empty() {
+            self.case.push(0);
+        }
+        if b.is_ascii_lowercase() != self.lower {
+            self.lower = !self.lower;
+            self.case.push(0);
+        }
+        *self.case.last_mut().unwrap() += 1;
+
+        let b = b.to_ascii_uppercase();
+        match nucleotide(b) {
+            Some(n) => {
+                self.nucleotides.push(n);
+                self.gap += 1;
+            }
+            None if self.gap == 0 && self.exception_symbols.last() == Some(&b) => {
+                *self.exception_lengths.last_mut().unwrap() += 1;
+            }
+            None => {
+                self.exception_gaps.push(self.gap);
+                self.exception_symbols.push(b);
+                self.exception_lengths.push(1);
+                self.gap = 0;
+            }
+        }
+    }
+}
+
+fn kmers(nucleotides: &[u8], k: u8) -> Vec<Bits> {
+    nucleotides
+        .chunks(k as usize)
+        .map(|c| {
+            let mut l = Letter::with_capacity(2 * c.len() as u64);
+            for n in c {
+                for bit in [n & 2, n & 1] {
+                    if bit != 0 {
+                        l.push1();
+                    } else {
+                        l.push0();
+                    }
+                }
+            }
+            if c.len() == k as usize {
+                Bits::Full(l)
+            } else {
+                Bits::Tail(l)
+            }
+        })
+        .collect()
+}
+
+fn from_kmers(kmers: Vec<Bits>) -> Result<Vec<u8>> {
+    let mut nucleotides = Vec::new();
+    for b in kmers {
+        let l = match b {
+            Bits::Full(l) | Bits::Tail(l) => l,
+        };
+        for i in (0..l.bit_count() as usize).step_by(2) {
+            let n = (l.at(i)? as usize) << 1 | l.at(i + 1)? as usize;
+            nucleotides.push(NUCLEOTIDES[n]);
+        }
+    }
+    Ok(nucleotides)
+}
+
+/// Compress `data` with a nucleotide sequence front end.
+///
+/// Nucleotides are grouped into k-mers of `k` nucleotides. Each token stream
+/// is compressed with an encoding generated with `encoding_scheme`. The output
+/// can be decompressed with [`unpack()`].
+///
+/// Returns an error unless `k` is between 1 and [`MAX_K`].
+pub fn pack<W: std::io::Write>(
+    data: &[u8],
+    encoding_scheme: &EncodingScheme,
+    k: u8,
+    mut w: W,
+) -> Result<()> {
+    if k == 0 || k > MAX_K {
+        return Err(anyhow!(
+            "k-mer length must be between 1 and {}, got {}",
+            MAX_K,
+            k
+        ));
+    }
+
+    let mut kinds = Vec::new();
+    let mut lengths = Vec::new();
+    let mut headers = Vec::new();
+    let mut qualities = Vec::new();
+    let mut splitter = Splitter::default();
+    let mut state = State::Fasta;
+    for line in data.split_inclusive(|b| *b == b'\n') {
+        let line = line.strip_suffix(b"\n").unwrap_or(line);
+        let kind = match state {
+            State::Fasta if line.starts_with(b">") => HEADER,
+            State::Fasta if line.starts_with(b"@") => {
+                state = State::FastqSequence { bases: 0 };
+                HEADER
+            }
+            State::FastqSequence { bases } if line.starts_with(b"+") => {
+                state = State::FastqQuality { remaining: bases };
+                PLUS
+            }
+            State::FastqSequence { bases } => {
+                state = State::FastqSequence {
+                    bases: bases + line.len(),
+                };
+                SEQUENCE
+            }
+            State::FastqQuality { remaining } => {
+                let remaining = remaining.saturating_sub(line.len());
+                state = if remaining == 0 {
+                    State::Fasta
+                } else {
+                    State::FastqQuality { remaining }
+                };
+                QUALITY
+            }
+            State::Fasta => SEQUENCE,
+        };
+        kinds.push(Byte::from(kind));
+        match kind {
+            SEQUENCE => {
+                lengths.push(number(line.len()));
+                for b in line {
+                    splitter.push(*b);
+                }
+            }
+            QUALITY => {
+                qualities.extend(line.iter().map(|b| Byte::from(*b)));
+                qualities.push(Byte::from(b'\n'));
+            }
+            _ => {
+                headers.extend(line.iter().map(|b| Byte::from(*b)));
+                headers.push(Byte::from(b'\n'));
+            }
+        }
+    }
+
+    let to_numbers = |v: &[usize]| v.iter().map(|n| number(*n)).collect::<Vec<_>>();
+    let symbols: Vec<Byte> = splitter
+        .exception_symbols
+        .iter()
+        .map(|b| Byte::from(*b))
+        .collect();
+    w.write_all(&[(data.last() == Some(&b'\n')) as u8])?;
+    stream::pack(&kinds, encoding_scheme, &mut w)?;
+    stream::pack(&lengths, encoding_scheme, &mut w)?;
+    stream::pack(&headers, encoding_scheme, &mut w)?;
+    stream::pack(&qualities, encoding_scheme, &mut w)?;
+    stream::pack(&to_numbers(&splitter.case), encoding_scheme, &mut w)?;
+    stream::pack(
+        &to_numbers(&splitter.exception_gaps),
+        encoding_scheme,
+        &mut w,
+    )?;
+    stream::pack(&symbols, encoding_scheme, &mut w)?;
+    stream::pack(
+        &to_numbers(&splitter.exception_lengths),
+        encoding_scheme,
+        &mut w,
+    )?;
+    stream::pack(&kmers(&splitter.nucleotides, k), encoding_scheme, &mut w)?;
+    w.flush()?;
+    Ok(())
+}
+
+// Read the next line (up to a `\n`) from `bytes`.
+fn next_line<I: Iterator<Item = Byte>>(bytes: &mut I, out: &mut Vec<u8>) -> Result<()> {
+    loop {
+        match bytes.next().map(u8::from) {
+            Some(b'\n') => return Ok(()),
+            Some(b) => out.push(b),
+            None => return Err(anyhow!("Ran out of header or quality text")),
+        }
+    }
+}
+
+/// Decompress data compressed with [`pack()`].
+pub fn unpack<R: std::io::Read, W: std::io::Write>(mut r: R, mut w: W) -> Result<()> {
+    let mut ends_with_newline = [0u8];
+    r.read_exact(&mut ends_with_newline)?;
+    let kinds: Vec<Byte> = stream::unpack(&mut r)?;
+    let lengths: Vec<Integer> = stream::unpack(&mut r)?;
+    let headers: Vec<Byte> = stream::unpack(&mut r)?;
+    let qualities: Vec<Byte> = stream::unpack(&mut r)?;
+    let case: Vec<Integer> = stream::unpack(&mut r)?;
+    let exception_gaps: Vec<Integer> = stream::unpack(&mut r)?;
+    let exception_symbols: Vec<Byte> = stream::unpack(&mut r)?;
+    let exception_lengths: Vec<Integer> = stream::unpack(&mut r)?;
+    let kmers: Vec<Bits> = stream::unpack(&mut r)?;
+
+    // Rebuild the sequence: nucleotides, with the exceptions in between.
+    let mut nucleotides = from_kmers(kmers)?.into_iter();
+    let mut sequence = Vec::new();
+    for ((gap, symbol), length) in exception_gaps
+        .into_iter()
+        .zip(exception_symbols)
+        .zip(exception_lengths)
+    {
+        let gap = from_number(gap)?;
+        sequence.extend(nucleotides.by_ref().take(gap));
+        sequence.extend(std::iter::repeat_n(u8::from(symbol), from_number(length)?));
+    }
+    sequence.extend(nucleotides);
+    let mut i = 0;
+    for (j, run) in case.into_iter().enumerate() {
+        let run = from_number(run)?;
+        if i + run > sequence.len() {
+            return Err(anyhow!("Case runs are longer than the sequence"));
+        }
+        if j % 2 == 1 {
+            sequence[i..i + run].make_ascii_lowercase();
+        }
+        i += run;
+    }
+
+    let mut out = Vec::new();
+    let mut lengths = lengths.into_iter();
+    let mut sequence = &sequence[..];
+    let mut headers = headers.into_iter();
+    let mut qualities = qualities.into_iter();
+    for kind in kinds {
+        match u8::from(kind) {
+            SEQUENCE => {
+                let length = match lengths.next() {
+                    Some(l) => from_number(l)?,
+                    None => return Err(anyhow!("Ran out of sequence line lengths")),
+                };
+                if length > sequence.len() {
+                    return Err(anyhow!("Ran out of sequence"));
+                }
+                let (line, rest) = sequence.split_at(length);
+                out.extend_from_slice(line);
+                sequence = rest;
+            }
+            QUALITY => next_line(&mut qualities, &mut out)?,
+            HEADER | PLUS => next_line(&mut headers, &mut out)?,
+            k => return Err(anyhow!("Unknown line kind {}", k)),
+        }
+        out.push(b'\n');
+    }
+    if ends_with_newline[0] == 0 {
+        out.pop();
+    }
+    w.write_all(&out)?;
+    w.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    const FASTA: &str = "\
+>chr1 test sequence
+NNNNACGTACGTTTGAcgtacgtaNNACG
+TTGCAnnnRYACGTACGTACGTACGTACG
+ACG
+>chr2
+ACGT
+";
+
+    const FASTQ: &str = "\
+@read1
+ACGTNACGTT
++
+IIIIIIIII#
+@read2 multi-line
+ACGT
+acgt
++read2
+@@@@
+IIII
+";
+
+    fn roundtrip(text: &[u8], k: u8) -> Vec<u8> {
+        let mut buf = Vec::new();
+        pack(text, &EncodingScheme::Huffman, k, &mut buf).unwrap();
+        let mut got = Vec::new();
+        unpack(Cursor::new(buf), &mut got).unwrap();
+        got
+    }
+
+    #[test]
+    fn roundtrip_fasta() {
+        for k in [1, 3, 4, MAX_K] {
+            assert_eq!(roundtrip(FASTA.as_bytes(), k), FASTA.as_bytes());
+        }
+    }
+
+    #[test]
+    fn roundtrip_fastq() {
+        assert_eq!(roundtrip(FASTQ.as_bytes(), 4), FASTQ.as_bytes());
+    }
+
+    #[test]
+    fn roundtrip_other() {
+        for text in [&b""[..], b"\n", b"ACGT", b"@\n", b"no\r\nsequence\xff"] {
+            assert_eq!(roundtrip(text, 4), text);
+        }
+    }
+
+    #[test]
+    fn invalid_k() {
+        let mut buf = Vec::new();
+        assert!(pack(b"", &EncodingScheme::Huffman, 0, &mut buf).is_err());
+        assert!(pack(b"", &EncodingScheme::Huffman, MAX_K + 1, &mut buf).is_err());
+    }
+
+    #[test]
+    fn split_sequence() {
+        let mut s = Splitter::default();
+        for b in b"NNacGNt" {
+            s.push(*b);
+        }
+        assert_eq!(s.case, vec![2, 2, 2, 1]);
+        assert_eq!(s.exception_gaps, vec![0, 3]);
+        assert_eq!(s.exception_symbols, b"NN");
+        assert_eq!(s.exception_lengths, vec![2, 1]);
+        assert_eq!(s.nucleotides, vec![0, 1, 2, 3]);
+    }
+}
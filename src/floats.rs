@@ -0,0 +1,334 @@
+// Copyright 2020 Prathmesh Prabhu
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! floats module implements a front end to the entropy coders for arrays of
+//! IEEE 754 floating point numbers, in the manner of [Gorilla].
+//!
+//! Consecutive values in a time series are usually close to each other, so
+//! their sign, exponent and leading mantissa bits are equal. Each value is
+//! XORed with the value before it, and the result is split into token streams
+//! that are each compressed with their own
+//! [`Encoding`](crate::encoding::Encoding):
+//!
+//! - lengths: the number of meaningful bits of each XOR, i.e. the bits between
+//!   its leading and trailing zeros. A length of 0 means the value repeats the
+//!   previous one.
+//! - leading: the number of leading zeros of each non-zero XOR.
+//! - payload: the meaningful bits of each non-zero XOR, as whole bytes. The
+//!   payload is coded byte by byte because most payloads occur only once.
+//!
+//! The bytes left over at the end of the source text when its length is not a
+//! multiple of the width are included in the compressed output as is.
+//!
+//! [Gorilla]: https://www.vldb.org/pvldb/vol8/p1816-teller.pdf
+
+use crate::encoding::EncodingScheme;
+use crate::stream;
+use crate::tokens::bytes::Byte;
+use crate::tokens::integers::Endianness;
+use crate::util::{pack_u64, unpack_bytes};
+use anyhow::{anyhow, Result};
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// The type of floating point numbers in the source text.
+#[derive(Copy, Clone, Debug, Eq, Hash, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum FloatWidth {
+    F32,
+    F64,
+}
+
+impl FloatWidth {
+    fn bytes(&self) -> usize {
+        match self {
+            FloatWidth::F32 => 4,
+            FloatWidth::F64 => 8,
+        }
+    }
+
+    fn bits(&self) -> u32 {
+        8 * self.bytes() as u32
+    }
+}
+
+pub fn pack_float_width(width: FloatWidth) -> u8 {
+    match width {
+        FloatWidth::F32 => 0u8,
+        FloatWidth::F64 => 1u8,
+    }
+}
+
+pub fn unpack_float_width(marker: u8) -> Result<FloatWidth> {
+    match marker {
+        0u8 => Ok(FloatWidth::F32),
+        1u8 => Ok(FloatWidth::F64),
+        m => Err(anyhow!("Unknown float width marker {}", m)),
+    }
+}
+
+fn read(bytes: &[u8], endianness: Endianness) -> u64 {
+    let mut buf = [0u8; 8];
+    match endianness {
+        Endianness::Little => {
+            buf[..bytes.len()].copy_from_slice(bytes);
+            u64::from_le_bytes(buf)
+        }
+        Endianness::Big => {
+            buf[8 - bytes.len()..].copy_from_slice(bytes);
+            u64::from_be_bytes(buf)
+        }
+    }
+}
+
+fn write(value: u64, width: FloatWidth, endianness: Endianness, out: &mut Vec<u8>) {
+    let n = width.bytes();
+    match endianness {
+        Endianness::Little => out.extend_from_slice(&value.to_le_bytes()[..n]),
+        Endianness::Big => out.extend_from_slice(&value.to_be_bytes()[8 - n..]),
+    }
+}
+
+/// The parts of the XOR of a value with the previous value.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Xor {
+    /// The number of leading zeros.
+    pub leading: u32,
+    /// The number of bits between the leading and trailing zeros, 0 if the
+    /// XOR is 0.
+    pub length: u32,
+    /// The meaningful bits, shifted to the right.
+    pub payload: u64,
+}
+
+impl Xor {
+    /// Split the XOR `x` of two values of `width`.
+    pub fn new(x: u64, width: FloatWidth) -> Self {
+        if x == 0 {
+            return Xor {
+                leading: 0,
+                length: 0,
+                payload: 0,
+            };
+        }
+        let leading = x.leading_zeros() - (64 - width.bits());
+        let trailing = x.trailing_zeros();
+        Xor {
+            leading,
+            length: width.bits() - leading - trailing,
+            payload: x >> trailing,
+        }
+    }
+
+    /// Join the parts back into the XOR of two values of `width`.
+    pub fn join(&self, width: FloatWidth) -> Result<u64> {
+        if self.length == 0 {
+            return Ok(0);
+        }
+        let trailing = width
+            .bits()
+            .checked_sub(self.leading + self.length)
+            .ok_or_else(|| anyhow!("Invalid XOR {:?}", self))?;
+        if self.payload >> (self.length - 1) != 1 || self.payload & 1 != 1 {
+            return Err(anyhow!("Invalid XOR {:?}", self));
+        }
+        Ok(self.payload << trailing)
+    }
+
+    fn payload_bytes(&self) -> usize {
+        (self.length as usize).div_ceil(8)
+    }
+}
+
+/// Compress `data` as an array of floats of `width` and `endianness`, with an
+/// XOR front end.
+///
+/// Each token stream is compressed with an encoding generated with
+/// `encoding_scheme`. The output can be decompressed with [`unpack()`].
+pub fn pack<W: std::io::Write>(
+    data: &[u8],
+    encoding_scheme: &EncodingScheme,
+    width: FloatWidth,
+    endianness: Endianness,
+    mut w: W,
+) -> Result<()> {
+    let values = data.chunks_exact(width.bytes());
+    let tail = values.remainder();
+    let mut lengths = Vec::new();
+    let mut leading = Vec::new();
+    let mut payload = Vec::new();
+    let mut previous = 0u64;
+    for v in values {
+        let v = read(v, endianness);
+        let x = Xor::new(v ^ previous, width);
+        previous = v;
+        lengths.push(Byte::from(x.length as u8));
+        if x.length == 0 {
+            continue;
+        }
+        leading.push(Byte::from(x.leading as u8));
+        let bytes = x.payload.to_be_bytes();
+        payload.extend(
+            bytes[8 - x.payload_bytes()..]
+                .iter()
+                .map(|b| Byte::from(*b)),
+        );
+    }
+
+    for s in [lengths, leading, payload] {
+        stream::pack(&s, encoding_scheme, &mut w)?;
+    }
+    w.write_all(&pack_u64(tail.len() as u64))?;
+    w.write_all(tail)?;
+    w.flush()?;
+    Ok(())
+}
+
+/// Decompress data compressed with [`pack()`].
+///
+/// `width` and `endianness` must be the same as used with [`pack()`].
+pub fn unpack<R: std::io::Read, W: std::io::Write>(
+    mut r: R,
+    width: FloatWidth,
+    endianness: Endianness,
+    mut w: W,
+) -> Result<()> {
+    let mut streams = Vec::new();
+    for _ in 0..3 {
+        let s: Vec<Byte> = stream::unpack(&mut r)?;
+        streams.push(s.into_iter().map(u8::from));
+    }
+    let mut out = Vec::new();
+    let mut previous = 0u64;
+    while let Some(length) = streams[0].next() {
+        let mut x = Xor {
+            leading: 0,
+            length: length as u32,
+            payload: 0,
+        };
+        if x.length != 0 {
+            x.leading = streams[1]
+                .next()
+                .ok_or_else(|| anyhow!("Ran out of leading zero counts"))?
+                as u32;
+            for _ in 0..x.payload_bytes() {
+                let b = streams[2]
+                    .next()
+                    .ok_or_else(|| anyhow!("Ran out of payload"))?;
+                x.payload = x.payload << 8 | b as u64;
+            }
+        }
+        previous ^= x.join(width)?;
+        write(previous, width, endianness, &mut out);
+    }
+
+    out.extend(unpack_bytes(&mut r)?);
+    w.write_all(&out)?;
+    w.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    // A slowly varying series, with repeats.
+    fn series() -> Vec<f64> {
+        (0..1000)
+            .map(|i| 20.0 + (i / 3) as f64 * 0.25 + ((i / 50) as f64).sin())
+            .collect()
+    }
+
+    fn compress(data: &[u8], width: FloatWidth, endianness: Endianness) -> Vec<u8> {
+        let mut buf = Vec::new();
+        pack(data, &EncodingScheme::Huffman, width, endianness, &mut buf).unwrap();
+        buf
+    }
+
+    fn roundtrip(data: &[u8], width: FloatWidth, endianness: Endianness) -> Vec<u8> {
+        let mut got = Vec::new();
+        unpack(
+            Cursor::new(compress(data, width, endianness)),
+            width,
+            endianness,
+            &mut got,
+        )
+        .unwrap();
+        got
+    }
+
+    #[test]
+    fn roundtrip_f64() {
+        let mut data: Vec<u8> = series().iter().flat_map(|v| v.to_le_bytes()).collect();
+        data.extend_from_slice(b"abc");
+        for endianness in [Endianness::Little, Endianness::Big] {
+            assert_eq!(roundtrip(&data, FloatWidth::F64, endianness), data);
+        }
+    }
+
+    #[test]
+    fn roundtrip_f32() {
+        let data: Vec<u8> = series()
+            .iter()
+            .flat_map(|v| (*v as f32).to_be_bytes())
+            .collect();
+        for endianness in [Endianness::Little, Endianness::Big] {
+            assert_eq!(roundtrip(&data, FloatWidth::F32, endianness), data);
+        }
+    }
+
+    #[test]
+    fn roundtrip_other() {
+        let special: Vec<u8> = [0.0, -0.0, f64::NAN, f64::INFINITY, f64::MIN, 1e-310]
+            .iter()
+            .flat_map(|v: &f64| v.to_le_bytes())
+            .collect();
+        for data in [&b""[..], b"abc", b"not really floats", &special[..]] {
+            for width in [FloatWidth::F32, FloatWidth::F64] {
+                assert_eq!(roundtrip(data, width, Endianness::Little), data);
+            }
+        }
+    }
+
+    #[test]
+    fn xor_parts() {
+        assert_eq!(
+            Xor::new(0x0ff0, FloatWidth::F32),
+            Xor {
+                leading: 20,
+                length: 8,
+                payload: 0xff,
+            }
+        );
+        for (x, width) in [
+            (0, FloatWidth::F64),
+            (1, FloatWidth::F64),
+            (u64::MAX, FloatWidth::F64),
+            (0x8000_0001, FloatWidth::F32),
+        ] {
+            assert_eq!(Xor::new(x, width).join(width).unwrap(), x);
+        }
+    }
+
+    #[test]
+    fn xor_helps() {
+        let data: Vec<u8> = series().iter().flat_map(|v| v.to_le_bytes()).collect();
+        let mut bytes = Vec::new();
+        let tokens: Vec<Byte> = data.iter().map(|b| Byte::from(*b)).collect();
+        stream::pack(&tokens, &EncodingScheme::Huffman, &mut bytes).unwrap();
+        assert!(compress(&data, FloatWidth::F64, Endianness::Little).len() < bytes.len() / 2);
+    }
+}
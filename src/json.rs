@@ -0,0 +1,341 @@
+// Copyright 2020 Prathmesh Prabhu
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! json module implements a structural front end to the entropy coders for
+//! [JSON] text, including [JSON Lines].
+//!
+//! The source text is split into lexical tokens, which are compressed as
+//! separate streams, each with its own
+//! [`Encoding`](crate::encoding::Encoding):
+//!
+//! - structure: one token per lexical token, i.e. the punctuation
+//!   (`{}[]:,`), the literals (`true`, `false`, `null`), and a placeholder for
+//!   each key, string, number and run of whitespace.
+//! - keys: the object keys. A key is a string followed by `:`.
+//! - strings: all other strings.
+//! - numbers: the bytes of each number, each followed by a `,`. Numbers are
+//!   coded byte by byte because, unlike keys, most numbers (e.g. timestamps)
+//!   occur only once.
+//! - whitespace: the text of each run of whitespace.
+//!
+//! Keys repeat far more than the other values (e.g. every line of a JSON Lines
+//! log has the same keys), so giving them their own encoding makes them cheap.
+//! Strings and numbers are stored as written (escapes included), and
+//! whitespace is stored as well, so the output is byte-for-byte identical to
+//! the source text.
+//!
+//! Only the lexical structure of the text is recognized, so the text need not
+//! be a valid JSON document. Text that can not be split into JSON tokens
+//! (e.g. with an unterminated string) is compressed as a single stream of bytes
+//! instead.
+//!
+//! [JSON]: https://www.rfc-editor.org/rfc/rfc8259
+//! [JSON Lines]: https://jsonlines.org/
+
+use crate::encoding::EncodingScheme;
+use crate::stream;
+use crate::tokens::bytes::Byte;
+use crate::tokens::rules::Lexeme;
+use anyhow::{anyhow, Result};
+
+// Markers for the mode of the compressed text.
+const RAW: u8 = 0;
+const STRUCTURE: u8 = 1;
+
+// Terminates each number in the numbers stream.
+const END_OF_NUMBER: u8 = b',';
+
+// Placeholders in the structure stream. Punctuation is included as is.
+const KEY: u8 = b'k';
+const STRING: u8 = b'"';
+const NUMBER: u8 = b'0';
+const WHITESPACE: u8 = b' ';
+const LITERALS: [(u8, &[u8]); 3] = [(b't', b"true"), (b'f', b"false"), (b'n', b"null")];
+
+/// A lexical token of JSON text.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Lex<'a> {
+    /// One of `{}[]:,`.
+    Punctuation(u8),
+    /// One of `true`, `false` or `null`, as its structure stream token.
+    Literal(u8),
+    /// A string followed by `:`, without the quotes.
+    Key(&'a [u8]),
+    /// Any other string, without the quotes.
+    String(&'a [u8]),
+    Number(&'a [u8]),
+    Whitespace(&'a [u8]),
+}
+
+fn is_whitespace(b: u8) -> bool {
+    matches!(b, b' ' | b'\t' | b'\n' | b'\r')
+}
+
+fn is_number(b: u8) -> bool {
+    matches!(b, b'0'..=b'9' | b'-' | b'+' | b'.' | b'e' | b'E')
+}
+
+// The length of the string starting with the `"` at `data[i]`, including
+// both quotes.
+fn string_length(data: &[u8], i: usize) -> Option<usize> {
+    let mut j = i + 1;
+    loop {
+        match data.get(j)? {
+            b'"' => return Some(j + 1 - i),
+            b'\\' => j += 2,
+            _ => j += 1,
+        }
+    }
+}
+
+/// Split `data` into [`Lex`]s.
+///
+/// Returns `None` if `data` contains anything other than JSON tokens and
+/// whitespace.
+pub fn lex(data: &[u8]) -> Option<Vec<Lex<'_>>> {
+    let mut lexes = Vec::new();
+    let mut i = 0;
+    while i < data.len() {
+        let b = data[i];
+        let len = match b {
+            b'{' | b'}' | b'[' | b']' | b':' | b',' => {
+                lexes.push(Lex::Punctuation(b));
+                1
+            }
+            b'"' => {
+                let len = string_length(data, i)?;
+                let text = &data[i + 1..i + len - 1];
+                let rest = &data[i + len..];
+                match rest.iter().find(|b| !is_whitespace(**b)) {
+                    Some(b':') => lexes.push(Lex::Key(text)),
+                    _ => lexes.push(Lex::String(text)),
+                }
+                len
+            }
+            b'-' | b'0'..=b'9' => {
+                let len = data[i..].iter().take_while(|b| is_number(**b)).count();
+                lexes.push(Lex::Number(&data[i..i + len]));
+                len
+            }
+            _ if is_whitespace(b) => {
+                let len = data[i..].iter().take_while(|b| is_whitespace(**b)).count();
+                lexes.push(Lex::Whitespace(&data[i..i + len]));
+                len
+            }
+            _ => {
+                let (marker, text) = LITERALS
+                    .iter()
+                    .find(|(_, text)| data[i..].starts_with(text))?;
+                lexes.push(Lex::Literal(*marker));
+                text.len()
+            }
+        };
+        i += len;
+    }
+    Some(lexes)
+}
+
+/// Compress `data` with a structural JSON front end.
+///
+/// Each token stream is compressed with an encoding generated with
+/// `encoding_scheme`. The output can be decompressed with [`unpack()`].
+pub fn pack<W: std::io::Write>(
+    data: &[u8],
+    encoding_scheme: &EncodingScheme,
+    mut w: W,
+) -> Result<()> {
+    let lexes = match lex(data) {
+        Some(lexes) => lexes,
+        None => {
+            w.write_all(&[RAW])?;
+            let bytes: Vec<Byte> = data.iter().map(|b| Byte::from(*b)).collect();
+            stream::pack(&bytes, encoding_scheme, &mut w)?;
+            w.flush()?;
+            return Ok(());
+        }
+    };
+
+    let mut structure = Vec::with_capacity(lexes.len());
+    let mut keys = Vec::new();
+    let mut strings = Vec::new();
+    let mut numbers = Vec::new();
+    let mut whitespace = Vec::new();
+    for l in lexes {
+        let (marker, text, values) = match l {
+            Lex::Punctuation(b) | Lex::Literal(b) => {
+                structure.push(Byte::from(b));
+                continue;
+            }
+            Lex::Key(text) => (KEY, text, &mut keys),
+            Lex::String(text) => (STRING, text, &mut strings),
+            Lex::Number(text) => {
+                structure.push(Byte::from(NUMBER));
+                numbers.extend(text.iter().map(|b| Byte::from(*b)));
+                numbers.push(Byte::from(END_OF_NUMBER));
+                continue;
+            }
+            Lex::Whitespace(text) => (WHITESPACE, text, &mut whitespace),
+        };
+        structure.push(Byte::from(marker));
+        values.push(Lexeme::from(text.to_vec()));
+    }
+
+    w.write_all(&[STRUCTURE])?;
+    stream::pack(&structure, encoding_scheme, &mut w)?;
+    stream::pack(&numbers, encoding_scheme, &mut w)?;
+    for values in [keys, strings, whitespace] {
+        stream::pack(&values, encoding_scheme, &mut w)?;
+    }
+    w.flush()?;
+    Ok(())
+}
+
+/// Decompress data compressed with [`pack()`].
+pub fn unpack<R: std::io::Read, W: std::io::Write>(mut r: R, mut w: W) -> Result<()> {
+    let mut mode = [0u8];
+    r.read_exact(&mut mode)?;
+    match mode[0] {
+        RAW => {
+            let bytes: Vec<Byte> = stream::unpack(&mut r)?;
+            let data: Vec<u8> = bytes.into_iter().map(u8::from).collect();
+            w.write_all(&data)?;
+            w.flush()?;
+            return Ok(());
+        }
+        STRUCTURE => (),
+        m => return Err(anyhow!("Unknown JSON mode {}", m)),
+    }
+
+    let structure: Vec<Byte> = stream::unpack(&mut r)?;
+    let numbers: Vec<Byte> = stream::unpack(&mut r)?;
+    let mut numbers = numbers.into_iter().map(u8::from);
+    let mut streams = Vec::new();
+    for _ in 0..3 {
+        let values: Vec<Lexeme> = stream::unpack(&mut r)?;
+        streams.push(values.into_iter().map(Vec::from));
+    }
+    let mut out = Vec::new();
+    for s in structure {
+        let marker = u8::from(s);
+        let (stream, quote) = match marker {
+            b'{' | b'}' | b'[' | b']' | b':' | b',' => {
+                out.push(marker);
+                continue;
+            }
+            KEY => (0, true),
+            STRING => (1, true),
+            NUMBER => {
+                loop {
+                    match numbers.next() {
+                        Some(END_OF_NUMBER) => break,
+                        Some(b) => out.push(b),
+                        None => return Err(anyhow!("Ran out of numbers")),
+                    }
+                }
+                continue;
+            }
+            WHITESPACE => (2, false),
+            _ => match LITERALS.iter().find(|(m, _)| *m == marker) {
+                Some((_, text)) => {
+                    out.extend_from_slice(text);
+                    continue;
+                }
+                None => return Err(anyhow!("Unknown JSON structure token {}", marker)),
+            },
+        };
+        let text = streams[stream]
+            .next()
+            .ok_or_else(|| anyhow!("Ran out of values for JSON token {}", marker))?;
+        if quote {
+            out.push(b'"');
+        }
+        out.extend_from_slice(&text);
+        if quote {
+            out.push(b'"');
+        }
+    }
+    w.write_all(&out)?;
+    w.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    const TEXT: &str = r#"{"line": 1, "text": "Ah! well a-day! what evil looks", "rhymes": true}
+{"line": 2, "text": "Had I from old and young!", "rhymes": false}
+{"line": 3, "text": "Instead of the \"cross\", the Albatross", "rhymes": null}
+{"line": 4.0e0, "text": "About my neck was hung.", "words": [5, -1]}
+"#;
+
+    fn roundtrip(text: &[u8]) -> Vec<u8> {
+        let mut buf = Vec::new();
+        pack(text, &EncodingScheme::Huffman, &mut buf).unwrap();
+        let mut got = Vec::new();
+        unpack(Cursor::new(buf), &mut got).unwrap();
+        got
+    }
+
+    #[test]
+    fn roundtrip_lines() {
+        assert_eq!(roundtrip(TEXT.as_bytes()), TEXT.as_bytes());
+    }
+
+    #[test]
+    fn roundtrip_documents() {
+        for text in [
+            &b""[..],
+            b"  ",
+            b"[]",
+            b"{\r\n\t\"a\" :\"b\\\\\"\r\n}",
+            b"\"\\u00e9\"",
+            b"{\"nested\": {\"a\": [[], {}]}}",
+        ] {
+            assert_eq!(roundtrip(text), text);
+        }
+    }
+
+    #[test]
+    fn roundtrip_malformed() {
+        for text in [&b"\"unterminated"[..], b"{\"a\": nil}", b"tru"] {
+            assert!(lex(text).is_none());
+            assert_eq!(roundtrip(text), text);
+        }
+    }
+
+    #[test]
+    fn keys_and_values() {
+        assert_eq!(
+            lex(br#"{"a" : "b", "c":[-1.5e3]}"#).unwrap(),
+            vec![
+                Lex::Punctuation(b'{'),
+                Lex::Key(b"a"),
+                Lex::Whitespace(b" "),
+                Lex::Punctuation(b':'),
+                Lex::Whitespace(b" "),
+                Lex::String(b"b"),
+                Lex::Punctuation(b','),
+                Lex::Whitespace(b" "),
+                Lex::Key(b"c"),
+                Lex::Punctuation(b':'),
+                Lex::Punctuation(b'['),
+                Lex::Number(b"-1.5e3"),
+                Lex::Punctuation(b']'),
+                Lex::Punctuation(b'}'),
+            ]
+        );
+    }
+}
@@ -38,13 +38,17 @@
 //!
 //! The library exposes the same functionality via the [`run`] function:
 //! ```
-//! use cshannon::{Args, Command, CompressArgs, EncodingScheme, TokenizationScheme, run};
+//! use cshannon::{
+//!     Args, Charset, Command, CompressArgs, EncodingScheme, TokenizationScheme, Transform, run,
+//! };
 //! use std::path::Path;
 //!
 //! run(Args{
 //!     command: Command::Compress(CompressArgs{
 //!         tokenization_scheme: TokenizationScheme::Byte,
-//!         encoding_scheme: EncodingScheme::Fano
+//!         encoding_scheme: EncodingScheme::Fano,
+//!         transform: Transform::None,
+//!         charset: Charset::Auto,
 //!     }),
 //!     input_file: &Path::new("/path/to/input_file"),
 //!     output_file: &Path::new("/path/to/output_file"),
@@ -89,9 +93,11 @@
 //
 // # Crate layout
 //
-// - The [tokens] module provides traits for tokenizing text. Three concrete
-//   tokenization schemes are implemented: [tokens::bytes], [tokens::graphemes]
-//   and [tokens::words].
+// - The [tokens] module provides traits for tokenizing text. Twelve concrete
+//   tokenization schemes are implemented: [tokens::bits], [tokens::bytes],
+//   [tokens::byte_pairs], [tokens::code_points], [tokens::graphemes],
+//   [tokens::integers], [tokens::lines], [tokens::n_grams], [tokens::rules],
+//   [tokens::runs], [tokens::segments] and [tokens::words].
 // - The [model] module provides a way to compute a zeroeth order model from a
 //   stream of tokens.
 // - The [encoding] module provides traits for creating an encoding scheme from
@@ -101,16 +107,67 @@
 // - Finally, the [code] module provides methods to encode a token stream given
 //   an encoding. The encoding itself is also included in the compressed
 //   output.
+// - The [stream] module packs a single token stream along with its own
+//   encoding, for schemes that split the input into several streams.
+// - The [lz77] module implements an LZ77 front end that replaces repeated text
+//   with back-references, and codes the resulting literal, length and distance
+//   streams separately.
+// - The [charsets] module decodes source text in charsets other than utf-8
+//   (e.g. UTF-16 or Shift-JIS) ahead of tokenization.
+// - The [csv] module implements a columnar front end for CSV text, that codes
+//   the values in each column separately.
+// - The [fasta] module implements a front end for nucleotide sequences, that
+//   codes bases as 2-bit k-mers apart from headers, masking and exceptions.
+// - The [floats] module implements a front end for arrays of floating point
+//   numbers, that codes the XOR of each value with the previous one.
+// - The [json] module implements a structural front end for JSON text, that
+//   codes keys, values and punctuation separately.
+// - The [log_templates] module implements a front end for log text, that
+//   mines line templates and codes the values of their slots separately.
+// - The [lzw] module implements LZW compression, including the `.Z` format of
+//   the Unix `compress(1)` utility.
+// - The [pnm] module implements lossless coding of PGM and PPM images, that
+//   codes the residuals of predicting each sample from its neighbours.
+// - The [registry] module holds tokenization and encoding schemes registered
+//   at runtime by downstream crates.
+// - The [strided] module implements a front end for binary data made of
+//   fixed-size records, that codes each byte position of a record separately.
+// - The [transforms] module provides reversible transforms (e.g. the
+//   Burrows-Wheeler transform) applied to the input ahead of tokenization.
+// - The [wav] module implements lossless coding of PCM WAV audio with linear
+//   prediction, in the manner of FLAC.
 
+mod charsets;
 mod code;
+mod csv;
 mod encoding;
+mod fasta;
+mod floats;
+mod json;
+mod log_templates;
+mod lz77;
+mod lzw;
 mod model;
+mod pnm;
+mod registry;
+mod stream;
+mod strided;
 mod tokenization_scheme;
 mod tokens;
+mod transforms;
 mod util;
+mod wav;
 
-pub use crate::encoding::EncodingScheme;
+pub use crate::charsets::Charset;
+pub use crate::code::Letter;
+pub use crate::encoding::{EncoderFactory, EncodingScheme};
+pub use crate::floats::FloatWidth;
+pub use crate::pnm::Predictor;
+pub use crate::registry::{register_encoding, register_tokenization};
 pub use crate::tokenization_scheme::TokenizationScheme;
+pub use crate::tokens::integers::{Endianness, IntegerWidth};
+pub use crate::tokens::{Token, TokenPacker, Tokenizer};
+pub use crate::transforms::Transform;
 
 use anyhow::Result;
 use std::path::Path;
@@ -119,7 +176,7 @@ use std::path::Path;
 use serde::{Deserialize, Serialize};
 
 /// The command to invoke via the `run` entry-point.
-#[derive(Copy, Clone, Debug, Eq, Hash, PartialEq)]
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum Command {
     /// Compress the data using one of the implemented algorithms.
@@ -128,12 +185,19 @@ pub enum Command {
     ///
     /// This library does not maintain at-rest data format compatibility.
     /// Trying to decompress data compressed using a different version of the
-    /// library is not guaranteed to work.
+    /// library is not guaranteed to work, and data compressed using cshannon
+    /// 0.2.1 or older is rejected.
     Decompress(DecompressArgs),
+    /// Compress the data into the `.Z` format of the Unix `compress(1)`
+    /// utility.
+    ///
+    /// Unlike the native format, this format is stable and can be read by
+    /// `uncompress(1)`. [`Command::Decompress`] accepts either format.
+    UnixCompress(UnixCompressArgs),
 }
 
 /// Arguments specific to the compression operation.
-#[derive(Copy, Clone, Debug, Eq, Hash, PartialEq)]
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct CompressArgs {
     /// Choose how to split the input data into tokens that are individually
@@ -141,6 +205,22 @@ pub struct CompressArgs {
     pub tokenization_scheme: TokenizationScheme,
     /// Choose the compression algorithm used to compress the tokenized text.
     pub encoding_scheme: EncodingScheme,
+    /// Choose a reversible transform applied to the input data ahead of
+    /// tokenization.
+    pub transform: Transform,
+    /// Choose the character set of the input data. For text tokenization
+    /// schemes (see [`TokenizationScheme::is_text()`]), text in charsets other
+    /// than utf-8 is decoded ahead of the transform and tokenization. Other
+    /// schemes compress the input data as is.
+    pub charset: Charset,
+}
+
+/// Arguments specific to compression into the `.Z` format.
+#[derive(Copy, Clone, Debug, Eq, Hash, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct UnixCompressArgs {
+    /// The maximum width of a code, between 9 and 16 bits.
+    pub max_bits: u8,
 }
 
 /// Placeholder for (future) arguments specific to the decompression operation.
@@ -149,15 +229,17 @@ pub struct CompressArgs {
 pub struct DecompressArgs {}
 
 /// Arguments for the `run` entry-point of this library.
-#[derive(Copy, Clone, Debug, Eq, Hash, PartialEq)]
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
 pub struct Args<'a> {
     pub command: Command,
     /// File to read the input text from.
     ///
     /// Some tokenization schemes make assumptions about the encoding of the
-    /// source text. See documentation for [`TokenizationScheme`].
+    /// source text. See documentation for [`TokenizationScheme`] and
+    /// [`Charset`].
     ///
     /// [`TokenizationScheme`]: ./enum.TokenizationScheme.html
+    /// [`Charset`]: ./enum.Charset.html
     pub input_file: &'a Path,
     /// File to write the output (de)compressed text to.
     ///
@@ -169,13 +251,17 @@ pub struct Args<'a> {
 ///
 /// Example invocation:
 /// ```
-/// use cshannon::{Args, Command, CompressArgs, EncodingScheme, TokenizationScheme, run};
+/// use cshannon::{
+///     Args, Charset, Command, CompressArgs, EncodingScheme, TokenizationScheme, Transform, run,
+/// };
 /// use std::path::Path;
 ///
 /// run(Args{
 ///     command: Command::Compress(CompressArgs{
 ///         tokenization_scheme: TokenizationScheme::Byte,
-///         encoding_scheme: EncodingScheme::Fano
+///         encoding_scheme: EncodingScheme::Fano,
+///         transform: Transform::None,
+///         charset: Charset::Auto,
 ///     }),
 ///     input_file: &Path::new("/path/to/input_file"),
 ///     output_file: &Path::new("/path/to/output_file"),
@@ -187,8 +273,8 @@ pub struct Args<'a> {
 ///
 /// # Errors
 ///
-/// Returns [`anyhow::Error`] for IO errors and errors in parsing the source file
-/// for tokenization schemes where utf-8 encoding is assumed.
+/// Returns [`anyhow::Error`] for IO errors and errors in parsing compressed
+/// data during decompression.
 ///
 /// [`anyhow::Error`]: https://docs.rs/anyhow/latest/anyhow/struct.Error.html
 pub fn run(args: Args) -> Result<()> {
@@ -198,127 +284,348 @@ pub fn run(args: Args) -> Result<()> {
             args.output_file,
             command_args.encoding_scheme,
             command_args.tokenization_scheme,
+            command_args.transform,
+            command_args.charset,
         ),
         Command::Decompress(_) => internal::decompress(args.input_file, args.output_file),
+        Command::UnixCompress(command_args) => {
+            internal::unix_compress(args.input_file, args.output_file, command_args.max_bits)
+        }
     }
 }
 
 mod internal {
 
+    use crate::charsets::{pack_charset, unpack_charset};
     use crate::code::Letter;
-    use crate::encoding::{new_encoder, Encoding};
+    use crate::csv;
+    use crate::encoding::{new_encoder, pack_encoding_scheme, unpack_encoding_scheme, Encoding};
+    use crate::fasta;
+    use crate::floats;
+    use crate::json;
+    use crate::log_templates;
+    use crate::lz77;
+    use crate::lzw;
     use crate::model;
+    use crate::pnm;
+    use crate::registry;
+    use crate::stream::{decode, encode};
+    use crate::strided;
     use crate::tokenization_scheme::{pack_tokenization_scheme, unpack_tokenization_scheme};
-    use crate::tokens::bytes::Byte;
-    use crate::tokens::graphemes::Grapheme;
-    use crate::tokens::words::Word;
+    use crate::tokens::bits::{Bits, BitsPacker, BitsTokenizer};
+    use crate::tokens::byte_pairs::{BytePairPacker, BytePairTokenizer, Merges, Symbol};
+    use crate::tokens::bytes::{Byte, BytePacker, ByteTokenizer};
+    use crate::tokens::code_points::{CodePoint, CodePointPacker, CodePointTokenizer};
+    use crate::tokens::graphemes::{Grapheme, GraphemePacker, GraphemeTokenizer};
+    use crate::tokens::integers::{self, IntegerFormat};
+    use crate::tokens::lines::{Line, LinePacker, LineTokenizer};
+    use crate::tokens::n_grams::{NGram, NGramPacker, NGramTokenizer};
+    use crate::tokens::rules::{Lexeme, LexemePacker, RuleTokenizer};
+    use crate::tokens::runs::{Run, RunPacker, RunTokenizer};
+    use crate::tokens::segments::{Segment, SegmentPacker, SegmentTokenizer};
+    use crate::tokens::words::{Word, WordPacker, WordTokenizer};
     use crate::tokens::{Token, TokenPacker, Tokenizer};
-    use crate::{EncodingScheme, TokenizationScheme};
+    use crate::transforms::{pack_transform, unpack_transform};
+    use crate::wav;
+    use crate::{Charset, EncodingScheme, TokenizationScheme, Transform};
     use anyhow::{anyhow, Result};
-    use log::info;
-    use std::collections::HashMap;
+    use log::{debug, info, warn};
     use std::fs::File;
-    use std::io::{BufReader, BufWriter};
+    use std::io::{BufRead, BufReader, Read, Write};
     use std::path::Path;
 
+    // Compressed files start with `MAGIC`, followed by the version of the
+    // format of the rest of the file.
+    //
+    // Files written before the version was included (by cshannon 0.2.1 and
+    // older) start with the tokenization scheme marker instead.
+    const MAGIC: [u8; 4] = *b"CSHN";
+    const FORMAT_VERSION: u8 = 1;
+
     pub fn compress(
         input_file: &Path,
         output_file: &Path,
         encoding_scheme: EncodingScheme,
         tokenization_scheme: TokenizationScheme,
+        transform: Transform,
+        charset: Charset,
     ) -> Result<()> {
         info!("Compressing...");
 
-        let mut w = BufWriter::new(File::create(output_file)?);
-        pack_tokenization_scheme(tokenization_scheme, &mut w)?;
+        let data = std::fs::read(input_file)?;
+        let charset = if !tokenization_scheme.is_text() {
+            if !matches!(charset, Charset::Auto | Charset::Utf8) {
+                warn!(
+                    "Charset {:?} only applies to text tokenization schemes",
+                    charset
+                );
+            }
+            Charset::Utf8
+        } else if charset == Charset::Auto {
+            Charset::detect(&data)
+        } else {
+            charset
+        };
+        let (charset, data) = match charset.decode(&data) {
+            Some(text) => (charset, text),
+            None => {
+                warn!("Input is not {:?} text, compressing it as is", charset);
+                (Charset::Utf8, data)
+            }
+        };
+        debug!("Input charset is {:?}", charset);
+        let data = transform.forward(data)?;
+        // Compress into memory, so that no output file is left behind when
+        // compression fails.
+        let mut w = Vec::new();
+        w.write_all(&MAGIC)?;
+        w.write_all(&[FORMAT_VERSION])?;
+        pack_tokenization_scheme(&tokenization_scheme, &mut w)?;
+        pack_transform(transform, &mut w)?;
+        pack_charset(charset, &mut w)?;
+        pack_encoding_scheme(&encoding_scheme, &mut w)?;
 
         match tokenization_scheme {
             TokenizationScheme::Byte => {
-                compress_with_token::<Byte, _>(input_file, w, encoding_scheme)
-            }
-            TokenizationScheme::Grapheme => {
-                compress_with_token::<Grapheme, _>(input_file, w, encoding_scheme)
+                compress_with_token::<Byte, _>(&data, &mut w, &encoding_scheme, ByteTokenizer)
             }
+            TokenizationScheme::CodePoint => compress_with_token::<CodePoint, _>(
+                &data,
+                &mut w,
+                &encoding_scheme,
+                CodePointTokenizer,
+            ),
+            TokenizationScheme::Grapheme => compress_with_token::<Grapheme, _>(
+                &data,
+                &mut w,
+                &encoding_scheme,
+                GraphemeTokenizer,
+            ),
+            TokenizationScheme::NGram { n } => compress_with_token::<NGram, _>(
+                &data,
+                &mut w,
+                &encoding_scheme,
+                NGramTokenizer::new(n)?,
+            ),
             TokenizationScheme::Word => {
-                compress_with_token::<Word, _>(input_file, w, encoding_scheme)
+                compress_with_token::<Word, _>(&data, &mut w, &encoding_scheme, WordTokenizer)
             }
-        }
+            TokenizationScheme::BytePair { vocabulary_size } => {
+                let merges = Merges::learn(&data[..], vocabulary_size)?;
+                merges.pack(&mut w)?;
+                compress_with_token::<Symbol, _>(
+                    &data,
+                    &mut w,
+                    &encoding_scheme,
+                    BytePairTokenizer::new(merges),
+                )
+            }
+            TokenizationScheme::Segment { rounds } => {
+                let tokenizer = SegmentTokenizer::learn(&data[..], &encoding_scheme, rounds)?;
+                compress_with_token::<Segment, _>(&data, &mut w, &encoding_scheme, tokenizer)
+            }
+            TokenizationScheme::RunLength => {
+                compress_with_token::<Run, _>(&data, &mut w, &encoding_scheme, RunTokenizer)
+            }
+            TokenizationScheme::Integer {
+                width,
+                endianness,
+                delta,
+                zigzag,
+            } => integers::pack(
+                &data,
+                &encoding_scheme,
+                IntegerFormat {
+                    width,
+                    endianness,
+                    delta,
+                    zigzag,
+                },
+                &mut w,
+            ),
+            TokenizationScheme::Bits { k } => compress_with_token::<Bits, _>(
+                &data,
+                &mut w,
+                &encoding_scheme,
+                BitsTokenizer::new(k)?,
+            ),
+            TokenizationScheme::Line { min_count } => {
+                let tokenizer = LineTokenizer::learn(&data[..], min_count)?;
+                compress_with_token::<Line, _>(&data, &mut w, &encoding_scheme, tokenizer)
+            }
+            TokenizationScheme::Rules { patterns } => compress_with_token::<Lexeme, _>(
+                &data,
+                &mut w,
+                &encoding_scheme,
+                RuleTokenizer::new(&patterns)?,
+            ),
+            TokenizationScheme::Custom { id, params } => {
+                registry::compress(&id, &params, &data, &encoding_scheme, &mut w)
+            }
+            TokenizationScheme::Lz77 => lz77::pack(&data, &encoding_scheme, &mut w),
+            TokenizationScheme::Csv { delta } => csv::pack(&data, &encoding_scheme, delta, &mut w),
+            TokenizationScheme::Json => json::pack(&data, &encoding_scheme, &mut w),
+            TokenizationScheme::Fasta { k } => fasta::pack(&data, &encoding_scheme, k, &mut w),
+            TokenizationScheme::Pnm { predictor } => {
+                pnm::pack(&data, &encoding_scheme, predictor, &mut w)
+            }
+            TokenizationScheme::Float { width, endianness } => {
+                floats::pack(&data, &encoding_scheme, width, endianness, &mut w)
+            }
+            TokenizationScheme::Strided { stride } => {
+                strided::pack(&data, &encoding_scheme, stride, &mut w)
+            }
+            TokenizationScheme::Wav { lpc_order, rice } => {
+                wav::pack(&data, &encoding_scheme, lpc_order, rice, &mut w)
+            }
+            TokenizationScheme::LogTemplate => log_templates::pack(&data, &encoding_scheme, &mut w),
+            TokenizationScheme::Lzw { max_bits } => lzw::pack(&data, max_bits, &mut w),
+        }?;
+        std::fs::write(output_file, w)?;
+        Ok(())
     }
 
     pub fn decompress(input_file: &Path, output_file: &Path) -> Result<()> {
         info!("Decompressing...");
-        let w = BufWriter::new(File::create(output_file)?);
         let mut r = BufReader::new(File::open(input_file)?);
-        match unpack_tokenization_scheme(&mut r)? {
-            TokenizationScheme::Byte => decompress_with_token::<Byte, _, _>(r, w),
-            TokenizationScheme::Grapheme => decompress_with_token::<Grapheme, _, _>(r, w),
-            TokenizationScheme::Word => decompress_with_token::<Word, _, _>(r, w),
+        if r.fill_buf()?.starts_with(&lzw::MAGIC) {
+            let mut data = Vec::new();
+            lzw::unpack_unix(r, &mut data)?;
+            std::fs::write(output_file, data)?;
+            return Ok(());
+        }
+        let mut header = [0u8; 5];
+        r.read_exact(&mut header)?;
+        if header[..4] != MAGIC {
+            return Err(anyhow!(
+                "Not a cshannon file, or written by cshannon 0.2.1 or older"
+            ));
         }
+        if header[4] != FORMAT_VERSION {
+            return Err(anyhow!("Unsupported format version {}", header[4]));
+        }
+        let tokenization_scheme = unpack_tokenization_scheme(&mut r)?;
+        let transform = unpack_transform(&mut r)?;
+        let charset = unpack_charset(&mut r)?;
+        let encoding_scheme = unpack_encoding_scheme(&mut r)?;
+        debug!("Data was compressed with {:?}", encoding_scheme);
+        let mut data = Vec::new();
+        match tokenization_scheme {
+            TokenizationScheme::Byte => {
+                decompress_with_token::<Byte, _, _>(r, &mut data, BytePacker)
+            }
+            TokenizationScheme::CodePoint => {
+                decompress_with_token::<CodePoint, _, _>(r, &mut data, CodePointPacker)
+            }
+            TokenizationScheme::Grapheme => {
+                decompress_with_token::<Grapheme, _, _>(r, &mut data, GraphemePacker::default())
+            }
+            TokenizationScheme::NGram { .. } => {
+                decompress_with_token::<NGram, _, _>(r, &mut data, NGramPacker)
+            }
+            TokenizationScheme::Word => {
+                decompress_with_token::<Word, _, _>(r, &mut data, WordPacker::default())
+            }
+            TokenizationScheme::BytePair { .. } => {
+                let merges = Merges::unpack(&mut r)?;
+                decompress_with_token::<Symbol, _, _>(r, &mut data, BytePairPacker::new(&merges))
+            }
+            TokenizationScheme::Segment { .. } => {
+                decompress_with_token::<Segment, _, _>(r, &mut data, SegmentPacker)
+            }
+            TokenizationScheme::RunLength => {
+                decompress_with_token::<Run, _, _>(r, &mut data, RunPacker)
+            }
+            TokenizationScheme::Integer {
+                width,
+                endianness,
+                delta,
+                zigzag,
+            } => integers::unpack(
+                r,
+                IntegerFormat {
+                    width,
+                    endianness,
+                    delta,
+                    zigzag,
+                },
+                &mut data,
+            ),
+            TokenizationScheme::Bits { .. } => {
+                decompress_with_token::<Bits, _, _>(r, &mut data, BitsPacker)
+            }
+            TokenizationScheme::Line { .. } => {
+                decompress_with_token::<Line, _, _>(r, &mut data, LinePacker::default())
+            }
+            TokenizationScheme::Rules { .. } => {
+                decompress_with_token::<Lexeme, _, _>(r, &mut data, LexemePacker)
+            }
+            TokenizationScheme::Custom { id, params } => {
+                registry::decompress(&id, &params, &mut r, &mut data)
+            }
+            TokenizationScheme::Lz77 => lz77::unpack(r, &mut data),
+            TokenizationScheme::Csv { delta } => csv::unpack(r, delta, &mut data),
+            TokenizationScheme::Json => json::unpack(r, &mut data),
+            TokenizationScheme::Fasta { .. } => fasta::unpack(r, &mut data),
+            TokenizationScheme::Pnm { predictor } => pnm::unpack(r, predictor, &mut data),
+            TokenizationScheme::Float { width, endianness } => {
+                floats::unpack(r, width, endianness, &mut data)
+            }
+            TokenizationScheme::Strided { .. } => strided::unpack(r, &mut data),
+            TokenizationScheme::Wav { lpc_order, rice } => {
+                wav::unpack(r, lpc_order, rice, &mut data)
+            }
+            TokenizationScheme::LogTemplate => log_templates::unpack(r, &mut data),
+            TokenizationScheme::Lzw { max_bits } => lzw::unpack(r, max_bits, true, &mut data),
+        }?;
+        std::fs::write(output_file, charset.encode(&transform.inverse(data)?)?)?;
+        Ok(())
     }
 
-    fn compress_with_token<T: Token, W: std::io::Write>(
-        input_file: &Path,
-        mut w: W,
-        encoding_scheme: EncodingScheme,
-    ) -> Result<()> {
+    pub fn unix_compress(input_file: &Path, output_file: &Path, max_bits: u8) -> Result<()> {
         info!("Compressing...");
-        let r = BufReader::new(File::open(input_file)?);
-        let tokens = T::Tokenizer::tokenize(r).unwrap().map(|r| r.unwrap());
-        let encoding = new_encoder(&&encoding_scheme, model::from(tokens))?;
+        let data = std::fs::read(input_file)?;
+        let mut w = Vec::new();
+        lzw::pack_unix(&data, max_bits, &mut w)?;
+        std::fs::write(output_file, w)?;
+        Ok(())
+    }
 
-        let r = BufReader::new(File::open(input_file)?);
-        let tokens = T::Tokenizer::tokenize(r).unwrap().map(|r| r.unwrap());
-        let code_text = encode(encoding.map(), tokens).map(|r| r.unwrap());
+    pub fn compress_with_token<T: Token, W: std::io::Write>(
+        data: &[u8],
+        mut w: W,
+        encoding_scheme: &EncodingScheme,
+        tokenizer: T::Tokenizer,
+    ) -> Result<()> {
+        let tokens: Vec<T> = tokenizer.tokenize(data)?.collect::<Result<_>>()?;
+        let encoding = new_encoder(encoding_scheme, model::from(tokens.iter().cloned()))?;
+        let code_text: Vec<&Letter> =
+            encode(encoding.map(), tokens.into_iter()).collect::<Result<_>>()?;
 
         encoding.pack(&mut w)?;
-        crate::code::pack(code_text, &mut w)?;
+        crate::code::pack(code_text.into_iter(), &mut w)?;
         Ok(())
     }
 
-    fn decompress_with_token<T: Token, R: std::io::Read, W: std::io::Write>(
+    pub fn decompress_with_token<T: Token, R: std::io::Read, W: std::io::Write>(
         mut r: R,
         mut w: W,
+        packer: T::Packer,
     ) -> Result<()> {
-        let encoding: Encoding<T> = Encoding::unpack(&mut r).unwrap();
+        let encoding: Encoding<T> = Encoding::unpack(&mut r)?;
         let map = encoding.reverse_map();
-        let coded_text = crate::code::parse(&encoding.alphabet(), r)?.map(|r| r.unwrap());
-        let decoded_text = decode(&map, coded_text).map(|r| r.unwrap());
-        T::Packer::pack(decoded_text, &mut w)?;
+        let coded_text: Vec<&Letter> =
+            crate::code::parse(encoding.alphabet(), r)?.collect::<Result<_>>()?;
+        let decoded_text: Vec<T> = decode(&map, coded_text.into_iter()).collect::<Result<_>>()?;
+        packer.pack(decoded_text.into_iter(), &mut w)?;
         Ok(())
     }
-
-    fn encode<'a, T, TS>(
-        encoding: &'a HashMap<T, Letter>,
-        input: TS,
-    ) -> impl Iterator<Item = Result<&'a Letter>>
-    where
-        T: Token,
-        TS: std::iter::Iterator<Item = T>,
-    {
-        input.map(move |t| match encoding.get(&t) {
-            Some(l) => Ok(l),
-            None => Err(anyhow!("Unknown token {}", t.to_string())),
-        })
-    }
-
-    fn decode<'a, T, CS: 'a>(
-        encoding: &'a HashMap<&'a Letter, &'a T>,
-        input: CS,
-    ) -> impl Iterator<Item = Result<T>> + 'a
-    where
-        T: Token,
-        CS: std::iter::Iterator<Item = &'a Letter>,
-    {
-        input.map(move |l| match encoding.get(l) {
-            Some(t) => Ok((*t).clone()),
-            None => Err(anyhow!("no encoding for letter {}", l)),
-        })
-    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::charsets::unpack_charset;
 
     // Per API guidelines, our public types should be `Send`.
     #[test]
@@ -333,4 +640,80 @@ mod tests {
         fn assert_sync<T: Sync>() {}
         assert_sync::<Args>();
     }
+
+    #[test]
+    fn failed_compression_leaves_no_output() {
+        let work_dir = tempfile::tempdir().unwrap();
+        let input_file = work_dir.path().join("input");
+        let output_file = work_dir.path().join("output");
+        std::fs::write(&input_file, "abracadabra").unwrap();
+        let args = Args {
+            command: Command::Compress(CompressArgs {
+                tokenization_scheme: TokenizationScheme::Bits { k: 99 },
+                encoding_scheme: EncodingScheme::Huffman,
+                transform: Transform::None,
+                charset: Charset::Utf8,
+            }),
+            input_file: &input_file,
+            output_file: &output_file,
+        };
+        assert!(run(args).is_err());
+        assert!(!output_file.exists());
+    }
+
+    #[test]
+    fn charset_only_applies_to_text() {
+        let work_dir = tempfile::tempdir().unwrap();
+        let input_file = work_dir.path().join("input");
+        std::fs::write(&input_file, b"caf\xe9 \xe0 la cr\xe8me").unwrap();
+        let compress = |tokenization_scheme, charset| {
+            let output_file = work_dir.path().join("output");
+            let args = Args {
+                command: Command::Compress(CompressArgs {
+                    tokenization_scheme,
+                    encoding_scheme: EncodingScheme::Huffman,
+                    transform: Transform::None,
+                    charset,
+                }),
+                input_file: &input_file,
+                output_file: &output_file,
+            };
+            run(args).unwrap();
+            std::fs::read(&output_file).unwrap()
+        };
+        // The charset marker follows the magic, the format version, and the
+        // tokenization and transform markers.
+        let charset = |output: Vec<u8>| unpack_charset(&output[7..]).unwrap();
+        assert_eq!(
+            charset(compress(TokenizationScheme::Grapheme, Charset::Latin1)),
+            Charset::Latin1
+        );
+        assert_eq!(
+            charset(compress(TokenizationScheme::Byte, Charset::Latin1)),
+            Charset::Utf8
+        );
+        assert_eq!(
+            charset(compress(TokenizationScheme::Byte, Charset::Auto)),
+            Charset::Utf8
+        );
+    }
+
+    #[test]
+    fn unversioned_format() {
+        let work_dir = tempfile::tempdir().unwrap();
+        let input_file = work_dir.path().join("input");
+        let output_file = work_dir.path().join("output");
+        // Byte tokenization and Huffman encoding, with an empty dictionary, in
+        // the format of cshannon 0.2.1.
+        let mut data = vec![1u8, 4u8];
+        data.extend_from_slice(&[0u8; 16]);
+        std::fs::write(&input_file, data).unwrap();
+        let args = Args {
+            command: Command::Decompress(DecompressArgs {}),
+            input_file: &input_file,
+            output_file: &output_file,
+        };
+        let err = run(args).unwrap_err();
+        assert!(err.to_string().contains("0.2.1"), "{}", err);
+    }
 }
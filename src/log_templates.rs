@@ -0,0 +1,533 @@
+// Copyright 2020 Prathmesh Prabhu
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! log_templates module implements a front end to the entropy coders for log
+//! text, that splits each line into a template and the values of its slots.
+//!
+//! Each line is split into words at spaces. Templates are mined as in
+//! [Drain]: a template is a list of words of a given length, some of which are
+//! slots that match any word. Words that contain digits are assumed to be
+//! variable. As in Drain, templates are grouped by their length and first word
+//! (or by their length alone if the first word is variable), and each line is
+//! only compared with the templates in its group. The line joins the most
+//! similar template (if at least half its words are equal to the constant
+//! words, or are variable words in slots), and the words in which they differ
+//! become slots. Otherwise the line starts a new template, with slots for the
+//! variable words. Once a group holds [`MAX_GROUP_SIZE`] templates, lines
+//! join the most similar template in the group regardless, so that the time
+//! to mine templates grows linearly with the number of lines.
+//!
+//! The compressed output consists of token streams that are each compressed
+//! with their own [`Encoding`](crate::encoding::Encoding):
+//!
+//! - shapes: one token per word of each template recording whether it is a
+//!   slot or a constant word, and one token for the end of each template.
+//! - words: the constant words of the templates.
+//! - templates: the template ID of each line.
+//! - one stream per slot of each template with the bytes of the words in that
+//!   slot, each followed by a space. Slot words are coded byte by byte because
+//!   many (e.g. timestamps) occur only once. Templates of fewer than
+//!   [`MIN_LINES`] lines share a single stream for their slots instead.
+//!
+//! If it is smaller, the words in all slots are coded as a single stream
+//! instead, e.g. for text that is mostly distinct lines.
+//!
+//! [Drain]: https://jiemingzhu.github.io/pub/pjhe_icws2017.pdf
+
+use crate::encoding::EncodingScheme;
+use crate::stream;
+use crate::tokens::bytes::Byte;
+use crate::tokens::integers::Integer;
+use crate::tokens::rules::Lexeme;
+use anyhow::{anyhow, Result};
+use std::collections::HashMap;
+use std::convert::TryFrom;
+
+/// The minimum fraction of words of a line that must match a template for
+/// the line to join it.
+const SIMILARITY: f64 = 0.5;
+/// The maximum number of templates with the same length and first word.
+pub const MAX_GROUP_SIZE: usize = 32;
+/// With [`SLOT_STREAMS`], templates of fewer lines share a single stream for
+/// the words in their slots, which is cheaper than an encoding for each of
+/// their slots.
+pub const MIN_LINES: usize = 64;
+
+// Markers for the layout of the slot streams.
+const SHARED_SLOTS: u8 = 0;
+const SLOT_STREAMS: u8 = 1;
+
+// Tokens in the template shapes stream: the words of a template, followed by
+// the end of the template.
+const SLOT: u8 = 0;
+const WORD: u8 = 1;
+const END: u8 = 2;
+
+/// A line template: constant words, and slots (`None`) that match any word.
+pub type Template = Vec<Option<Vec<u8>>>;
+
+fn words(line: &[u8]) -> Vec<&[u8]> {
+    line.split(|b| *b == b' ').collect()
+}
+
+// Words with digits (e.g. timestamps, IDs) are assumed to be variable.
+fn is_variable(word: &[u8]) -> bool {
+    word.iter().any(u8::is_ascii_digit)
+}
+
+// The number of words that match `template`: constant words that are equal,
+// and variable words in slots.
+fn similarity(template: &Template, words: &[&[u8]]) -> usize {
+    template
+        .iter()
+        .zip(words)
+        .filter(|(t, w)| match t {
+            Some(t) => t == *w,
+            None => is_variable(w),
+        })
+        .count()
+}
+
+// The group of the templates that a line can join: its number of words, and
+// its first word unless that is variable.
+fn group<'a>(words: &[&'a [u8]]) -> (usize, Option<&'a [u8]>) {
+    let first = words.first().copied().filter(|w| !is_variable(w));
+    (words.len(), first)
+}
+
+/// Mines [`Template`]s from lines of text.
+#[derive(Debug, Default)]
+pub struct Miner {
+    templates: Vec<Template>,
+    // Indices in `templates` of the templates in each group.
+    groups: HashMap<(usize, Option<Vec<u8>>), Vec<usize>>,
+}
+
+impl Miner {
+    /// Add a line (without its terminator) to the mined templates.
+    ///
+    /// Returns the index of the template for the line. Later lines only make
+    /// templates more general, so the template continues to match the line.
+    pub fn add(&mut self, line: &[u8]) -> usize {
+        let words = words(line);
+        let templates = &mut self.templates;
+        let (length, first) = group(&words);
+        let candidates = self
+            .groups
+            .entry((length, first.map(<[u8]>::to_vec)))
+            .or_default();
+        let best = candidates
+            .iter()
+            .map(|i| (similarity(&templates[*i], &words), *i))
+            .max_by_key(|(s, i)| (*s, std::cmp::Reverse(*i)));
+        if let Some((s, i)) = best {
+            if s as f64 >= SIMILARITY * words.len() as f64 || candidates.len() >= MAX_GROUP_SIZE {
+                for (t, w) in templates[i].iter_mut().zip(&words) {
+                    if t.as_deref() != Some(*w) {
+                        *t = None;
+                    }
+                }
+                return i;
+            }
+        }
+
+        let template = words
+            .iter()
+            .map(|w| {
+                if is_variable(w) {
+                    None
+                } else {
+                    Some(w.to_vec())
+                }
+            })
+            .collect();
+        candidates.push(templates.len());
+        templates.push(template);
+        templates.len() - 1
+    }
+
+    /// The mined templates, indexed as returned from [`Self::add()`].
+    pub fn templates(&self) -> &[Template] {
+        &self.templates
+    }
+}
+
+fn pack_templates<W: std::io::Write>(
+    templates: &[Template],
+    encoding_scheme: &EncodingScheme,
+    mut w: W,
+) -> Result<()> {
+    let mut shapes = Vec::new();
+    let mut words = Vec::new();
+    for t in templates {
+        for word in t {
+            match word {
+                None => shapes.push(Byte::from(SLOT)),
+                Some(word) => {
+                    shapes.push(Byte::from(WORD));
+                    words.push(Lexeme::from(word.clone()));
+                }
+            }
+        }
+        shapes.push(Byte::from(END));
+    }
+    stream::pack(&shapes, encoding_scheme, &mut w)?;
+    stream::pack(&words, encoding_scheme, &mut w)?;
+    Ok(())
+}
+
+fn unpack_templates<R: std::io::Read>(mut r: R) -> Result<Vec<Template>> {
+    let shapes: Vec<Byte> = stream::unpack(&mut r)?;
+    let words: Vec<Lexeme> = stream::unpack(&mut r)?;
+    let mut words = words.into_iter().map(Vec::from);
+    let mut templates = Vec::new();
+    let mut template = Vec::new();
+    for s in shapes {
+        match u8::from(s) {
+            SLOT => template.push(None),
+            WORD => match words.next() {
+                Some(word) => template.push(Some(word)),
+                None => return Err(anyhow!("Ran out of template words")),
+            },
+            END => templates.push(std::mem::take(&mut template)),
+            m => return Err(anyhow!("Unknown template shape {}", m)),
+        }
+    }
+    if !template.is_empty() {
+        return Err(anyhow!("Unterminated template"));
+    }
+    Ok(templates)
+}
+
+// The `min_lines` for [`slot_streams()`] with the given layout.
+fn min_lines(layout: u8) -> Result<usize> {
+    match layout {
+        SHARED_SLOTS => Ok(usize::MAX),
+        SLOT_STREAMS => Ok(MIN_LINES),
+        m => Err(anyhow!("Unknown slot layout {}", m)),
+    }
+}
+
+// The index of the stream for each slot of each template, and the number of
+// streams. Stream 0 is shared by the slots of templates of fewer than
+// `min_lines` lines.
+fn slot_streams(
+    templates: &[Template],
+    ids: &[usize],
+    min_lines: usize,
+) -> (Vec<Vec<usize>>, usize) {
+    let mut counts = vec![0; templates.len()];
+    for id in ids {
+        counts[*id] += 1;
+    }
+    let mut stream_count = 1;
+    let streams = templates
+        .iter()
+        .zip(counts)
+        .map(|(t, count)| {
+            t.iter()
+                .filter(|w| w.is_none())
+                .map(|_| {
+                    if count < min_lines {
+                        return 0;
+                    }
+                    stream_count += 1;
+                    stream_count - 1
+                })
+                .collect()
+        })
+        .collect();
+    (streams, stream_count)
+}
+
+/// Compress `data` with a log template front end.
+///
+/// Each token stream is compressed with an encoding generated with
+/// `encoding_scheme`. The output can be decompressed with [`unpack()`].
+pub fn pack<W: std::io::Write>(
+    data: &[u8],
+    encoding_scheme: &EncodingScheme,
+    mut w: W,
+) -> Result<()> {
+    let lines: Vec<&[u8]> = data.split_inclusive(|b| *b == b'\n').collect();
+    let ends_with_newline = data.last() == Some(&b'\n');
+    let mut miner = Miner::default();
+    let ids: Vec<usize> = lines
+        .iter()
+        .map(|l| miner.add(l.strip_suffix(b"\n").unwrap_or(l)))
+        .collect();
+
+    let templates = miner.templates();
+    let line_words: Vec<Vec<&[u8]>> = lines
+        .iter()
+        .map(|l| words(l.strip_suffix(b"\n").unwrap_or(l)))
+        .collect();
+    let mut slots = Vec::new();
+    for layout in [SHARED_SLOTS, SLOT_STREAMS] {
+        let (slot_streams, stream_count) = slot_streams(templates, &ids, min_lines(layout)?);
+        let mut streams: Vec<Vec<Byte>> = vec![Vec::new(); stream_count];
+        for (words, id) in line_words.iter().zip(&ids) {
+            let values = templates[*id]
+                .iter()
+                .zip(words)
+                .filter(|(t, _)| t.is_none())
+                .map(|(_, w)| w);
+            for (i, w) in slot_streams[*id].iter().zip(values) {
+                streams[*i].extend(w.iter().map(|b| Byte::from(*b)));
+                streams[*i].push(Byte::from(b' '));
+            }
+        }
+        let mut buf = vec![layout];
+        for s in &streams {
+            stream::pack(s, encoding_scheme, &mut buf)?;
+        }
+        slots.push(buf);
+    }
+    let ids: Vec<Integer> = ids
+        .into_iter()
+        .map(|id| Integer::Value {
+            value: id as u64,
+            width: 8,
+        })
+        .collect();
+
+    w.write_all(&[ends_with_newline as u8])?;
+    pack_templates(templates, encoding_scheme, &mut w)?;
+    stream::pack(&ids, encoding_scheme, &mut w)?;
+    w.write_all(&slots.into_iter().min_by_key(|s| s.len()).unwrap())?;
+    w.flush()?;
+    Ok(())
+}
+
+/// Decompress data compressed with [`pack()`].
+pub fn unpack<R: std::io::Read, W: std::io::Write>(mut r: R, mut w: W) -> Result<()> {
+    let mut ends_with_newline = [0u8];
+    r.read_exact(&mut ends_with_newline)?;
+    let templates = unpack_templates(&mut r)?;
+    let ids: Vec<Integer> = stream::unpack(&mut r)?;
+    let mut line_templates = Vec::with_capacity(ids.len());
+    for id in ids {
+        let id = match id {
+            Integer::Value { value, .. } => usize::try_from(value)?,
+            Integer::Tail(_) => return Err(anyhow!("Unexpected tail in template IDs")),
+        };
+        if id >= templates.len() {
+            return Err(anyhow!("Unknown template {}", id));
+        }
+        line_templates.push(id);
+    }
+    let mut layout = [0u8];
+    r.read_exact(&mut layout)?;
+    let (slot_streams, stream_count) =
+        slot_streams(&templates, &line_templates, min_lines(layout[0])?);
+    let mut streams = Vec::with_capacity(stream_count);
+    for _ in 0..stream_count {
+        let values: Vec<Byte> = stream::unpack(&mut r)?;
+        streams.push(values.into_iter().map(u8::from));
+    }
+
+    let mut out = Vec::new();
+    for id in line_templates {
+        let template = &templates[id];
+        let mut values = slot_streams[id].iter();
+        for (i, word) in template.iter().enumerate() {
+            if i > 0 {
+                out.push(b' ');
+            }
+            match word {
+                Some(word) => out.extend_from_slice(word),
+                None => {
+                    let slot = values
+                        .next()
+                        .ok_or_else(|| anyhow!("Ran out of slots for template {}", id))?;
+                    loop {
+                        match streams[*slot].next() {
+                            Some(b' ') => break,
+                            Some(b) => out.push(b),
+                            None => return Err(anyhow!("Ran out of values for template {}", id)),
+                        }
+                    }
+                }
+            }
+        }
+        out.push(b'\n');
+    }
+    if ends_with_newline[0] == 0 {
+        out.pop();
+    }
+    w.write_all(&out)?;
+    w.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    const TEXT: &str = "\
+10:00:01 INFO connected to db-1 in 12 ms
+10:00:02 INFO connected to db-2 in 7 ms
+10:00:02 WARN retrying request 17 after timeout
+10:00:03 INFO connected to cache in 3 ms
+10:00:04 WARN retrying request 18 after reset
+";
+
+    fn roundtrip(text: &[u8]) -> Vec<u8> {
+        let mut buf = Vec::new();
+        pack(text, &EncodingScheme::Huffman, &mut buf).unwrap();
+        let mut got = Vec::new();
+        unpack(Cursor::new(buf), &mut got).unwrap();
+        got
+    }
+
+    fn template(s: &str) -> Template {
+        s.split(' ')
+            .map(|w| match w {
+                "*" => None,
+                w => Some(w.as_bytes().to_vec()),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn roundtrip_lines() {
+        assert_eq!(roundtrip(TEXT.as_bytes()), TEXT.as_bytes());
+    }
+
+    #[test]
+    fn roundtrip_edge_cases() {
+        for text in [
+            &b""[..],
+            b"\n",
+            b"no newline at end",
+            b"  double  spaces \n\n\xff\xfe\n",
+            b"a\r\nb\r\n",
+        ] {
+            assert_eq!(roundtrip(text), text);
+        }
+    }
+
+    #[test]
+    fn mine_templates() {
+        let mut miner = Miner::default();
+        let ids: Vec<usize> = TEXT.lines().map(|l| miner.add(l.as_bytes())).collect();
+        assert_eq!(ids, vec![0, 0, 1, 0, 1]);
+        assert_eq!(
+            miner.templates(),
+            &[
+                template("* INFO connected to * in * ms"),
+                template("* WARN retrying request * after *"),
+            ]
+        );
+    }
+
+    // Lines of words without digits, most of which match no other line.
+    fn distinct_lines(count: usize) -> Vec<u8> {
+        const WORDS: [&str; 16] = [
+            "alpha", "beta", "gamma", "delta", "eps", "zeta", "eta", "theta", "iota", "kappa",
+            "lambda", "mu", "nu", "xi", "pi", "rho",
+        ];
+        // A linear congruential generator, so that the text is the same on
+        // every run.
+        let mut seed = 1u64;
+        let mut text = Vec::new();
+        for _ in 0..count {
+            let words: Vec<&str> = (0..6)
+                .map(|_| {
+                    seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1);
+                    WORDS[(seed >> 33) as usize % WORDS.len()]
+                })
+                .collect();
+            text.extend_from_slice(words.join(" ").as_bytes());
+            text.push(b'\n');
+        }
+        text
+    }
+
+    #[test]
+    fn mining_is_linear() {
+        let text = distinct_lines(20000);
+        let start = std::time::Instant::now();
+        let mut miner = Miner::default();
+        for l in text.split(|b| *b == b'\n') {
+            miner.add(l);
+        }
+        // Each line is compared with at most MAX_GROUP_SIZE templates, one
+        // group for each first word (and one for the empty last line).
+        assert!(miner.templates().len() <= 17 * MAX_GROUP_SIZE);
+        assert!(start.elapsed() < std::time::Duration::from_secs(10));
+        assert_eq!(roundtrip(&text), text);
+    }
+
+    // A server log with timestamps, request IDs and latencies.
+    fn server_log() -> Vec<u8> {
+        const PATHS: [&str; 4] = ["/", "/login", "/api/items", "/static/app.js"];
+        let mut seed = 1u64;
+        let mut random = |n: u64| {
+            seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1);
+            (seed >> 33) % n
+        };
+        let mut text = String::new();
+        for i in 0..5000 {
+            let time = format!(
+                "12:{:02}:{:02}.{:03}",
+                i / 600 % 60,
+                i / 10 % 60,
+                random(1000)
+            );
+            match random(4) {
+                0 => text.push_str(&format!(
+                    "{} WARN retrying request {} after timeout\n",
+                    time,
+                    random(100000)
+                )),
+                _ => text.push_str(&format!(
+                    "{} INFO GET {} 200 in {} ms from 10.0.{}.{}\n",
+                    time,
+                    PATHS[random(4) as usize],
+                    random(300),
+                    random(4),
+                    random(256)
+                )),
+            }
+        }
+        text.into_bytes()
+    }
+
+    #[test]
+    fn smaller_than_bytes() {
+        for text in [server_log(), distinct_lines(5000)] {
+            let bytes: Vec<Byte> = text.iter().map(|b| Byte::from(*b)).collect();
+            let mut by_byte = Vec::new();
+            stream::pack(&bytes, &EncodingScheme::Huffman, &mut by_byte).unwrap();
+            let mut buf = Vec::new();
+            pack(&text, &EncodingScheme::Huffman, &mut buf).unwrap();
+            assert!(
+                buf.len() < by_byte.len(),
+                "{} bytes, {} bytes by byte",
+                buf.len(),
+                by_byte.len()
+            );
+        }
+    }
+
+    #[test]
+    fn templates_pack_unpack() {
+        let templates = vec![template("a * b"), template("*"), Vec::new()];
+        let mut buf = Vec::new();
+        pack_templates(&templates, &EncodingScheme::Huffman, &mut buf).unwrap();
+        assert_eq!(unpack_templates(&buf[..]).unwrap(), templates);
+    }
+}
@@ -0,0 +1,419 @@
+// Copyright 2020 Prathmesh Prabhu
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! lz77 module implements an [LZ77] (LZSS-style) front end to the entropy
+//! coders.
+//!
+//! The source text is parsed into a sequence of [`Op`]s: literal bytes, and
+//! matches that copy `length` bytes starting `distance` bytes back in the
+//! already decoded text. Matches are found in a sliding window using hash
+//! chains, with one step of lazy evaluation (as in DEFLATE).
+//!
+//! The parsed sequence is split into three token streams, each compressed with
+//! its own [`Encoding`](crate::encoding::Encoding):
+//!
+//! - literals: the literal bytes.
+//! - lengths: one token per [`Op`]. `0` for a literal, else the bucket of the
+//!   match length.
+//! - distances: the bucket of each match distance.
+//!
+//! As in DEFLATE, lengths and distances are coded as a bucket (which is
+//! entropy coded) followed by raw extra bits that locate the value within the
+//! bucket. Buckets keep the dictionaries of the length and distance encodings
+//! small. The extra bits are collected in a fourth, raw, stream.
+//!
+//! [LZ77]: https://en.wikipedia.org/wiki/LZ77_and_LZ78
+
+use crate::code::Letter;
+use crate::encoding::EncodingScheme;
+use crate::stream;
+use crate::tokens::bytes::Byte;
+use crate::util::{pack_u64, unpack_bytes};
+use anyhow::{anyhow, Result};
+
+/// The maximum distance of a match.
+const WINDOW_SIZE: usize = 1 << 15;
+/// Shorter matches are not worth replacing with a match.
+const MIN_MATCH: usize = 3;
+const MAX_MATCH: usize = 258;
+// The maximum number of earlier positions checked for a match.
+const MAX_CHAIN: usize = 128;
+const HASH_BITS: usize = 15;
+const NONE: usize = usize::MAX;
+
+/// A single step in the LZ77 parse of some text.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum Op {
+    /// A single byte copied verbatim.
+    Literal(u8),
+    /// Copy `length` bytes, starting `distance` bytes before the end of the
+    /// text decoded so far. The copied range may overlap the bytes being
+    /// written.
+    Match { length: usize, distance: usize },
+}
+
+/// Parse `data` into a sequence of [`Op`]s.
+pub fn parse(data: &[u8]) -> Vec<Op> {
+    let mut finder = MatchFinder::new(data);
+    let mut ops = Vec::new();
+    let mut i = 0;
+    while i < data.len() {
+        let found = finder.find(i);
+        finder.insert(i);
+        match found {
+            Some((length, distance)) => {
+                // Prefer a literal if a longer match starts at the next byte.
+                if let Some((next, _)) = finder.find(i + 1) {
+                    if next > length {
+                        ops.push(Op::Literal(data[i]));
+                        i += 1;
+                        continue;
+                    }
+                }
+                for j in i + 1..i + length {
+                    finder.insert(j);
+                }
+                ops.push(Op::Match { length, distance });
+                i += length;
+            }
+            None => {
+                ops.push(Op::Literal(data[i]));
+                i += 1;
+            }
+        }
+    }
+    ops
+}
+
+/// Expand a sequence of [`Op`]s back to text.
+///
+/// Returns an error if a match refers to data before the start of the text.
+pub fn expand<I: Iterator<Item = Op>>(ops: I) -> Result<Vec<u8>> {
+    let mut out = Vec::new();
+    for op in ops {
+        match op {
+            Op::Literal(b) => out.push(b),
+            Op::Match { length, distance } => {
+                if distance == 0 || distance > out.len() {
+                    return Err(anyhow!(
+                        "Match distance {} is out of bounds at {}",
+                        distance,
+                        out.len()
+                    ));
+                }
+                let start = out.len() - distance;
+                for j in start..start + length {
+                    out.push(out[j]);
+                }
+            }
+        }
+    }
+    Ok(out)
+}
+
+// Finds earlier occurrences of the bytes at a position using hash chains.
+//
+// `head` holds the latest position with a given hash of MIN_MATCH bytes, and
+// `prev` links each position to the previous position with the same hash.
+struct MatchFinder<'a> {
+    data: &'a [u8],
+    head: Vec<usize>,
+    prev: Vec<usize>,
+}
+
+impl<'a> MatchFinder<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self {
+            data,
+            head: vec![NONE; 1 << HASH_BITS],
+            prev: vec![NONE; data.len()],
+        }
+    }
+
+    fn hash(&self, i: usize) -> usize {
+        let d = &self.data[i..i + MIN_MATCH];
+        ((usize::from(d[0]) << 10) ^ (usize::from(d[1]) << 5) ^ usize::from(d[2]))
+            & ((1 << HASH_BITS) - 1)
+    }
+
+    fn insert(&mut self, i: usize) {
+        if i + MIN_MATCH > self.data.len() {
+            return;
+        }
+        let h = self.hash(i);
+        self.prev[i] = self.head[h];
+        self.head[h] = i;
+    }
+
+    // Returns the (length, distance) of the longest match for the bytes at
+    // `i`, if any.
+    fn find(&self, i: usize) -> Option<(usize, usize)> {
+        if i + MIN_MATCH > self.data.len() {
+            return None;
+        }
+        let max_len = std::cmp::min(MAX_MATCH, self.data.len() - i);
+        let mut best: Option<(usize, usize)> = None;
+        let mut j = self.head[self.hash(i)];
+        for _ in 0..MAX_CHAIN {
+            if j == NONE || i - j > WINDOW_SIZE {
+                break;
+            }
+            let len = self.data[j..]
+                .iter()
+                .zip(self.data[i..i + max_len].iter())
+                .take_while(|(a, b)| a == b)
+                .count();
+            if len >= MIN_MATCH && best.is_none_or(|(l, _)| len > l) {
+                best = Some((len, i - j));
+                if len == max_len {
+                    break;
+                }
+            }
+            j = self.prev[j];
+        }
+        best
+    }
+}
+
+// Split `v` into a bucket and the extra bits that locate `v` in the bucket.
+//
+// Values 0..4 have buckets of their own. Beyond that, each power of 2 is split
+// into two buckets: bucket `2k` and `2k + 1` cover the lower and upper halves of
+// `[2^k, 2^(k+1))`, with `k - 1` extra bits.
+pub fn bucket(v: usize) -> (u8, u64, usize) {
+    if v < 4 {
+        return (v as u8, 0, 0);
+    }
+    let k = (usize::BITS - 1 - v.leading_zeros()) as usize;
+    let code = 2 * k + ((v >> (k - 1)) & 1);
+    (code as u8, (k - 1) as u64, v & ((1 << (k - 1)) - 1))
+}
+
+// The smallest value in the given bucket, and its number of extra bits.
+//
+// Returns an error if the bucket holds values larger than `max_value`.
+pub fn bucket_base(code: u8, max_value: usize) -> Result<(usize, u64)> {
+    if code > bucket(max_value).0 {
+        return Err(anyhow!("Invalid bucket {}", code));
+    }
+    let code = usize::from(code);
+    if code < 4 {
+        return Ok((code, 0));
+    }
+    let k = code / 2;
+    Ok(((2 | (code & 1)) << (k - 1), (k - 1) as u64))
+}
+
+// The lowest `bit_count` bits of `value`, if any.
+fn extra_bits(value: usize, bit_count: u64) -> Option<Letter> {
+    if bit_count == 0 {
+        return None;
+    }
+    let data = ((value as u32) << (32 - bit_count)).to_be_bytes();
+    Some(Letter::new(&data, bit_count))
+}
+
+// Reads back the extra bits written with [`extra_bits()`].
+struct ExtraBits {
+    data: Vec<u8>,
+    // Offset of the next bit to read.
+    offset: usize,
+}
+
+impl ExtraBits {
+    fn read(&mut self, bit_count: u64) -> Result<usize> {
+        let mut v = 0;
+        for _ in 0..bit_count {
+            let b = match self.data.get(self.offset / 8) {
+                Some(b) => b,
+                None => return Err(anyhow!("Ran out of extra bits")),
+            };
+            v = (v << 1) | usize::from((b >> (7 - self.offset % 8)) & 1);
+            self.offset += 1;
+        }
+        Ok(v)
+    }
+}
+
+/// Compress `data` with an LZ77 front end.
+///
+/// Each token stream is compressed with an encoding generated with
+/// `encoding_scheme`. The output can be decompressed with [`unpack()`].
+pub fn pack<W: std::io::Write>(
+    data: &[u8],
+    encoding_scheme: &EncodingScheme,
+    mut w: W,
+) -> Result<()> {
+    let mut literals = Vec::new();
+    let mut lengths = Vec::new();
+    let mut distances = Vec::new();
+    let mut extras = Vec::new();
+    for op in parse(data) {
+        match op {
+            Op::Literal(b) => {
+                lengths.push(Byte::from(0));
+                literals.push(Byte::from(b));
+            }
+            Op::Match { length, distance } => {
+                let (code, bit_count, extra) = bucket(length - MIN_MATCH);
+                lengths.push(Byte::from(code + 1));
+                extras.extend(extra_bits(extra, bit_count));
+                let (code, bit_count, extra) = bucket(distance - 1);
+                distances.push(Byte::from(code));
+                extras.extend(extra_bits(extra, bit_count));
+            }
+        }
+    }
+
+    stream::pack(&literals, encoding_scheme, &mut w)?;
+    stream::pack(&lengths, encoding_scheme, &mut w)?;
+    stream::pack(&distances, encoding_scheme, &mut w)?;
+    let mut extra_text = Vec::new();
+    crate::code::pack(extras.iter(), &mut extra_text)?;
+    w.write_all(&pack_u64(extra_text.len() as u64))?;
+    w.write_all(&extra_text)?;
+    w.flush()?;
+    Ok(())
+}
+
+/// Decompress data compressed with [`pack()`].
+pub fn unpack<R: std::io::Read, W: std::io::Write>(mut r: R, mut w: W) -> Result<()> {
+    let literals: Vec<Byte> = stream::unpack(&mut r)?;
+    let lengths: Vec<Byte> = stream::unpack(&mut r)?;
+    let distances: Vec<Byte> = stream::unpack(&mut r)?;
+    let mut extras = ExtraBits {
+        data: unpack_bytes(&mut r)?,
+        offset: 0,
+    };
+
+    let mut literals = literals.into_iter();
+    let mut distances = distances.into_iter();
+    let mut ops = Vec::with_capacity(lengths.len());
+    for l in lengths {
+        let op = match u8::from(l) {
+            0 => match literals.next() {
+                Some(b) => Op::Literal(u8::from(b)),
+                None => return Err(anyhow!("Ran out of literals")),
+            },
+            code => {
+                let (base, bit_count) = bucket_base(code - 1, MAX_MATCH - MIN_MATCH)?;
+                let length = base + extras.read(bit_count)? + MIN_MATCH;
+                let code = match distances.next() {
+                    Some(d) => u8::from(d),
+                    None => return Err(anyhow!("Ran out of distances")),
+                };
+                let (base, bit_count) = bucket_base(code, WINDOW_SIZE - 1)?;
+                let distance = base + extras.read(bit_count)? + 1;
+                Op::Match { length, distance }
+            }
+        };
+        ops.push(op);
+    }
+    w.write_all(&expand(ops.into_iter())?)?;
+    w.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    const TEXT: &str = "
+Ah! well a-day! what evil looks
+Had I from old and young!
+Instead of the cross, the Albatross
+About my neck was hung.
+";
+
+    #[test]
+    fn roundtrip() {
+        let mut buf = Vec::new();
+        pack(TEXT.as_bytes(), &EncodingScheme::Huffman, &mut buf).unwrap();
+        let mut got = Vec::new();
+        unpack(Cursor::new(buf), &mut got).unwrap();
+        assert_eq!(std::str::from_utf8(&got).unwrap(), TEXT);
+    }
+
+    #[test]
+    fn empty() {
+        let mut buf = Vec::new();
+        pack(&[], &EncodingScheme::Huffman, &mut buf).unwrap();
+        let mut got = Vec::new();
+        unpack(Cursor::new(buf), &mut got).unwrap();
+        assert!(got.is_empty());
+    }
+
+    #[test]
+    fn parse_matches() {
+        assert_eq!(
+            parse(b"abcabcabcx"),
+            vec![
+                Op::Literal(b'a'),
+                Op::Literal(b'b'),
+                Op::Literal(b'c'),
+                // Overlapping match.
+                Op::Match {
+                    length: 6,
+                    distance: 3
+                },
+                Op::Literal(b'x'),
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_prefers_longer_match() {
+        // At "bcd..." a match of length 3 ("bcd") is available, but a match of
+        // length 4 ("cdef") starts at the next byte.
+        let data = b"bcdxcdefybcdef";
+        let ops = parse(data);
+        assert_eq!(
+            ops[ops.len() - 2..],
+            [
+                Op::Literal(b'b'),
+                Op::Match {
+                    length: 4,
+                    distance: 6
+                }
+            ]
+        );
+        assert_eq!(expand(ops.into_iter()).unwrap(), data.to_vec());
+    }
+
+    #[test]
+    fn expand_rejects_bad_distance() {
+        let ops = vec![
+            Op::Literal(b'a'),
+            Op::Match {
+                length: 3,
+                distance: 2,
+            },
+        ];
+        assert!(expand(ops.into_iter()).is_err());
+    }
+
+    #[test]
+    fn buckets() {
+        for v in 0..(1 << 16) {
+            let (code, bit_count, extra) = bucket(v);
+            assert_eq!(bucket_base(code, v).unwrap(), (v - extra, bit_count));
+            assert!(extra < 1 << bit_count);
+        }
+        assert_eq!(bucket(MAX_MATCH - MIN_MATCH).0, 15);
+        assert_eq!(bucket(WINDOW_SIZE - 1).0, 29);
+        assert!(bucket_base(16, MAX_MATCH - MIN_MATCH).is_err());
+        assert!(bucket_base(255, WINDOW_SIZE - 1).is_err());
+    }
+}
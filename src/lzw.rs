@@ -0,0 +1,452 @@
+// Copyright 2020 Prathmesh Prabhu
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! lzw module implements [LZW] compression, as used by the Unix `compress(1)`
+//! utility.
+//!
+//! Unlike the entropy coders in this library, LZW needs no model of the source
+//! text: a dictionary of strings is grown while the text is read, and each
+//! code refers to the longest dictionary entry matching the upcoming text.
+//! Codes start out 9 bits wide and widen by a bit each time the dictionary
+//! outgrows the current width, up to `max_bits` (at most 16).
+//!
+//! The code stream layout is compatible with `compress(1)` in "block mode":
+//!
+//! - Codes are packed least significant bit first.
+//! - Code 256 (CLEAR) resets the dictionary. It is emitted once the dictionary
+//!   is full and the compression ratio starts dropping.
+//! - Codes are written in groups of `n_bits` bytes (i.e. 8 codes of `n_bits`
+//!   each). Whenever the code width changes, the rest of the current group is
+//!   padded out.
+//!
+//! [`pack_unix()`] and [`unpack_unix()`] add the `.Z` file header on top of
+//! the bare code stream.
+//!
+//! [LZW]: https://en.wikipedia.org/wiki/Lempel%E2%80%93Ziv%E2%80%93Welch
+
+use anyhow::{anyhow, Result};
+use std::collections::HashMap;
+
+/// The magic bytes at the start of a `.Z` file.
+pub const MAGIC: [u8; 2] = [0x1f, 0x9d];
+
+const MAX_BITS_MASK: u8 = 0x1f;
+const BLOCK_MODE_MASK: u8 = 0x80;
+
+const INIT_BITS: u32 = 9;
+/// The largest supported code width.
+pub const MAX_BITS: u8 = 16;
+const CLEAR: u32 = 256;
+// Number of input bytes between checks of the compression ratio, once the
+// dictionary is full.
+const CHECK_GAP: u64 = 10000;
+
+// Returns an error unless `max_bits` is a supported code width.
+fn check_max_bits(max_bits: u8) -> Result<()> {
+    if (INIT_BITS as u8..=MAX_BITS).contains(&max_bits) {
+        Ok(())
+    } else {
+        Err(anyhow!(
+            "Maximum code width must be between {} and {} bits, got {}",
+            INIT_BITS,
+            MAX_BITS,
+            max_bits
+        ))
+    }
+}
+
+// The width of codes and the largest code that fits in it.
+//
+// The last width is allowed to use all codes up to `1 << max_bits`.
+//
+// For compatibility with `compress(1)`, this rule does not apply to the
+// initial width. With `max_bits` of 9, codes are widened to 10 bits once the
+// dictionary fills up.
+struct Width {
+    n_bits: u32,
+    max_bits: u32,
+    max_code: u32,
+}
+
+impl Width {
+    fn new(max_bits: u8) -> Self {
+        Self {
+            n_bits: INIT_BITS,
+            max_bits: u32::from(max_bits),
+            max_code: (1 << INIT_BITS) - 1,
+        }
+    }
+
+    fn reset(&mut self) {
+        *self = Self::new(self.max_bits as u8);
+    }
+
+    fn set(&mut self, n_bits: u32) {
+        self.n_bits = n_bits;
+        self.max_code = if n_bits == self.max_bits {
+            1 << n_bits
+        } else {
+            (1 << n_bits) - 1
+        };
+    }
+}
+
+// Writes codes packed least significant bit first, in groups of `n_bits`
+// bytes.
+#[derive(Default)]
+struct CodeWriter {
+    data: Vec<u8>,
+    bit_count: usize,
+    // Offset (in bits) of the first group written with the current width.
+    group_start: usize,
+}
+
+impl CodeWriter {
+    fn write(&mut self, code: u32, n_bits: u32) {
+        for i in 0..n_bits {
+            if self.bit_count.is_multiple_of(8) {
+                self.data.push(0);
+            }
+            if (code >> i) & 1 == 1 {
+                *self.data.last_mut().unwrap() |= 1 << (self.bit_count % 8);
+            }
+            self.bit_count += 1;
+        }
+    }
+
+    // Pad out the current group of `n_bits` codes.
+    fn align(&mut self, n_bits: u32) {
+        let group = 8 * n_bits as usize;
+        let used = (self.bit_count - self.group_start) % group;
+        if used != 0 {
+            self.bit_count += group - used;
+            self.data.resize(self.bit_count / 8, 0);
+        }
+        self.group_start = self.bit_count;
+    }
+}
+
+// Reads codes written by a [`CodeWriter`].
+struct CodeReader<'a> {
+    data: &'a [u8],
+    bit_count: usize,
+    group_start: usize,
+}
+
+impl<'a> CodeReader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self {
+            data,
+            bit_count: 0,
+            group_start: 0,
+        }
+    }
+
+    // Returns None once fewer than `n_bits` bits are left.
+    fn read(&mut self, n_bits: u32) -> Option<u32> {
+        if self.bit_count + n_bits as usize > 8 * self.data.len() {
+            return None;
+        }
+        let mut code = 0;
+        for i in 0..n_bits {
+            let b = self.data[self.bit_count / 8] >> (self.bit_count % 8);
+            code |= u32::from(b & 1) << i;
+            self.bit_count += 1;
+        }
+        Some(code)
+    }
+
+    // Skip the rest of the current group of `n_bits` codes.
+    fn align(&mut self, n_bits: u32) {
+        let group = 8 * n_bits as usize;
+        let used = (self.bit_count - self.group_start) % group;
+        if used != 0 {
+            self.bit_count += group - used;
+        }
+        self.group_start = self.bit_count;
+    }
+}
+
+/// Compress `data` into a block mode LZW code stream, with codes of up to
+/// `max_bits` bits.
+pub fn pack<W: std::io::Write>(data: &[u8], max_bits: u8, mut w: W) -> Result<()> {
+    check_max_bits(max_bits)?;
+    let max_max_code = 1u32 << max_bits;
+    let mut out = CodeWriter::default();
+    let mut width = Width::new(max_bits);
+    let mut dictionary: HashMap<(u32, u8), u32> = HashMap::new();
+    let mut next_code = CLEAR + 1;
+    // Tracks the compression ratio (scaled by 256) once the dictionary fills.
+    let mut checkpoint = CHECK_GAP;
+    let mut ratio = 0;
+
+    // Write `code`, then widen the codes (or reset the width after a CLEAR)
+    // as needed for the next code.
+    let output = |out: &mut CodeWriter, width: &mut Width, code, next_code, clear| {
+        out.write(code, width.n_bits);
+        if clear {
+            out.align(width.n_bits);
+            width.reset();
+        } else if next_code > width.max_code {
+            out.align(width.n_bits);
+            width.set(width.n_bits + 1);
+        }
+    };
+
+    let mut bytes = data.iter();
+    let mut prefix = match bytes.next() {
+        Some(b) => u32::from(*b),
+        None => return Ok(()),
+    };
+    for (i, &b) in bytes.enumerate() {
+        if let Some(&code) = dictionary.get(&(prefix, b)) {
+            prefix = code;
+            continue;
+        }
+        output(&mut out, &mut width, prefix, next_code, false);
+        if next_code < max_max_code {
+            dictionary.insert((prefix, b), next_code);
+            next_code += 1;
+        } else if i as u64 + 1 >= checkpoint {
+            let read = i as u64 + 1;
+            checkpoint = read + CHECK_GAP;
+            let current = (read << 8) / std::cmp::max(out.data.len() as u64, 1);
+            if current > ratio {
+                ratio = current;
+            } else {
+                ratio = 0;
+                dictionary.clear();
+                next_code = CLEAR + 1;
+                output(&mut out, &mut width, CLEAR, next_code, true);
+            }
+        }
+        prefix = u32::from(b);
+    }
+    output(&mut out, &mut width, prefix, next_code, false);
+
+    w.write_all(&out.data)?;
+    w.flush()?;
+    Ok(())
+}
+
+/// Decompress an LZW code stream written with [`pack()`] (or by
+/// `compress(1)`).
+///
+/// CLEAR codes are only recognized in `block_mode`.
+pub fn unpack<R: std::io::Read, W: std::io::Write>(
+    mut r: R,
+    max_bits: u8,
+    block_mode: bool,
+    mut w: W,
+) -> Result<()> {
+    check_max_bits(max_bits)?;
+    let mut data = Vec::new();
+    r.read_to_end(&mut data)?;
+    let first_code = if block_mode { CLEAR + 1 } else { CLEAR };
+    let max_max_code = 1u32 << max_bits;
+
+    let mut codes = CodeReader::new(&data);
+    let mut width = Width::new(max_bits);
+    // Each entry beyond the single bytes extends an earlier entry by a byte.
+    let mut prefixes: Vec<u32> = Vec::new();
+    let mut suffixes: Vec<u8> = Vec::new();
+    let mut next_code = first_code;
+    let mut previous: Option<u32> = None;
+    let mut out = Vec::new();
+    let mut entry = Vec::new();
+    loop {
+        if next_code > width.max_code {
+            codes.align(width.n_bits);
+            width.set(width.n_bits + 1);
+        }
+        let code = match codes.read(width.n_bits) {
+            Some(c) => c,
+            None => break,
+        };
+        if block_mode && code == CLEAR {
+            codes.align(width.n_bits);
+            width.reset();
+            prefixes.clear();
+            suffixes.clear();
+            next_code = first_code;
+            previous = None;
+            continue;
+        }
+
+        let previous_code = match previous {
+            Some(p) => p,
+            None => {
+                if code >= CLEAR {
+                    return Err(anyhow!("Invalid first code {}", code));
+                }
+                out.push(code as u8);
+                previous = Some(code);
+                continue;
+            }
+        };
+        // The code being defined by this step may be used right away (the
+        // "KwKwK" case). It then stands for the previous entry, extended by
+        // its own first byte.
+        let known = if code < next_code {
+            code
+        } else if code == next_code {
+            previous_code
+        } else {
+            return Err(anyhow!("Invalid code {} (next code {})", code, next_code));
+        };
+        entry.clear();
+        let mut c = known;
+        while c >= first_code {
+            let i = (c - first_code) as usize;
+            entry.push(suffixes[i]);
+            c = prefixes[i];
+        }
+        entry.push(c as u8);
+        entry.reverse();
+        let first_byte = entry[0];
+        if code == next_code {
+            entry.push(first_byte);
+        }
+        out.extend_from_slice(&entry);
+
+        if next_code < max_max_code {
+            prefixes.push(previous_code);
+            suffixes.push(first_byte);
+            next_code += 1;
+        }
+        previous = Some(code);
+    }
+
+    w.write_all(&out)?;
+    w.flush()?;
+    Ok(())
+}
+
+/// Compress `data` into the `.Z` format of `compress(1)`.
+pub fn pack_unix<W: std::io::Write>(data: &[u8], max_bits: u8, mut w: W) -> Result<()> {
+    check_max_bits(max_bits)?;
+    w.write_all(&MAGIC)?;
+    w.write_all(&[max_bits | BLOCK_MODE_MASK])?;
+    pack(data, max_bits, w)
+}
+
+/// Decompress data in the `.Z` format of `compress(1)`.
+pub fn unpack_unix<R: std::io::Read, W: std::io::Write>(mut r: R, w: W) -> Result<()> {
+    let mut header = [0u8; 3];
+    r.read_exact(&mut header)?;
+    if header[..2] != MAGIC {
+        return Err(anyhow!("Not a .Z file: bad magic {:02x?}", &header[..2]));
+    }
+    let flags = header[2];
+    if flags & !(MAX_BITS_MASK | BLOCK_MODE_MASK) != 0 {
+        return Err(anyhow!("Unknown .Z flags {:#04x}", flags));
+    }
+    unpack(r, flags & MAX_BITS_MASK, flags & BLOCK_MODE_MASK != 0, w)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    const TEXT: &str = "
+Ah! well a-day! what evil looks
+Had I from old and young!
+Instead of the cross, the Albatross
+About my neck was hung.
+";
+
+    fn roundtrip(data: &[u8], max_bits: u8) {
+        let mut buf = Vec::new();
+        pack(data, max_bits, &mut buf).unwrap();
+        let mut got = Vec::new();
+        unpack(Cursor::new(buf), max_bits, true, &mut got).unwrap();
+        assert_eq!(got, data);
+    }
+
+    #[test]
+    fn roundtrip_text() {
+        roundtrip(TEXT.as_bytes(), MAX_BITS);
+        roundtrip(b"", MAX_BITS);
+    }
+
+    #[test]
+    fn roundtrip_code_widths() {
+        // Enough distinct strings to widen the codes past 9 bits, and to fill
+        // up (and CLEAR) a 9 bit dictionary repeatedly.
+        let data: Vec<u8> = (0..100_000u32)
+            .map(|i| (i.wrapping_mul(2_654_435_761) >> 27) as u8)
+            .collect();
+        for max_bits in INIT_BITS as u8..=MAX_BITS {
+            roundtrip(&data, max_bits);
+        }
+    }
+
+    #[test]
+    fn kwkwk() {
+        // The third code refers to the entry being defined ("aa").
+        let mut buf = Vec::new();
+        pack(b"aaaa", MAX_BITS, &mut buf).unwrap();
+        let mut codes = CodeReader::new(&buf);
+        assert_eq!(codes.read(9), Some(u32::from(b'a')));
+        assert_eq!(codes.read(9), Some(257));
+        assert_eq!(codes.read(9), Some(u32::from(b'a')));
+        roundtrip(b"aaaa", MAX_BITS);
+    }
+
+    #[test]
+    fn unix_format() {
+        // Checked against `gzip -d`, which also reads the .Z format.
+        let z = [0x1f, 0x9d, 0x90, 0x61, 0xc4, 0x04, 0x1c, 0x28, 0x46, 0x01];
+        let mut got = Vec::new();
+        unpack_unix(Cursor::new(&z), &mut got).unwrap();
+        assert_eq!(got, b"abababab\n");
+
+        let mut buf = Vec::new();
+        pack_unix(b"abababab\n", MAX_BITS, &mut buf).unwrap();
+        assert_eq!(buf, z);
+    }
+
+    #[test]
+    fn unix_format_without_block_mode() {
+        // Code 256 is an ordinary dictionary entry ("ab") without block mode.
+        let mut codes = CodeWriter::default();
+        for c in [u32::from(b'a'), u32::from(b'b'), 256] {
+            codes.write(c, INIT_BITS);
+        }
+        let mut z = vec![0x1f, 0x9d, MAX_BITS];
+        z.extend_from_slice(&codes.data);
+        let mut got = Vec::new();
+        unpack_unix(Cursor::new(z), &mut got).unwrap();
+        assert_eq!(got, b"abab");
+    }
+
+    #[test]
+    fn unix_format_rejects_bad_header() {
+        let mut got = Vec::new();
+        assert!(unpack_unix(Cursor::new([0x1f, 0x8b, 0x90]), &mut got).is_err());
+        assert!(unpack_unix(Cursor::new([0x1f, 0x9d, 0x91]), &mut got).is_err());
+        assert!(unpack_unix(Cursor::new([0x1f, 0x9d, 0x88]), &mut got).is_err());
+    }
+
+    #[test]
+    fn rejects_invalid_codes() {
+        let mut codes = CodeWriter::default();
+        for c in [u32::from(b'a'), 300] {
+            codes.write(c, INIT_BITS);
+        }
+        let mut got = Vec::new();
+        assert!(unpack(Cursor::new(&codes.data), MAX_BITS, true, &mut got).is_err());
+    }
+}
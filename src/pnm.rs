@@ -0,0 +1,487 @@
+// Copyright 2020 Prathmesh Prabhu
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! pnm module implements lossless compression of binary [PGM] (grayscale) and
+//! PPM (color) images with predictive filters.
+//!
+//! Each sample is predicted from its already coded neighbours in the same
+//! channel: `a` to the left, `b` above and `c` above left (`0` outside the
+//! image). The difference between the sample and its prediction (the
+//! residual) is usually small, so the residuals of each channel are far more
+//! compressible than the samples. The residuals of each channel are
+//! compressed as a separate token stream with its own
+//! [`Encoding`](crate::encoding::Encoding).
+//!
+//! The image header is included in the compressed output as is, as are any
+//! bytes after the raster, so the output is byte-for-byte identical to the
+//! source. Text that is not a binary PGM or PPM image is compressed as a
+//! single stream of bytes instead.
+//!
+//! [PGM]: https://netpbm.sourceforge.net/doc/pgm.html
+
+use crate::encoding::EncodingScheme;
+use crate::stream;
+use crate::tokens::bytes::Byte;
+use crate::tokens::integers::Integer;
+use crate::util::{pack_u64, unpack_u64};
+use anyhow::{anyhow, Result};
+use std::convert::TryFrom;
+use std::io::Read;
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// The method to predict each sample from its neighbours.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Predictor {
+    /// The sample to the left, as PNG filter type 1.
+    Left,
+    /// The sample above, as PNG filter type 2.
+    Up,
+    /// The average of the samples to the left and above, as PNG filter type 3.
+    Average,
+    /// The [Paeth] predictor, as PNG filter type 4.
+    ///
+    /// [Paeth]: https://www.w3.org/TR/png/#9Filter-type-4-Paeth
+    Paeth,
+    /// The median edge detector of [LOCO-I] (JPEG-LS).
+    ///
+    /// [LOCO-I]: https://en.wikipedia.org/wiki/Lossless_JPEG#LOCO-I_algorithm
+    Med,
+}
+
+impl Predictor {
+    fn predict(&self, a: i64, b: i64, c: i64) -> i64 {
+        match self {
+            Predictor::Left => a,
+            Predictor::Up => b,
+            Predictor::Average => (a + b) / 2,
+            Predictor::Paeth => {
+                let p = a + b - c;
+                let (pa, pb, pc) = ((p - a).abs(), (p - b).abs(), (p - c).abs());
+                if pa <= pb && pa <= pc {
+                    a
+                } else if pb <= pc {
+                    b
+                } else {
+                    c
+                }
+            }
+            Predictor::Med => {
+                if c >= a.max(b) {
+                    a.min(b)
+                } else if c <= a.min(b) {
+                    a.max(b)
+                } else {
+                    a + b - c
+                }
+            }
+        }
+    }
+}
+
+pub fn pack_predictor(p: Predictor) -> u8 {
+    match p {
+        Predictor::Left => 0,
+        Predictor::Up => 1,
+        Predictor::Average => 2,
+        Predictor::Paeth => 3,
+        Predictor::Med => 4,
+    }
+}
+
+pub fn unpack_predictor(b: u8) -> Result<Predictor> {
+    match b {
+        0 => Ok(Predictor::Left),
+        1 => Ok(Predictor::Up),
+        2 => Ok(Predictor::Average),
+        3 => Ok(Predictor::Paeth),
+        4 => Ok(Predictor::Med),
+        m => Err(anyhow!("Unknown predictor {}", m)),
+    }
+}
+
+// Markers for the mode of the compressed text.
+const RAW: u8 = 0;
+const IMAGE: u8 = 1;
+
+/// The layout of a binary PGM or PPM image.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Header {
+    pub width: usize,
+    pub height: usize,
+    /// 1 for PGM, 3 for PPM.
+    pub channels: usize,
+    pub max_value: u16,
+    /// The number of bytes in the header, up to the start of the raster.
+    pub len: usize,
+}
+
+impl Header {
+    // The number of bytes in each sample.
+    fn sample_size(&self) -> usize {
+        if self.max_value < 256 {
+            1
+        } else {
+            2
+        }
+    }
+
+    fn raster_len(&self) -> Option<usize> {
+        self.width
+            .checked_mul(self.height)?
+            .checked_mul(self.channels)?
+            .checked_mul(self.sample_size())
+    }
+}
+
+/// Parse the header of the binary PGM or PPM image in `data`.
+///
+/// Returns `None` if `data` does not start with a valid header, or is too
+/// short for the raster.
+pub fn parse_header(data: &[u8]) -> Option<Header> {
+    let header = parse_header_prefix(data)?;
+    if header.len.checked_add(header.raster_len()?)? > data.len() {
+        return None;
+    }
+    Some(header)
+}
+
+// Parse the header at the start of `data`, which need not include the raster.
+fn parse_header_prefix(data: &[u8]) -> Option<Header> {
+    let channels = match data.get(..2)? {
+        b"P5" => 1,
+        b"P6" => 3,
+        _ => return None,
+    };
+    let mut i = 2;
+    let mut fields = [0usize; 3];
+    for f in fields.iter_mut() {
+        // Skip whitespace and comments, of which there must be some.
+        let start = i;
+        loop {
+            match data.get(i)? {
+                b' ' | b'\t' | b'\n' | b'\r' | b'\x0b' | b'\x0c' => i += 1,
+                b'#' => {
+                    while *data.get(i)? != b'\n' {
+                        i += 1;
+                    }
+                }
+                _ => break,
+            }
+        }
+        if i == start {
+            return None;
+        }
+        let digits = data[i..].iter().take_while(|b| b.is_ascii_digit()).count();
+        *f = std::str::from_utf8(&data[i..i + digits])
+            .ok()?
+            .parse()
+            .ok()?;
+        i += digits;
+    }
+    // A single whitespace byte separates the header from the raster.
+    if !data.get(i)?.is_ascii_whitespace() {
+        return None;
+    }
+    let max_value = u16::try_from(fields[2]).ok().filter(|m| *m > 0)?;
+    Some(Header {
+        width: fields[0],
+        height: fields[1],
+        channels,
+        max_value,
+        len: i + 1,
+    })
+}
+
+// Computes the prediction for each sample of an image from the samples coded
+// before it.
+struct Predictions<'a> {
+    header: &'a Header,
+    predictor: Predictor,
+}
+
+impl Predictions<'_> {
+    // The prediction for the sample of `channel` at (`x`, `y`), given the
+    // samples in `image` (one per entry).
+    fn at(&self, image: &[u16], channel: usize, x: usize, y: usize) -> i64 {
+        let h = self.header;
+        let sample = |x: usize, y: usize| image[(y * h.width + x) * h.channels + channel] as i64;
+        let a = if x > 0 { sample(x - 1, y) } else { 0 };
+        let b = if y > 0 { sample(x, y - 1) } else { 0 };
+        let c = if x > 0 && y > 0 {
+            sample(x - 1, y - 1)
+        } else {
+            0
+        };
+        self.predictor.predict(a, b, c)
+    }
+}
+
+fn zigzag(n: i64) -> u64 {
+    ((n << 1) ^ (n >> 63)) as u64
+}
+
+fn unzigzag(n: u64) -> i64 {
+    (n >> 1) as i64 ^ -((n & 1) as i64)
+}
+
+/// Compress `data` with a predictive image front end.
+///
+/// The residuals of each channel are compressed with an encoding generated
+/// with `encoding_scheme`. The output can be decompressed with [`unpack()`].
+pub fn pack<W: std::io::Write>(
+    data: &[u8],
+    encoding_scheme: &EncodingScheme,
+    predictor: Predictor,
+    mut w: W,
+) -> Result<()> {
+    let header = match parse_header(data) {
+        Some(header) => header,
+        None => {
+            w.write_all(&[RAW])?;
+            let bytes: Vec<Byte> = data.iter().map(|b| Byte::from(*b)).collect();
+            stream::pack(&bytes, encoding_scheme, &mut w)?;
+            w.flush()?;
+            return Ok(());
+        }
+    };
+
+    let size = header.sample_size();
+    let raster_end = header.len + header.raster_len().unwrap();
+    let image: Vec<u16> = data[header.len..raster_end]
+        .chunks(size)
+        .map(|s| s.iter().fold(0u16, |v, b| v << 8 | *b as u16))
+        .collect();
+    // Residuals wrap around, so that they fit in a sample.
+    let modulus = 1i64 << (8 * size);
+    let predictions = Predictions {
+        header: &header,
+        predictor,
+    };
+    let mut residuals = vec![Vec::new(); header.channels];
+    for y in 0..header.height {
+        for x in 0..header.width {
+            for (channel, r) in residuals.iter_mut().enumerate() {
+                let sample = image[(y * header.width + x) * header.channels + channel] as i64;
+                let mut residual = (sample - predictions.at(&image, channel, x, y)) % modulus;
+                if residual >= modulus / 2 {
+                    residual -= modulus;
+                } else if residual < -modulus / 2 {
+                    residual += modulus;
+                }
+                r.push(Integer::Value {
+                    value: zigzag(residual),
+                    width: size as u8,
+                });
+            }
+        }
+    }
+
+    w.write_all(&[IMAGE])?;
+    w.write_all(&pack_u64(header.len as u64))?;
+    w.write_all(&data[..header.len])?;
+    for r in residuals {
+        stream::pack(&r, encoding_scheme, &mut w)?;
+    }
+    w.write_all(&pack_u64((data.len() - raster_end) as u64))?;
+    w.write_all(&data[raster_end..])?;
+    w.flush()?;
+    Ok(())
+}
+
+/// Decompress data compressed with [`pack()`].
+///
+/// `predictor` must be the same as used with [`pack()`].
+pub fn unpack<R: std::io::Read, W: std::io::Write>(
+    mut r: R,
+    predictor: Predictor,
+    mut w: W,
+) -> Result<()> {
+    let mut mode = [0u8];
+    r.read_exact(&mut mode)?;
+    match mode[0] {
+        RAW => {
+            let bytes: Vec<Byte> = stream::unpack(&mut r)?;
+            let data: Vec<u8> = bytes.into_iter().map(u8::from).collect();
+            w.write_all(&data)?;
+            w.flush()?;
+            return Ok(());
+        }
+        IMAGE => (),
+        m => return Err(anyhow!("Unknown image mode {}", m)),
+    }
+
+    let len = unpack_u64(&mut r)?;
+    let mut header_text = Vec::new();
+    (&mut r).take(len).read_to_end(&mut header_text)?;
+    let header = match parse_header_prefix(&header_text) {
+        Some(h) if h.len == header_text.len() => h,
+        _ => return Err(anyhow!("Invalid image header")),
+    };
+    let samples = header.width.checked_mul(header.height);
+    let mut residuals = Vec::new();
+    for channel in 0..header.channels {
+        let r: Vec<Integer> = stream::unpack(&mut r)?;
+        if Some(r.len()) != samples {
+            return Err(anyhow!("Wrong number of residuals for channel {}", channel));
+        }
+        residuals.push(r.into_iter());
+    }
+
+    let size = header.sample_size();
+    let mask = ((1u64 << (8 * size)) - 1) as i64;
+    let predictions = Predictions {
+        header: &header,
+        predictor,
+    };
+    let mut image = vec![0u16; header.width * header.height * header.channels];
+    for y in 0..header.height {
+        for x in 0..header.width {
+            for (channel, r) in residuals.iter_mut().enumerate() {
+                let residual = match r.next() {
+                    Some(Integer::Value { value, .. }) => unzigzag(value),
+                    _ => return Err(anyhow!("Ran out of residuals for channel {}", channel)),
+                };
+                let sample = (predictions.at(&image, channel, x, y) + residual) & mask;
+                image[(y * header.width + x) * header.channels + channel] = sample as u16;
+            }
+        }
+    }
+
+    w.write_all(&header_text)?;
+    let mut raster = Vec::with_capacity(image.len() * size);
+    for s in image {
+        raster.extend_from_slice(&s.to_be_bytes()[2 - size..]);
+    }
+    w.write_all(&raster)?;
+    let len = unpack_u64(&mut r)?;
+    let mut trailer = Vec::new();
+    r.take(len).read_to_end(&mut trailer)?;
+    w.write_all(&trailer)?;
+    w.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    const PREDICTORS: [Predictor; 5] = [
+        Predictor::Left,
+        Predictor::Up,
+        Predictor::Average,
+        Predictor::Paeth,
+        Predictor::Med,
+    ];
+
+    // A `width` x `height` image of gradients with a bit of noise.
+    fn image(magic: &str, width: usize, height: usize, max_value: u16, trailer: &[u8]) -> Vec<u8> {
+        let mut data = format!(
+            "{}\n# made by hand\n{} {}\n{}\n",
+            magic, width, height, max_value
+        )
+        .into_bytes();
+        let channels = if magic == "P5" { 1 } else { 3 };
+        for y in 0..height {
+            for x in 0..width {
+                for c in 0..channels {
+                    let v =
+                        ((x * 7 + y * 3 + c * 50 + (x * y) % 5) % (max_value as usize + 1)) as u16;
+                    if max_value < 256 {
+                        data.push(v as u8);
+                    } else {
+                        data.extend_from_slice(&v.to_be_bytes());
+                    }
+                }
+            }
+        }
+        data.extend_from_slice(trailer);
+        data
+    }
+
+    fn roundtrip(data: &[u8], predictor: Predictor) -> Vec<u8> {
+        let mut buf = Vec::new();
+        pack(data, &EncodingScheme::Huffman, predictor, &mut buf).unwrap();
+        let mut got = Vec::new();
+        unpack(Cursor::new(buf), predictor, &mut got).unwrap();
+        got
+    }
+
+    #[test]
+    fn roundtrip_images() {
+        for data in [
+            image("P5", 17, 9, 255, b""),
+            image("P6", 8, 5, 200, b"trailing bytes"),
+            image("P5", 6, 7, 1023, b""),
+            image("P6", 3, 2, 65535, b"\n"),
+            image("P5", 0, 0, 255, b""),
+        ] {
+            for p in PREDICTORS {
+                assert_eq!(roundtrip(&data, p), data, "{:?}", p);
+            }
+        }
+    }
+
+    #[test]
+    fn roundtrip_other() {
+        for data in [
+            &b""[..],
+            b"P5",
+            b"P5 2 2 255 abc",
+            b"P4\n1 1\n\x00",
+            b"P52 2 255 abcd",
+        ] {
+            assert!(parse_header(data).is_none());
+            assert_eq!(roundtrip(data, Predictor::Med), data);
+        }
+    }
+
+    #[test]
+    fn header() {
+        assert_eq!(
+            parse_header(b"P6 #comment\n2\t1 65535\nabcdefghijkl"),
+            Some(Header {
+                width: 2,
+                height: 1,
+                channels: 3,
+                max_value: 65535,
+                len: 22,
+            })
+        );
+    }
+
+    #[test]
+    fn predictions() {
+        assert_eq!(Predictor::Med.predict(10, 20, 25), 10);
+        assert_eq!(Predictor::Med.predict(10, 20, 5), 20);
+        assert_eq!(Predictor::Med.predict(10, 20, 15), 15);
+        assert_eq!(Predictor::Paeth.predict(10, 20, 25), 10);
+        assert_eq!(Predictor::Paeth.predict(10, 20, 8), 20);
+        assert_eq!(Predictor::Average.predict(10, 21, 0), 15);
+    }
+
+    #[test]
+    fn residuals_are_small() {
+        let data = image("P5", 32, 32, 255, b"");
+        let mut med = Vec::new();
+        pack(&data, &EncodingScheme::Huffman, Predictor::Med, &mut med).unwrap();
+        let bytes: Vec<Byte> = data.iter().map(|b| Byte::from(*b)).collect();
+        let mut raw = Vec::new();
+        stream::pack(&bytes, &EncodingScheme::Huffman, &mut raw).unwrap();
+        assert!(med.len() < raw.len() / 2);
+    }
+}
@@ -0,0 +1,340 @@
+// Copyright 2020 Prathmesh Prabhu
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! registry module implements a process-wide registry of tokenization and
+//! encoding schemes provided outside this crate.
+//!
+//! A downstream crate implements the [`Token`], [`Tokenizer`] and
+//! [`TokenPacker`] traits for its own token type, and registers it under a
+//! string ID with [`register_tokenization()`]. The registered scheme is then
+//! selected with [`TokenizationScheme::Custom`]. The ID and the scheme
+//! parameters are included in the compressed output, so decompression only
+//! requires that the same ID is registered in the decompressing process.
+//!
+//! Similarly, an [`EncoderFactory`] registered with [`register_encoding()`] is
+//! selected with [`EncodingScheme::Custom`]. The encoding itself is included
+//! in the compressed output, so decompression does not need the factory.
+//!
+//! [`Token`]: crate::Token
+//! [`Tokenizer`]: crate::Tokenizer
+//! [`TokenPacker`]: crate::TokenPacker
+//! [`TokenizationScheme::Custom`]: crate::TokenizationScheme::Custom
+
+use crate::encoding::{EncoderFactory, EncodingScheme};
+use crate::tokens::Token;
+use anyhow::{anyhow, Result};
+use std::collections::HashMap;
+use std::io::{Read, Write};
+use std::sync::{Arc, OnceLock, RwLock};
+
+type CompressFn = dyn Fn(&[u8], &[u8], &EncodingScheme, &mut dyn Write) -> Result<()> + Send + Sync;
+type DecompressFn = dyn Fn(&[u8], &mut dyn Read, &mut dyn Write) -> Result<()> + Send + Sync;
+
+struct Entry {
+    compress: Box<CompressFn>,
+    decompress: Box<DecompressFn>,
+}
+
+fn registry() -> &'static RwLock<HashMap<String, Arc<Entry>>> {
+    static REGISTRY: OnceLock<RwLock<HashMap<String, Arc<Entry>>>> = OnceLock::new();
+    REGISTRY.get_or_init(Default::default)
+}
+
+/// Register a tokenization scheme for the [`Token`] type `T` under `id`.
+///
+/// `tokenizer` and `packer` create the [`Tokenizer`] and [`TokenPacker`] for
+/// the scheme from the `params` in [`TokenizationScheme::Custom`]. They are
+/// called during compression and decompression respectively.
+///
+/// Returns an error if a scheme is already registered under `id`.
+///
+/// [`Token`]: crate::Token
+/// [`Tokenizer`]: crate::Tokenizer
+/// [`TokenPacker`]: crate::TokenPacker
+/// [`TokenizationScheme::Custom`]: crate::TokenizationScheme::Custom
+pub fn register_tokenization<T, FT, FP>(id: &str, tokenizer: FT, packer: FP) -> Result<()>
+where
+    T: Token + 'static,
+    FT: Fn(&[u8]) -> Result<T::Tokenizer> + Send + Sync + 'static,
+    FP: Fn(&[u8]) -> Result<T::Packer> + Send + Sync + 'static,
+{
+    let entry = Entry {
+        compress: Box::new(move |data, params, encoding_scheme, w| {
+            crate::internal::compress_with_token::<T, _>(
+                data,
+                w,
+                encoding_scheme,
+                tokenizer(params)?,
+            )
+        }),
+        decompress: Box::new(move |params, r, w| {
+            crate::internal::decompress_with_token::<T, _, _>(r, w, packer(params)?)
+        }),
+    };
+    let mut registry = registry().write().unwrap();
+    if registry.contains_key(id) {
+        return Err(anyhow!(
+            "Tokenization scheme {:?} is already registered",
+            id
+        ));
+    }
+    registry.insert(id.to_owned(), Arc::new(entry));
+    Ok(())
+}
+
+fn encoders() -> &'static RwLock<HashMap<String, Arc<dyn EncoderFactory>>> {
+    static ENCODERS: OnceLock<RwLock<HashMap<String, Arc<dyn EncoderFactory>>>> = OnceLock::new();
+    ENCODERS.get_or_init(Default::default)
+}
+
+/// Register an [`EncoderFactory`] under `id`.
+///
+/// Returns an error if a factory is already registered under `id`.
+pub fn register_encoding<F: EncoderFactory + 'static>(id: &str, factory: F) -> Result<()> {
+    let mut encoders = encoders().write().unwrap();
+    if encoders.contains_key(id) {
+        return Err(anyhow!("Encoding scheme {:?} is already registered", id));
+    }
+    encoders.insert(id.to_owned(), Arc::new(factory));
+    Ok(())
+}
+
+/// The [`EncoderFactory`] registered under `id`.
+pub fn encoder(id: &str) -> Result<Arc<dyn EncoderFactory>> {
+    encoders()
+        .read()
+        .unwrap()
+        .get(id)
+        .cloned()
+        .ok_or_else(|| anyhow!("Unknown encoding scheme {:?}", id))
+}
+
+fn lookup(id: &str) -> Result<Arc<Entry>> {
+    registry()
+        .read()
+        .unwrap()
+        .get(id)
+        .cloned()
+        .ok_or_else(|| anyhow!("Unknown tokenization scheme {:?}", id))
+}
+
+/// Compress `data` with the tokenization scheme registered under `id`.
+pub fn compress(
+    id: &str,
+    params: &[u8],
+    data: &[u8],
+    encoding_scheme: &EncodingScheme,
+    w: &mut dyn Write,
+) -> Result<()> {
+    (lookup(id)?.compress)(data, params, encoding_scheme, w)
+}
+
+/// Decompress data previously written with [`compress()`].
+pub fn decompress(id: &str, params: &[u8], r: &mut dyn Read, w: &mut dyn Write) -> Result<()> {
+    (lookup(id)?.decompress)(params, r, w)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::code::Letter;
+    use crate::tokens::n_grams::{NGram, NGramPacker, NGramTokenizer};
+    use crate::{
+        Args, Charset, Command, CompressArgs, DecompressArgs, TokenizationScheme, Transform,
+    };
+    use std::path::Path;
+
+    const TEXT: &str = "
+Ah! well a-day! what evil looks
+Had I from old and young!
+Instead of the cross, the Albatross
+About my neck was hung.
+";
+
+    fn register_n_grams(id: &str) -> Result<()> {
+        register_tokenization::<NGram, _, _>(
+            id,
+            |params| NGramTokenizer::new(params[0]),
+            |_| Ok(NGramPacker),
+        )
+    }
+
+    #[test]
+    fn roundtrip() {
+        register_n_grams("registry-roundtrip").unwrap();
+        let mut compressed = Vec::new();
+        compress(
+            "registry-roundtrip",
+            &[3],
+            TEXT.as_bytes(),
+            &EncodingScheme::Huffman,
+            &mut compressed,
+        )
+        .unwrap();
+        let mut got = Vec::new();
+        decompress("registry-roundtrip", &[3], &mut &compressed[..], &mut got).unwrap();
+        assert_eq!(std::str::from_utf8(&got).unwrap(), TEXT);
+    }
+
+    #[test]
+    fn truncated_input() {
+        register_n_grams("registry-truncated").unwrap();
+        let mut compressed = Vec::new();
+        compress(
+            "registry-truncated",
+            &[3],
+            TEXT.as_bytes(),
+            &EncodingScheme::Huffman,
+            &mut compressed,
+        )
+        .unwrap();
+        for len in [0, 1, compressed.len() / 2] {
+            let mut got = Vec::new();
+            let r = &mut &compressed[..len];
+            assert!(decompress("registry-truncated", &[3], r, &mut got).is_err());
+        }
+    }
+
+    #[test]
+    fn duplicate_id() {
+        register_n_grams("registry-duplicate").unwrap();
+        assert!(register_n_grams("registry-duplicate").is_err());
+    }
+
+    #[test]
+    fn unknown_id() {
+        let mut w = Vec::new();
+        assert!(compress(
+            "registry-unknown",
+            &[],
+            b"",
+            &EncodingScheme::Huffman,
+            &mut w
+        )
+        .is_err());
+        assert!(decompress("registry-unknown", &[], &mut &b""[..], &mut w).is_err());
+    }
+
+    fn compress_file(input_file: &Path, output_file: &Path, id: &str) -> Result<()> {
+        crate::run(Args {
+            command: Command::Compress(CompressArgs {
+                tokenization_scheme: TokenizationScheme::Custom {
+                    id: id.to_owned(),
+                    params: vec![3],
+                },
+                encoding_scheme: EncodingScheme::Huffman,
+                transform: Transform::None,
+                charset: Charset::Utf8,
+            }),
+            input_file,
+            output_file,
+        })
+    }
+
+    fn decompress_file(input_file: &Path, output_file: &Path) -> Result<()> {
+        crate::run(Args {
+            command: Command::Decompress(DecompressArgs {}),
+            input_file,
+            output_file,
+        })
+    }
+
+    #[test]
+    fn run_roundtrip() {
+        register_n_grams("registry-run-roundtrip").unwrap();
+        let work_dir = tempfile::tempdir().unwrap();
+        let input_file = work_dir.path().join("input");
+        let compressed_file = work_dir.path().join("compressed");
+        let output_file = work_dir.path().join("output");
+        std::fs::write(&input_file, TEXT).unwrap();
+        compress_file(&input_file, &compressed_file, "registry-run-roundtrip").unwrap();
+        decompress_file(&compressed_file, &output_file).unwrap();
+        assert_eq!(std::fs::read_to_string(&output_file).unwrap(), TEXT);
+    }
+
+    #[test]
+    fn run_unknown_id() {
+        register_n_grams("registry-run-known").unwrap();
+        let work_dir = tempfile::tempdir().unwrap();
+        let input_file = work_dir.path().join("input");
+        let compressed_file = work_dir.path().join("compressed");
+        let output_file = work_dir.path().join("output");
+        std::fs::write(&input_file, TEXT).unwrap();
+        compress_file(&input_file, &compressed_file, "registry-run-known").unwrap();
+
+        // Rename the scheme in the compressed file to an ID (of the same
+        // length) that is not registered.
+        let data = std::fs::read(&compressed_file).unwrap();
+        let at = data
+            .windows(b"registry-run-known".len())
+            .position(|w| w == b"registry-run-known")
+            .unwrap();
+        let mut renamed = data[..at].to_vec();
+        renamed.extend_from_slice(b"registry-run-other");
+        renamed.extend_from_slice(&data[at + b"registry-run-known".len()..]);
+        std::fs::write(&compressed_file, renamed).unwrap();
+
+        let err = decompress_file(&compressed_file, &output_file).unwrap_err();
+        assert!(err.to_string().contains("registry-run-other"), "{}", err);
+    }
+
+    // Codes the i-th token as the fixed width binary representation of i + 1.
+    struct FixedWidth;
+
+    impl EncoderFactory for FixedWidth {
+        fn letters(&self, frequencies: &[u64]) -> Result<Vec<Letter>> {
+            let width = 64 - (frequencies.len() as u64).leading_zeros() as u64;
+            Ok((1..=frequencies.len() as u64)
+                .map(|i| Letter::new(&(i << (64 - width)).to_be_bytes(), width))
+                .collect())
+        }
+    }
+
+    #[test]
+    fn encoding_roundtrip() {
+        register_encoding("registry-fixed-width", FixedWidth).unwrap();
+        register_n_grams("registry-encoding-roundtrip").unwrap();
+        let mut compressed = Vec::new();
+        compress(
+            "registry-encoding-roundtrip",
+            &[1],
+            TEXT.as_bytes(),
+            &EncodingScheme::Custom {
+                id: "registry-fixed-width".to_owned(),
+            },
+            &mut compressed,
+        )
+        .unwrap();
+        let mut got = Vec::new();
+        decompress(
+            "registry-encoding-roundtrip",
+            &[1],
+            &mut &compressed[..],
+            &mut got,
+        )
+        .unwrap();
+        assert_eq!(std::str::from_utf8(&got).unwrap(), TEXT);
+    }
+
+    #[test]
+    fn duplicate_encoding_id() {
+        register_encoding("registry-duplicate-encoding", FixedWidth).unwrap();
+        assert!(register_encoding("registry-duplicate-encoding", FixedWidth).is_err());
+    }
+
+    #[test]
+    fn unknown_encoding_id() {
+        assert!(encoder("registry-unknown-encoding").is_err());
+    }
+}
@@ -0,0 +1,135 @@
+// Copyright 2020 Prathmesh Prabhu
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! stream module provides methods to (de)compress a single [`Token`] stream
+//! with its own [`Encoding`].
+//!
+//! Schemes that split the source text into several streams (e.g. literals and
+//! match distances) use these methods to give each stream the encoding that
+//! best fits its own statistics. Each stream is written as:
+//!
+//! ```ascii-art
+//! [Encoding][u64 length of code text in bytes][code text]
+//! ```
+//!
+//! The length prefix allows several streams to be written back to back.
+
+use crate::code::Letter;
+use crate::encoding::{new_encoder, Encoding, EncodingScheme};
+use crate::model;
+use crate::tokens::Token;
+use crate::util::{pack_u64, unpack_bytes};
+use anyhow::{anyhow, Result};
+use std::collections::HashMap;
+
+/// Write `tokens` along with an [`Encoding`] generated for them with
+/// `encoding_scheme`.
+///
+/// Can be read back with [`unpack()`].
+pub fn pack<T: Token, W: std::io::Write>(
+    tokens: &[T],
+    encoding_scheme: &EncodingScheme,
+    mut w: W,
+) -> Result<()> {
+    let encoding = new_encoder(encoding_scheme, model::from(tokens.iter().cloned()))?;
+    let mut code_text = Vec::new();
+    let letters: Vec<&Letter> =
+        encode(encoding.map(), tokens.iter().cloned()).collect::<Result<_>>()?;
+    crate::code::pack(letters.into_iter(), &mut code_text)?;
+
+    encoding.pack(&mut w)?;
+    w.write_all(&pack_u64(code_text.len() as u64))?;
+    w.write_all(&code_text)?;
+    Ok(())
+}
+
+/// Read a [`Token`] stream previously written with [`pack()`].
+///
+/// Consumes exactly the data written by [`pack()`], so that more data may
+/// follow the stream in `r`.
+pub fn unpack<T: Token, R: std::io::Read>(mut r: R) -> Result<Vec<T>> {
+    let encoding: Encoding<T> = Encoding::unpack(&mut r)?;
+    let code_text = unpack_bytes(&mut r)?;
+
+    let map = encoding.reverse_map();
+    let letters: Vec<&Letter> =
+        crate::code::parse(encoding.alphabet(), &code_text[..])?.collect::<Result<_>>()?;
+    decode(&map, letters.into_iter()).collect()
+}
+
+/// Map each [`Token`] in `input` to its [`Letter`] in `encoding`.
+pub fn encode<T, TS>(
+    encoding: &HashMap<T, Letter>,
+    input: TS,
+) -> impl Iterator<Item = Result<&Letter>>
+where
+    T: Token,
+    TS: std::iter::Iterator<Item = T>,
+{
+    input.map(move |t| match encoding.get(&t) {
+        Some(l) => Ok(l),
+        None => Err(anyhow!("Unknown token {}", t)),
+    })
+}
+
+/// Map each [`Letter`] in `input` back to its [`Token`] in `encoding`.
+pub fn decode<'a, T, CS>(
+    encoding: &'a HashMap<&'a Letter, &'a T>,
+    input: CS,
+) -> impl Iterator<Item = Result<T>> + 'a
+where
+    T: Token,
+    CS: std::iter::Iterator<Item = &'a Letter> + 'a,
+{
+    input.map(move |l| match encoding.get(l) {
+        Some(t) => Ok((*t).clone()),
+        None => Err(anyhow!("no encoding for letter {}", l)),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tokens::bytes::Byte;
+    use std::io::Cursor;
+
+    #[test]
+    fn roundtrip() {
+        let first: Vec<Byte> = "abracadabra".bytes().map(Byte::from).collect();
+        let second: Vec<Byte> = "xyzzy".bytes().map(Byte::from).collect();
+        let mut buf = Vec::new();
+        pack(&first, &EncodingScheme::Huffman, &mut buf).unwrap();
+        pack(&second, &EncodingScheme::Fano, &mut buf).unwrap();
+
+        let mut r = Cursor::new(buf);
+        assert_eq!(unpack::<Byte, _>(&mut r).unwrap(), first);
+        assert_eq!(unpack::<Byte, _>(&mut r).unwrap(), second);
+    }
+
+    #[test]
+    fn empty() {
+        let mut buf = Vec::new();
+        pack::<Byte, _>(&[], &EncodingScheme::Huffman, &mut buf).unwrap();
+        assert_eq!(unpack::<Byte, _>(Cursor::new(buf)).unwrap(), vec![]);
+    }
+
+    #[test]
+    fn truncated() {
+        let tokens: Vec<Byte> = "abracadabra".bytes().map(Byte::from).collect();
+        let mut buf = Vec::new();
+        pack(&tokens, &EncodingScheme::Huffman, &mut buf).unwrap();
+        buf.pop();
+        assert!(unpack::<Byte, _>(Cursor::new(buf)).is_err());
+    }
+}
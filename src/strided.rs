@@ -0,0 +1,217 @@
+// Copyright 2020 Prathmesh Prabhu
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! strided module implements a front end to the entropy coders for binary
+//! data made of fixed-size records, e.g. arrays of structs or RGB pixels.
+//!
+//! The bytes of the source text are split into `stride` interleaved channels:
+//! channel `c` holds the bytes at positions `c`, `c + stride`, `c + 2 *
+//! stride`, and so on. Bytes at the same position in each record (e.g. the
+//! red samples of pixels, or the high bytes of a field) have similar values,
+//! so each channel is compressed as a stream of bytes with its own
+//! [`Model`](crate::model::Model) and [`Encoding`](crate::encoding::Encoding).
+//!
+//! The stride can be detected from the source text with [`detect_stride()`],
+//! which picks the stride for which the channels have the smallest estimated
+//! compressed size.
+
+use crate::encoding::EncodingScheme;
+use crate::stream;
+use crate::tokens::bytes::Byte;
+use anyhow::{anyhow, Result};
+use log::debug;
+
+/// The largest supported stride.
+pub const MAX_STRIDE: u16 = 1024;
+/// The largest stride tried by [`detect_stride()`].
+pub const MAX_DETECTED_STRIDE: u16 = 64;
+
+// Estimated cost (in bits) of each stream, and of each distinct byte in the
+// encoding of a stream.
+const STREAM_COST: f64 = 256.0;
+const SYMBOL_COST: f64 = 32.0;
+
+// The estimated size (in bits) of the bytes of `data` at `offset`, `offset +
+// stride`, ... compressed with an order-0 model.
+fn channel_cost(data: &[u8], offset: usize, stride: usize) -> f64 {
+    let mut counts = [0u64; 256];
+    for b in data.iter().skip(offset).step_by(stride) {
+        counts[*b as usize] += 1;
+    }
+    let total: u64 = counts.iter().sum();
+    let entropy: f64 = counts
+        .iter()
+        .filter(|c| **c > 0)
+        .map(|c| *c as f64 * (total as f64 / *c as f64).log2())
+        .sum();
+    let symbols = counts.iter().filter(|c| **c > 0).count();
+    STREAM_COST + SYMBOL_COST * symbols as f64 + entropy
+}
+
+/// Pick the stride (up to [`MAX_DETECTED_STRIDE`]) for which the channels of
+/// `data` have the smallest estimated compressed size.
+///
+/// Ties go to the smaller stride, so that multiples of the record size are
+/// not picked over the record size.
+pub fn detect_stride(data: &[u8]) -> u16 {
+    let mut best = (f64::INFINITY, 1);
+    for stride in 1..=MAX_DETECTED_STRIDE {
+        let cost: f64 = (0..stride as usize)
+            .map(|c| channel_cost(data, c, stride as usize))
+            .sum();
+        if cost < best.0 {
+            best = (cost, stride);
+        }
+    }
+    best.1
+}
+
+/// Compress `data` as `stride` interleaved channels of bytes.
+///
+/// With a `stride` of 0, the stride is detected with [`detect_stride()`].
+/// Each channel is compressed with an encoding generated with
+/// `encoding_scheme`. The output can be decompressed with [`unpack()`].
+///
+/// Returns an error if `stride` is larger than [`MAX_STRIDE`].
+pub fn pack<W: std::io::Write>(
+    data: &[u8],
+    encoding_scheme: &EncodingScheme,
+    stride: u16,
+    mut w: W,
+) -> Result<()> {
+    if stride > MAX_STRIDE {
+        return Err(anyhow!(
+            "Stride must be at most {}, got {}",
+            MAX_STRIDE,
+            stride
+        ));
+    }
+    let stride = match stride {
+        0 => detect_stride(data),
+        stride => stride,
+    };
+    debug!("Splitting input into {} channels", stride);
+    w.write_all(&stride.to_be_bytes())?;
+    for c in 0..stride as usize {
+        let channel: Vec<Byte> = data
+            .iter()
+            .skip(c)
+            .step_by(stride as usize)
+            .map(|b| Byte::from(*b))
+            .collect();
+        stream::pack(&channel, encoding_scheme, &mut w)?;
+    }
+    w.flush()?;
+    Ok(())
+}
+
+/// Decompress data compressed with [`pack()`].
+pub fn unpack<R: std::io::Read, W: std::io::Write>(mut r: R, mut w: W) -> Result<()> {
+    let mut buf = [0u8; 2];
+    r.read_exact(&mut buf)?;
+    let stride = u16::from_be_bytes(buf);
+    if stride == 0 || stride > MAX_STRIDE {
+        return Err(anyhow!("Invalid stride {}", stride));
+    }
+    let mut channels = Vec::new();
+    let mut len = 0;
+    for _ in 0..stride {
+        let channel: Vec<Byte> = stream::unpack(&mut r)?;
+        len += channel.len();
+        channels.push(channel.into_iter().map(u8::from));
+    }
+    let mut out = Vec::with_capacity(len);
+    for i in 0..len {
+        let b = channels[i % stride as usize]
+            .next()
+            .ok_or_else(|| anyhow!("Channel {} is too short", i % stride as usize))?;
+        out.push(b);
+    }
+    w.write_all(&out)?;
+    w.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    // Pseudo-random bytes.
+    fn noise(count: usize) -> Vec<u8> {
+        let mut state = 1u32;
+        (0..count)
+            .map(|_| {
+                state = state.wrapping_mul(1_103_515_245).wrapping_add(12345);
+                (state >> 16) as u8
+            })
+            .collect()
+    }
+
+    // Records with a small random field, a constant, a slowly varying field
+    // and a random letter.
+    fn records(count: usize) -> Vec<u8> {
+        noise(2 * count)
+            .chunks(2)
+            .enumerate()
+            .flat_map(|(i, n)| {
+                let mut r = vec![n[0] % 8, 0xab];
+                r.extend_from_slice(&(1000 + (i as u16 / 16)).to_le_bytes());
+                r.push(b'x' + n[1] % 3);
+                r
+            })
+            .collect()
+    }
+
+    fn compress(data: &[u8], stride: u16) -> Vec<u8> {
+        let mut buf = Vec::new();
+        pack(data, &EncodingScheme::Huffman, stride, &mut buf).unwrap();
+        buf
+    }
+
+    #[test]
+    fn roundtrip() {
+        let data = records(100);
+        for stride in [0, 1, 3, 5, 7, 1024] {
+            for len in [0, 1, 499, 500] {
+                let mut got = Vec::new();
+                unpack(Cursor::new(compress(&data[..len], stride)), &mut got).unwrap();
+                assert_eq!(got, &data[..len]);
+            }
+        }
+    }
+
+    #[test]
+    fn detect() {
+        assert_eq!(detect_stride(&records(1000)), 5);
+        let pixels: Vec<u8> = noise(1000)
+            .into_iter()
+            .flat_map(|n| [200, n % 50, 10 + n % 4])
+            .collect();
+        assert_eq!(detect_stride(&pixels), 3);
+        assert_eq!(detect_stride(b""), 1);
+    }
+
+    #[test]
+    fn channels_help() {
+        let data = records(1000);
+        assert!(compress(&data, 5).len() < compress(&data, 1).len() * 3 / 4);
+    }
+
+    #[test]
+    fn invalid_stride() {
+        assert!(pack(b"abc", &EncodingScheme::Huffman, MAX_STRIDE + 1, Vec::new()).is_err());
+        assert!(unpack(&[0u8, 0][..], Vec::new()).is_err());
+    }
+}
@@ -1,3 +1,10 @@
+use crate::floats::{pack_float_width, unpack_float_width, FloatWidth};
+use crate::pnm::{pack_predictor, unpack_predictor, Predictor};
+use crate::tokens::integers::{
+    pack_endianness, pack_integer_width, unpack_endianness, unpack_integer_width, Endianness,
+    IntegerWidth,
+};
+use crate::util::{pack_u64, unpack_bytes, unpack_u64};
 use anyhow::{anyhow, Result};
 
 #[cfg(feature = "serde")]
@@ -6,40 +13,365 @@ use serde::{Deserialize, Serialize};
 /// Source text needs to be split into tokens that are then compressed using one
 /// of the supported algorithms. This enum lists all the supported tokenization
 /// schemes.
-#[derive(Copy, Clone, Debug, Eq, Hash, PartialEq)]
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum TokenizationScheme {
     /// Split text byte-by-byte.
     ///
     /// This scheme makes no assumptions about the source text encoding.
     Byte,
+    /// Split text by unicode [code points] (i.e., `char`s).
+    ///
+    /// This scheme assumes that source text is utf-8 encoded. Bytes that are
+    /// not part of valid utf-8 text are split into individual raw byte tokens.
+    ///
+    /// Unlike `Grapheme`, multi-byte characters are never combined into larger
+    /// tokens and the source text is tokenized incrementally.
+    ///
+    /// [code points]: https://en.wikipedia.org/wiki/Code_point
+    CodePoint,
     /// Split text by unicode [graphemes].
     ///
-    /// This schemes assumes that source text is utf-8 encoded.
+    /// This schemes assumes that source text is utf-8 encoded. Bytes that are
+    /// not part of valid utf-8 text are split into individual raw byte tokens,
+    /// so that no input is rejected.
     ///
     /// [graphemes]: https://en.wikipedia.org/wiki/Grapheme
     Grapheme,
+    /// Split text into non-overlapping byte [n-grams] of length `n`.
+    ///
+    /// This scheme makes no assumptions about the source text encoding. If the
+    /// length of the source text is not a multiple of `n`, the leftover bytes
+    /// are tokenized as a single (shorter) tail token.
+    ///
+    /// [n-grams]: https://en.wikipedia.org/wiki/N-gram
+    NGram {
+        /// The number of bytes in each token. Must be positive.
+        n: u8,
+    },
     /// Split text by unicode "words".
     ///
-    /// This schemes assumes that source text is utf-8 encoded.
+    /// This schemes assumes that source text is utf-8 encoded. Bytes that are
+    /// not part of valid utf-8 text are split into individual raw byte tokens.
     /// This tokenization scheme (and hence the compression output) is lossy
     /// because punctuation etc. are lost after tokenization.
     ///
     /// > ⚠️ As of Nov 2025, this tokenization scheme is not yet implemented
     /// > properly. It is synonymous to `Grapheme`
     Word,
+    /// Split text into tokens learned with [byte pair encoding].
+    ///
+    /// This scheme makes no assumptions about the source text encoding. The
+    /// most frequent pairs of adjacent tokens in the source text are merged
+    /// into new tokens until there are `vocabulary_size` tokens (including
+    /// the 256 single byte tokens). The learned merges are included in the
+    /// compressed output.
+    ///
+    /// [byte pair encoding]: https://en.wikipedia.org/wiki/Byte_pair_encoding
+    BytePair {
+        /// The maximum number of distinct tokens.
+        vocabulary_size: u32,
+    },
+    /// Split text into the segments that minimize the length of the encoded
+    /// text.
+    ///
+    /// This scheme makes no assumptions about the source text encoding.
+    /// Candidate segments are words, repeated byte n-grams and single bytes.
+    /// The text is segmented by dynamic programming, using code lengths from
+    /// the chosen encoding scheme as segment costs. Segmentation and encoding
+    /// are refined alternately for (up to) `rounds` rounds.
+    Segment {
+        /// The number of refinement rounds.
+        rounds: u8,
+    },
+    /// Replace repeated text with back-references using [LZ77].
+    ///
+    /// This scheme makes no assumptions about the source text encoding. The
+    /// source text is parsed into literal bytes and (length, distance)
+    /// matches into a sliding window. Literals, lengths and distances are
+    /// each compressed with their own encoding.
+    ///
+    /// [LZ77]: https://en.wikipedia.org/wiki/LZ77_and_LZ78
+    Lz77,
+    /// Replace text with codes from a growing [LZW] dictionary.
+    ///
+    /// This scheme makes no assumptions about the source text encoding. Codes
+    /// are written as variable-width (9 to `max_bits` bits) integers, in the
+    /// same layout as the `compress(1)` utility. The encoding scheme is not
+    /// used.
+    ///
+    /// [LZW]: https://en.wikipedia.org/wiki/Lempel%E2%80%93Ziv%E2%80%93Welch
+    Lzw {
+        /// The maximum width of a code, between 9 and 16 bits.
+        max_bits: u8,
+    },
+    /// Split text into [runs] of identical bytes.
+    ///
+    /// This scheme makes no assumptions about the source text encoding. Each
+    /// token is a (value, length) pair. Runs longer than 255 bytes are split
+    /// into several tokens.
+    ///
+    /// [runs]: https://en.wikipedia.org/wiki/Run-length_encoding
+    RunLength,
+    /// Split binary data into fixed-width integers.
+    ///
+    /// Each token is one integer of the given `width` and `endianness`. With
+    /// `delta`, each value is replaced by its difference from the previous
+    /// value. With `zigzag`, (signed) values are mapped so that small
+    /// magnitudes become small numbers. Leftover bytes at the end of the
+    /// source text are tokenized as a single tail token.
+    ///
+    /// Values are coded whole, or as a bucket followed by raw extra bits,
+    /// whichever is smaller. The latter keeps mostly distinct values (e.g.
+    /// sorted ids without `delta`) from growing the dictionary.
+    Integer {
+        /// The type of each integer.
+        width: IntegerWidth,
+        /// The byte order of each integer.
+        endianness: Endianness,
+        /// Code differences between consecutive values.
+        delta: bool,
+        /// Zigzag-code (signed) values.
+        zigzag: bool,
+    },
+    /// Split text into symbols of `k` bits.
+    ///
+    /// This scheme makes no assumptions about the source text encoding. Bits
+    /// are read most-significant first, and symbols may span byte boundaries.
+    /// Leftover bits at the end of the source text are tokenized as a single
+    /// (shorter) tail token.
+    Bits {
+        /// The number of bits in each token, between 1 and 32.
+        k: u8,
+    },
+    /// Split text into lines.
+    ///
+    /// This scheme assumes that source text is utf-8 encoded. Each line,
+    /// including its terminator, is a token. Lines that occur fewer than
+    /// `min_count` times are split into graphemes instead. Bytes that are not
+    /// part of valid utf-8 text are split into individual raw byte tokens.
+    Line {
+        /// The minimum number of occurrences of a line to keep it whole.
+        min_count: u32,
+    },
+    /// Split text with a list of [regular expression] rules.
+    ///
+    /// This scheme makes no assumptions about the source text encoding. At
+    /// each position, the first rule that matches a non-empty prefix of the
+    /// remaining text produces the next token. When no rule matches, the next
+    /// byte is a token by itself. The rules are included in the compressed
+    /// output.
+    ///
+    /// [regular expression]: https://docs.rs/regex/latest/regex/#syntax
+    Rules {
+        /// The regular expressions, in order of priority.
+        patterns: Vec<String>,
+    },
+    /// Split [CSV] text into columns.
+    ///
+    /// The values in each column are compressed with their own encoding.
+    /// Columns of integers are coded as numbers and, with `delta`, as the
+    /// difference between consecutive values. Quoting and line terminators are
+    /// preserved exactly. Text that is not well-formed CSV is compressed as
+    /// bytes.
+    ///
+    /// [CSV]: https://www.rfc-editor.org/rfc/rfc4180
+    Csv {
+        /// Code differences between consecutive values in integer columns.
+        delta: bool,
+    },
+    /// Split [JSON] (or JSON Lines) text into structure, keys and values.
+    ///
+    /// Punctuation, object keys, string values, numbers and whitespace are
+    /// each compressed with their own encoding. Text that is not made up of
+    /// JSON tokens is compressed as bytes.
+    ///
+    /// [JSON]: https://www.rfc-editor.org/rfc/rfc8259
+    Json,
+    /// Split log lines into [Drain]-style templates and slot values.
+    ///
+    /// Lines are split into words at spaces, and similar lines of the same
+    /// length share a template in which the words they differ in are slots.
+    /// The templates are included in the compressed output. Template IDs and
+    /// the values in each slot are compressed with their own encodings.
+    ///
+    /// [Drain]: https://jiemingzhu.github.io/pub/pjhe_icws2017.pdf
+    LogTemplate,
+    /// Split [FASTA] or FASTQ text into headers, quality scores and
+    /// nucleotides.
+    ///
+    /// Nucleotides (`ACGT`) are coded as k-mers of `k` 2-bit nucleotides.
+    /// Lower case (soft masked) runs, other symbols (e.g. `N`), line lengths,
+    /// header lines and quality lines are each compressed with their own
+    /// encoding.
+    ///
+    /// [FASTA]: https://en.wikipedia.org/wiki/FASTA_format
+    Fasta {
+        /// The number of nucleotides in each k-mer, between 1 and 16.
+        k: u8,
+    },
+    /// Code the residuals of predicting each sample of a binary [PGM] or PPM
+    /// image from its neighbours.
+    ///
+    /// The residuals of each channel are compressed with their own encoding.
+    /// The image header, and any bytes after the image, are included in the
+    /// compressed output as is. Text that is not a PGM or PPM image is
+    /// compressed as bytes.
+    ///
+    /// [PGM]: https://netpbm.sourceforge.net/doc/pgm.html
+    Pnm {
+        /// The method to predict each sample.
+        predictor: Predictor,
+    },
+    /// Code the residuals of predicting each sample of a 16 or 24-bit PCM
+    /// [WAV] file from the samples before it in the same channel, in the
+    /// manner of FLAC.
+    ///
+    /// The RIFF header, and any bytes after the samples, are included in the
+    /// compressed output as is. Text that is not a PCM WAV file is compressed
+    /// as bytes.
+    ///
+    /// [WAV]: https://en.wikipedia.org/wiki/WAV
+    Wav {
+        /// The order of the linear predictor, at most 32. With 0, the best of
+        /// the fixed polynomial predictors of order 0 to 4 is used for each
+        /// frame.
+        lpc_order: u8,
+        /// Code residuals with Rice codes, instead of as buckets coded with an
+        /// encoding generated with the encoding scheme, plus extra bits.
+        rice: bool,
+    },
+    /// Code the XOR of each floating point number in an array with the
+    /// previous one, in the manner of [Gorilla].
+    ///
+    /// The leading zero counts, meaningful bit lengths and meaningful bits of
+    /// the XORs are each compressed with their own encoding. Leftover bytes
+    /// at the end of the source text are included in the compressed output as
+    /// is.
+    ///
+    /// [Gorilla]: https://www.vldb.org/pvldb/vol8/p1816-teller.pdf
+    Float {
+        /// The type of each number.
+        width: FloatWidth,
+        /// The byte order of each number.
+        endianness: Endianness,
+    },
+    /// Split binary data made of fixed-size records into interleaved channels
+    /// of bytes.
+    ///
+    /// Channel `c` holds the bytes at positions `c` modulo `stride`. Each
+    /// channel is compressed with its own encoding.
+    Strided {
+        /// The number of channels, at most 1024. With 0, the stride (up to 64)
+        /// is detected from the source text.
+        stride: u16,
+    },
+    /// Split text with a scheme registered at runtime with
+    /// [`register_tokenization()`](crate::register_tokenization).
+    ///
+    /// The `id` and `params` are included in the compressed output. The same
+    /// `id` must be registered when decompressing.
+    Custom {
+        /// The ID the scheme is registered under.
+        id: String,
+        /// Parameters passed to the scheme, in a format of its choosing.
+        params: Vec<u8>,
+    },
+}
+
+impl TokenizationScheme {
+    /// Whether this scheme splits the source text as (utf-8) text, rather
+    /// than as bytes or binary data.
+    ///
+    /// Source text in other charsets is only decoded to utf-8 for text
+    /// schemes. See [`Charset`](crate::Charset).
+    pub fn is_text(&self) -> bool {
+        matches!(
+            self,
+            TokenizationScheme::CodePoint
+                | TokenizationScheme::Grapheme
+                | TokenizationScheme::Word
+                | TokenizationScheme::Line { .. }
+                | TokenizationScheme::Rules { .. }
+                | TokenizationScheme::Csv { .. }
+                | TokenizationScheme::Json
+                | TokenizationScheme::LogTemplate
+        )
+    }
 }
 
 pub fn pack_tokenization_scheme<W: std::io::Write>(
-    scheme: TokenizationScheme,
+    scheme: &TokenizationScheme,
     mut w: W,
 ) -> Result<()> {
     let marker = match scheme {
         TokenizationScheme::Byte => 1u8,
         TokenizationScheme::Grapheme => 2u8,
         TokenizationScheme::Word => 3u8,
+        TokenizationScheme::CodePoint => 4u8,
+        TokenizationScheme::NGram { .. } => 5u8,
+        TokenizationScheme::BytePair { .. } => 6u8,
+        TokenizationScheme::Segment { .. } => 7u8,
+        TokenizationScheme::Lz77 => 8u8,
+        TokenizationScheme::Lzw { .. } => 9u8,
+        TokenizationScheme::RunLength => 10u8,
+        TokenizationScheme::Integer { .. } => 11u8,
+        TokenizationScheme::Bits { .. } => 12u8,
+        TokenizationScheme::Line { .. } => 13u8,
+        TokenizationScheme::Rules { .. } => 14u8,
+        TokenizationScheme::Custom { .. } => 15u8,
+        TokenizationScheme::Csv { .. } => 16u8,
+        TokenizationScheme::Json => 17u8,
+        TokenizationScheme::LogTemplate => 18u8,
+        TokenizationScheme::Fasta { .. } => 19u8,
+        TokenizationScheme::Pnm { .. } => 20u8,
+        TokenizationScheme::Wav { .. } => 21u8,
+        TokenizationScheme::Float { .. } => 22u8,
+        TokenizationScheme::Strided { .. } => 23u8,
     };
-    w.write(&[marker])?;
+    w.write_all(&[marker])?;
+    match scheme {
+        TokenizationScheme::NGram { n } => w.write_all(&[*n])?,
+        TokenizationScheme::BytePair { vocabulary_size } => {
+            w.write_all(&vocabulary_size.to_be_bytes())?
+        }
+        TokenizationScheme::Segment { rounds } => w.write_all(&[*rounds])?,
+        TokenizationScheme::Lzw { max_bits } => w.write_all(&[*max_bits])?,
+        TokenizationScheme::Integer {
+            width,
+            endianness,
+            delta,
+            zigzag,
+        } => w.write_all(&[
+            pack_integer_width(*width),
+            pack_endianness(*endianness),
+            *delta as u8 | (*zigzag as u8) << 1,
+        ])?,
+        TokenizationScheme::Bits { k } => w.write_all(&[*k])?,
+        TokenizationScheme::Line { min_count } => w.write_all(&min_count.to_be_bytes())?,
+        TokenizationScheme::Rules { patterns } => {
+            w.write_all(&pack_u64(patterns.len() as u64))?;
+            for p in patterns {
+                w.write_all(&pack_u64(p.len() as u64))?;
+                w.write_all(p.as_bytes())?;
+            }
+        }
+        TokenizationScheme::Custom { id, params } => {
+            w.write_all(&pack_u64(id.len() as u64))?;
+            w.write_all(id.as_bytes())?;
+            w.write_all(&pack_u64(params.len() as u64))?;
+            w.write_all(params)?;
+        }
+        TokenizationScheme::Csv { delta } => w.write_all(&[*delta as u8])?,
+        TokenizationScheme::Fasta { k } => w.write_all(&[*k])?,
+        TokenizationScheme::Pnm { predictor } => w.write_all(&[pack_predictor(*predictor)])?,
+        TokenizationScheme::Wav { lpc_order, rice } => w.write_all(&[*lpc_order, *rice as u8])?,
+        TokenizationScheme::Float { width, endianness } => {
+            w.write_all(&[pack_float_width(*width), pack_endianness(*endianness)])?
+        }
+        TokenizationScheme::Strided { stride } => w.write_all(&stride.to_be_bytes())?,
+        _ => (),
+    }
     Ok(())
 }
 
@@ -51,6 +383,106 @@ pub fn unpack_tokenization_scheme<R: std::io::Read>(mut r: R) -> Result<Tokeniza
         1u8 => Ok(TokenizationScheme::Byte),
         2u8 => Ok(TokenizationScheme::Grapheme),
         3u8 => Ok(TokenizationScheme::Word),
+        4u8 => Ok(TokenizationScheme::CodePoint),
+        5u8 => {
+            r.read_exact(&mut buf)?;
+            Ok(TokenizationScheme::NGram { n: buf[0] })
+        }
+        6u8 => {
+            let mut buf = [0u8; 4];
+            r.read_exact(&mut buf)?;
+            Ok(TokenizationScheme::BytePair {
+                vocabulary_size: u32::from_be_bytes(buf),
+            })
+        }
+        7u8 => {
+            r.read_exact(&mut buf)?;
+            Ok(TokenizationScheme::Segment { rounds: buf[0] })
+        }
+        8u8 => Ok(TokenizationScheme::Lz77),
+        9u8 => {
+            r.read_exact(&mut buf)?;
+            Ok(TokenizationScheme::Lzw { max_bits: buf[0] })
+        }
+        10u8 => Ok(TokenizationScheme::RunLength),
+        11u8 => {
+            let mut buf = [0u8; 3];
+            r.read_exact(&mut buf)?;
+            let width = unpack_integer_width(buf[0])?;
+            let endianness = unpack_endianness(buf[1])?;
+            if buf[2] > 3 {
+                return Err(anyhow!("Unknown integer transform flags {:#x}", buf[2]));
+            }
+            Ok(TokenizationScheme::Integer {
+                width,
+                endianness,
+                delta: buf[2] & 1 != 0,
+                zigzag: buf[2] & 2 != 0,
+            })
+        }
+        12u8 => {
+            r.read_exact(&mut buf)?;
+            Ok(TokenizationScheme::Bits { k: buf[0] })
+        }
+        13u8 => {
+            let mut buf = [0u8; 4];
+            r.read_exact(&mut buf)?;
+            Ok(TokenizationScheme::Line {
+                min_count: u32::from_be_bytes(buf),
+            })
+        }
+        14u8 => {
+            let count = unpack_u64(&mut r)?;
+            let mut patterns = Vec::new();
+            for _ in 0..count {
+                patterns.push(String::from_utf8(unpack_bytes(&mut r)?)?);
+            }
+            Ok(TokenizationScheme::Rules { patterns })
+        }
+        15u8 => {
+            let id = String::from_utf8(unpack_bytes(&mut r)?)?;
+            let params = unpack_bytes(&mut r)?;
+            Ok(TokenizationScheme::Custom { id, params })
+        }
+        16u8 => {
+            r.read_exact(&mut buf)?;
+            Ok(TokenizationScheme::Csv { delta: buf[0] != 0 })
+        }
+        17u8 => Ok(TokenizationScheme::Json),
+        18u8 => Ok(TokenizationScheme::LogTemplate),
+        19u8 => {
+            r.read_exact(&mut buf)?;
+            Ok(TokenizationScheme::Fasta { k: buf[0] })
+        }
+        20u8 => {
+            r.read_exact(&mut buf)?;
+            Ok(TokenizationScheme::Pnm {
+                predictor: unpack_predictor(buf[0])?,
+            })
+        }
+        21u8 => {
+            let mut buf = [0u8; 2];
+            r.read_exact(&mut buf)?;
+            Ok(TokenizationScheme::Wav {
+                lpc_order: buf[0],
+                rice: buf[1] != 0,
+            })
+        }
+        22u8 => {
+            let mut buf = [0u8; 2];
+            r.read_exact(&mut buf)?;
+            Ok(TokenizationScheme::Float {
+                width: unpack_float_width(buf[0])?,
+                endianness: unpack_endianness(buf[1])?,
+            })
+        }
+        23u8 => {
+            let mut buf = [0u8; 2];
+            r.read_exact(&mut buf)?;
+            Ok(TokenizationScheme::Strided {
+                stride: u16::from_be_bytes(buf),
+            })
+        }
         _ => Err(anyhow!("Unknown tokenization scheme marker {}", marker)),
     }
 }
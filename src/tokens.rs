@@ -25,12 +25,22 @@
 //! particular, [`unpack_all`] is guaranteed to only consume the required amount
 //! of data from the input.
 //!
-//! Three concrete tokenization schemes are exported from sub-modules:
-//! [bytes], [graphemes] and [words].
+//! Twelve concrete tokenization schemes are exported from sub-modules:
+//! [bits], [bytes], [byte_pairs], [code_points], [graphemes], [integers],
+//! [lines], [n_grams], [rules], [runs], [segments] and [words].
 //!
+//! [bits]: bits/index.html
 //! [bytes]: bytes/index.html
+//! [byte_pairs]: byte_pairs/index.html
+//! [code_points]: code_points/index.html
 //! [graphemes]: graphemes/index.html
+//! [integers]: integers/index.html
+//! [lines]: lines/index.html
+//! [n_grams]: n_grams/index.html
 //! [`pack_all`]: fn.pack_all.html
+//! [rules]: rules/index.html
+//! [runs]: runs/index.html
+//! [segments]: segments/index.html
 //! [`Token`]: trait.Token.html
 //! [`TokenIter`]: trait.TokenIter.html
 //! [`TokenPacker`]: trait.TokenPacker.html
@@ -40,9 +50,18 @@
 use anyhow::Result;
 use std::fmt::Display;
 
+pub mod bits;
+pub mod byte_pairs;
 pub mod bytes;
+pub mod code_points;
 pub mod graphemes;
-mod string_parts;
+pub mod integers;
+pub mod lines;
+pub mod n_grams;
+pub mod rules;
+pub mod runs;
+pub mod segments;
+pub mod string_parts;
 pub mod words;
 
 #[cfg(test)]
@@ -55,22 +74,33 @@ pub trait Token: Clone + std::fmt::Debug + Display + Eq + std::hash::Hash {
     type Tokenizer: Tokenizer<T = Self>;
     type Packer: TokenPacker<T = Self>;
 
-    // The number of bits of source text contained in this Token.
-    fn bit_count(&self) -> usize;
+    /// Write this token such that it can be read back with [`Self::unpack()`].
+    ///
+    /// Unlike [`TokenPacker::pack()`], the written data is self-delimiting so
+    /// that tokens can be read back one at a time, irrespective of the tokens
+    /// that follow.
+    fn pack<W: std::io::Write>(&self, w: W) -> Result<()>;
+
+    /// Read a single token previously written with [`Self::pack()`].
+    fn unpack<R: std::io::Read>(r: R) -> Result<Self>;
 }
 
+/// Provides a method to unpack text into a [`Token`] stream.
+///
+/// Parameters of the tokenization scheme (if any) are held by the tokenizer
+/// instance.
 pub trait Tokenizer {
     type T: Token;
     type Iter<R: std::io::Read>: std::iter::Iterator<Item = Result<Self::T>>;
 
-    fn tokenize<R: std::io::Read>(r: R) -> Result<Self::Iter<R>>;
+    fn tokenize<R: std::io::Read>(&self, r: R) -> Result<Self::Iter<R>>;
 }
 
 /// Provides a method to pack a [`Token`] stream to text.
 pub trait TokenPacker {
     type T: Token;
 
-    fn pack<I, W: std::io::Write>(i: I, w: W) -> Result<()>
+    fn pack<I, W: std::io::Write>(&self, i: I, w: W) -> Result<()>
     where
         I: std::iter::Iterator<Item = Self::T>;
 }
@@ -0,0 +1,260 @@
+// Copyright 2020 Prathmesh Prabhu
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! bits module implements tokenization of a string into symbols of `k` bits.
+//!
+//! Bits are read most-significant first from each byte, and symbols may span
+//! byte boundaries. This allows, for example, coding nibbles (`k = 4`) or the
+//! 12-bit samples of packed sensor formats.
+//!
+//! The bits left over at the end of the source text when its length (in bits)
+//! is not a multiple of `k` are tokenized as a single [`Bits::Tail`].
+
+use crate::code::Letter;
+use crate::tokens::bytes::{ByteIter, ByteTokenizer};
+use crate::tokens::{Token, TokenPacker, Tokenizer};
+use anyhow::{anyhow, Result};
+use std::fmt;
+use std::hash::Hash;
+
+/// The maximum number of bits in a single [`Bits`] token.
+pub const MAX_BITS: u8 = 32;
+
+/// A [`Token`] consisting of consecutive bits of data.
+#[derive(Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum Bits {
+    /// Exactly `k` consecutive bits.
+    Full(Letter),
+    /// The (fewer than `k`) bits at the end of the source text.
+    Tail(Letter),
+}
+
+impl Bits {
+    fn letter(&self) -> &Letter {
+        match self {
+            Bits::Full(l) => l,
+            Bits::Tail(l) => l,
+        }
+    }
+}
+
+impl std::fmt::Display for Bits {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Bits::Tail(_) = self {
+            write!(f, "tail:")?;
+        }
+        write!(f, "[{}]", self.letter())
+    }
+}
+
+const FULL_MARKER: u8 = 0;
+const TAIL_MARKER: u8 = 1;
+
+impl Token for Bits {
+    type Tokenizer = BitsTokenizer;
+    type Packer = BitsPacker;
+
+    fn pack<W: std::io::Write>(&self, mut w: W) -> Result<()> {
+        let marker = match self {
+            Bits::Full(_) => FULL_MARKER,
+            Bits::Tail(_) => TAIL_MARKER,
+        };
+        let l = self.letter();
+        let mut data = vec![0u8; (l.bit_count() as usize).div_ceil(8)];
+        for i in 0..l.bit_count() as usize {
+            if l.at(i)? {
+                data[i / 8] |= 0x80 >> (i % 8);
+            }
+        }
+        w.write_all(&[marker, l.bit_count() as u8])?;
+        w.write_all(&data)?;
+        Ok(())
+    }
+
+    fn unpack<R: std::io::Read>(mut r: R) -> Result<Self> {
+        let mut header = [0u8; 2];
+        r.read_exact(&mut header)?;
+        if header[1] > MAX_BITS {
+            return Err(anyhow!("Symbol of {} bits is too long", header[1]));
+        }
+        let mut data = vec![0u8; (header[1] as usize).div_ceil(8)];
+        r.read_exact(&mut data)?;
+        let l = Letter::new(&data, header[1] as u64);
+        match header[0] {
+            FULL_MARKER => Ok(Bits::Full(l)),
+            TAIL_MARKER => Ok(Bits::Tail(l)),
+            m => Err(anyhow!("Unknown bits marker {}", m)),
+        }
+    }
+}
+
+/// Tokenizes text into [`Bits`] of a fixed length.
+pub struct BitsTokenizer {
+    k: u8,
+}
+
+impl BitsTokenizer {
+    /// Create a tokenizer for symbols of `k` bits.
+    ///
+    /// Returns an error unless `k` is between 1 and [`MAX_BITS`].
+    pub fn new(k: u8) -> Result<Self> {
+        if k == 0 || k > MAX_BITS {
+            return Err(anyhow!(
+                "Symbol length must be between 1 and {} bits, got {}",
+                MAX_BITS,
+                k
+            ));
+        }
+        Ok(Self { k })
+    }
+}
+
+impl Tokenizer for BitsTokenizer {
+    type T = Bits;
+    type Iter<R: std::io::Read> = BitsIter<R>;
+
+    fn tokenize<R: std::io::Read>(&self, r: R) -> Result<Self::Iter<R>> {
+        Ok(BitsIter {
+            bytes: ByteTokenizer.tokenize(r)?,
+            k: self.k as u64,
+            current: 0,
+            remaining: 0,
+        })
+    }
+}
+
+/// Provides a method to create a [`Bits`] stream from text.
+#[derive(Clone, Debug)]
+pub struct BitsIter<R: std::io::Read> {
+    bytes: ByteIter<R>,
+    k: u64,
+    // The byte being split into symbols, and the number of its bits not yet
+    // included in a symbol.
+    current: u8,
+    remaining: u8,
+}
+
+impl<R: std::io::Read> std::iter::Iterator for BitsIter<R> {
+    type Item = Result<Bits>;
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut l = Letter::with_capacity(self.k);
+        while l.bit_count() < self.k {
+            if self.remaining == 0 {
+                match self.bytes.next() {
+                    Some(Ok(b)) => {
+                        self.current = u8::from(b);
+                        self.remaining = 8;
+                    }
+                    Some(Err(e)) => return Some(Err(e)),
+                    None if l.bit_count() == 0 => return None,
+                    None => return Some(Ok(Bits::Tail(l))),
+                }
+            }
+            self.remaining -= 1;
+            if self.current & (1 << self.remaining) != 0 {
+                l.push1();
+            } else {
+                l.push0();
+            }
+        }
+        Some(Ok(Bits::Full(l)))
+    }
+}
+
+/// Provides a method to pack a [`Bits`] stream to text.
+#[derive(Clone, Debug, Default)]
+pub struct BitsPacker;
+
+impl TokenPacker for BitsPacker {
+    type T = Bits;
+
+    fn pack<I, W: std::io::Write>(&self, i: I, w: W) -> Result<()>
+    where
+        I: std::iter::Iterator<Item = Self::T>,
+    {
+        let letters: Vec<Bits> = i.collect();
+        let bit_count: u64 = letters.iter().map(|t| t.letter().bit_count()).sum();
+        if !bit_count.is_multiple_of(8) {
+            return Err(anyhow!(
+                "Symbols add up to {} bits, not a whole number of bytes",
+                bit_count
+            ));
+        }
+        crate::code::pack(letters.iter().map(|t| t.letter()), w)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    const TEXT: &str = "
+Ah! well a-day! what evil looks
+Had I from old and young!
+Instead of the cross, the Albatross
+About my neck was hung.
+";
+
+    #[test]
+    fn roundtrip() {
+        for k in 1..=MAX_BITS {
+            let mut r = Cursor::new(TEXT);
+            let d = BitsTokenizer::new(k).unwrap().tokenize(&mut r).unwrap();
+            let i = d.map(|t| t.unwrap());
+            let mut wc: Cursor<Vec<u8>> = Cursor::new(vec![]);
+            BitsPacker.pack(i, &mut wc).unwrap();
+            let got = std::str::from_utf8(&wc.get_ref()[..]).unwrap();
+            assert_eq!(got, TEXT, "k = {}", k);
+        }
+    }
+
+    #[test]
+    fn nibbles_and_tail() {
+        let got: Vec<Bits> = BitsTokenizer::new(3)
+            .unwrap()
+            .tokenize(&[0b1010_0111u8][..])
+            .unwrap()
+            .map(|t| t.unwrap())
+            .collect();
+        assert_eq!(
+            got,
+            vec![
+                Bits::Full(Letter::new(&[0b1010_0000], 3)),
+                Bits::Full(Letter::new(&[0b0010_0000], 3)),
+                Bits::Tail(Letter::new(&[0b1100_0000], 2)),
+            ]
+        );
+    }
+
+    #[test]
+    fn invalid_length() {
+        assert!(BitsTokenizer::new(0).is_err());
+        assert!(BitsTokenizer::new(MAX_BITS + 1).is_err());
+    }
+
+    #[test]
+    fn pack_unpack() {
+        for t in [
+            Bits::Full(Letter::new(&[0b1010_0000], 3)),
+            Bits::Full(Letter::new(&[0xff, 0x0f, 0xf0, 0x01], 32)),
+            Bits::Tail(Letter::new(&[0b1000_0000], 1)),
+        ] {
+            let mut buf = Vec::new();
+            t.pack(&mut buf).unwrap();
+            assert_eq!(Bits::unpack(&buf[..]).unwrap(), t);
+        }
+    }
+}
@@ -0,0 +1,307 @@
+// Copyright 2020 Prathmesh Prabhu
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! byte_pairs module implements [byte pair encoding] (BPE) as a tokenization
+//! scheme.
+//!
+//! The vocabulary starts out as the 256 possible byte values. A table of
+//! [`Merges`] is learned from the source text by repeatedly replacing the most
+//! frequent pair of adjacent [`Symbol`]s with a new symbol, until the
+//! vocabulary reaches the requested size (or no pair occurs more than once).
+//!
+//! The merge table is needed to expand symbols back to text, so it must be
+//! stored alongside the compressed data.
+//!
+//! [byte pair encoding]: https://en.wikipedia.org/wiki/Byte_pair_encoding
+
+use crate::tokens::{Token, TokenPacker, Tokenizer};
+use crate::util::{pack_u64, unpack_u64};
+use anyhow::{anyhow, Error, Result};
+use std::collections::HashMap;
+use std::fmt;
+use std::hash::Hash;
+
+/// A [`Token`] from a byte pair encoding vocabulary.
+///
+/// Symbols `0..256` stand for the corresponding byte. Symbol `256 + i` stands
+/// for the pair of symbols merged by the `i`-th entry in the [`Merges`] table.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct Symbol(u32);
+
+// The number of symbols that stand for a single byte.
+const BYTE_SYMBOL_COUNT: u32 = 256;
+
+impl std::fmt::Display for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "#{}", self.0)
+    }
+}
+
+impl Token for Symbol {
+    type Tokenizer = BytePairTokenizer;
+    type Packer = BytePairPacker;
+
+    fn pack<W: std::io::Write>(&self, mut w: W) -> Result<()> {
+        w.write_all(&self.0.to_be_bytes())?;
+        Ok(())
+    }
+
+    fn unpack<R: std::io::Read>(mut r: R) -> Result<Self> {
+        let mut buf = [0u8; 4];
+        r.read_exact(&mut buf)?;
+        Ok(Symbol(u32::from_be_bytes(buf)))
+    }
+}
+
+/// An ordered table of merged [`Symbol`] pairs.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Merges(Vec<(Symbol, Symbol)>);
+
+impl Merges {
+    /// Learn a merge table from the given text.
+    ///
+    /// The learned table grows the vocabulary to at most `vocabulary_size`
+    /// symbols, including the 256 symbols for single bytes.
+    pub fn learn<R: std::io::Read>(r: R, vocabulary_size: u32) -> Result<Self> {
+        let mut symbols = read_symbols(r)?;
+        let mut merges = Vec::new();
+        while BYTE_SYMBOL_COUNT + (merges.len() as u32) < vocabulary_size {
+            match most_frequent_pair(&symbols) {
+                Some((pair, count)) if count > 1 => {
+                    let merged = Symbol(BYTE_SYMBOL_COUNT + merges.len() as u32);
+                    symbols = merge(&symbols, pair, merged);
+                    merges.push(pair);
+                }
+                _ => break,
+            }
+        }
+        Ok(Self(merges))
+    }
+
+    /// Serialize the table to a [`Write`er](std::io::Write).
+    ///
+    /// Can be deserialized back with [`Self::unpack()`].
+    pub fn pack<W: std::io::Write>(&self, mut w: W) -> Result<()> {
+        w.write_all(&pack_u64(self.0.len() as u64))?;
+        for (a, b) in self.0.iter() {
+            a.pack(&mut w)?;
+            b.pack(&mut w)?;
+        }
+        Ok(())
+    }
+
+    /// Deserialize a table generated with [`Self::pack()`] from a
+    /// [`Read`er](std::io::Read).
+    ///
+    /// Returns an error if a merged pair refers to a symbol that is not
+    /// defined by the preceding entries in the table.
+    pub fn unpack<R: std::io::Read>(mut r: R) -> Result<Self> {
+        let count = unpack_u64(&mut r)?;
+        let mut merges = Vec::new();
+        for i in 0..count {
+            let a = Symbol::unpack(&mut r)?;
+            let b = Symbol::unpack(&mut r)?;
+            let next = u64::from(BYTE_SYMBOL_COUNT) + i;
+            if u64::from(a.0) >= next || u64::from(b.0) >= next {
+                return Err(anyhow!("Merged pair ({}, {}) is not yet defined", a, b));
+            }
+            merges.push((a, b));
+        }
+        Ok(Self(merges))
+    }
+}
+
+fn read_symbols<R: std::io::Read>(mut r: R) -> Result<Vec<Symbol>> {
+    let mut data = Vec::<u8>::new();
+    r.read_to_end(&mut data)?;
+    Ok(data.into_iter().map(|b| Symbol(u32::from(b))).collect())
+}
+
+// Returns the most frequent pair of adjacent symbols and its frequency.
+//
+// Ties are broken in favour of the smaller pair, to keep the learned table
+// deterministic.
+fn most_frequent_pair(symbols: &[Symbol]) -> Option<((Symbol, Symbol), usize)> {
+    let mut counts = HashMap::new();
+    for w in symbols.windows(2) {
+        *counts.entry((w[0], w[1])).or_insert(0) += 1;
+    }
+    counts
+        .into_iter()
+        .max_by(|(p1, c1), (p2, c2)| c1.cmp(c2).then(p2.cmp(p1)))
+}
+
+// Replace non-overlapping occurrences of `pair`, from left to right.
+fn merge(symbols: &[Symbol], pair: (Symbol, Symbol), merged: Symbol) -> Vec<Symbol> {
+    let mut out = Vec::with_capacity(symbols.len());
+    let mut i = 0;
+    while i < symbols.len() {
+        if i + 1 < symbols.len() && (symbols[i], symbols[i + 1]) == pair {
+            out.push(merged);
+            i += 2;
+        } else {
+            out.push(symbols[i]);
+            i += 1;
+        }
+    }
+    out
+}
+
+/// Tokenizes text into [`Symbol`]s by applying a [`Merges`] table.
+pub struct BytePairTokenizer {
+    merges: Merges,
+}
+
+impl BytePairTokenizer {
+    pub fn new(merges: Merges) -> Self {
+        Self { merges }
+    }
+}
+
+impl Tokenizer for BytePairTokenizer {
+    type T = Symbol;
+    type Iter<R: std::io::Read> = SymbolIter;
+
+    fn tokenize<R: std::io::Read>(&self, r: R) -> Result<Self::Iter<R>> {
+        let mut symbols = read_symbols(r)?;
+        for (i, pair) in self.merges.0.iter().enumerate() {
+            symbols = merge(&symbols, *pair, Symbol(BYTE_SYMBOL_COUNT + i as u32));
+        }
+        Ok(SymbolIter(symbols.into_iter()))
+    }
+}
+
+/// Provides a method to create a [`Symbol`] stream from text.
+#[derive(Clone, Debug)]
+pub struct SymbolIter(std::vec::IntoIter<Symbol>);
+
+impl std::iter::Iterator for SymbolIter {
+    type Item = Result<Symbol>;
+    fn next(&mut self) -> Option<Self::Item> {
+        self.0.next().map(Ok)
+    }
+}
+
+/// Provides a method to pack a [`Symbol`] stream to text.
+#[derive(Clone, Debug, Default)]
+pub struct BytePairPacker {
+    // The text each symbol stands for, indexed by symbol.
+    expansions: Vec<Vec<u8>>,
+}
+
+impl BytePairPacker {
+    pub fn new(merges: &Merges) -> Self {
+        let mut expansions: Vec<Vec<u8>> = (0..BYTE_SYMBOL_COUNT).map(|b| vec![b as u8]).collect();
+        for (a, b) in merges.0.iter() {
+            let mut e = expansions[a.0 as usize].clone();
+            e.extend_from_slice(&expansions[b.0 as usize]);
+            expansions.push(e);
+        }
+        Self { expansions }
+    }
+}
+
+impl TokenPacker for BytePairPacker {
+    type T = Symbol;
+
+    fn pack<I, W: std::io::Write>(&self, i: I, mut w: W) -> Result<()>
+    where
+        I: std::iter::Iterator<Item = Self::T>,
+    {
+        for s in i {
+            let e = match self.expansions.get(s.0 as usize) {
+                Some(e) => e,
+                None => return Err(anyhow!("Unknown symbol {}", s)),
+            };
+            if let Err(e) = w.write_all(e) {
+                return Err(Error::new(e));
+            }
+        }
+        w.flush()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    const TEXT: &str = "
+Ah! well a-day! what evil looks
+Had I from old and young!
+Instead of the cross, the Albatross
+About my neck was hung.
+";
+
+    #[test]
+    fn roundtrip() {
+        let merges = Merges::learn(Cursor::new(TEXT), 300).unwrap();
+        let packer = BytePairPacker::new(&merges);
+        let d = BytePairTokenizer::new(merges)
+            .tokenize(Cursor::new(TEXT))
+            .unwrap();
+        let i = d.map(|t| t.unwrap());
+        let mut wc: Cursor<Vec<u8>> = Cursor::new(vec![]);
+        packer.pack(i, &mut wc).unwrap();
+        let got = std::str::from_utf8(&wc.get_ref()[..]).unwrap();
+        assert_eq!(got, TEXT);
+    }
+
+    #[test]
+    fn learn() {
+        let merges = Merges::learn(Cursor::new("abababcab"), 1024).unwrap();
+        let a = Symbol(u32::from(b'a'));
+        let b = Symbol(u32::from(b'b'));
+        // "ab" occurs 4 times, after which "#256#256" occurs twice.
+        assert_eq!(merges, Merges(vec![(a, b), (Symbol(256), Symbol(256))]));
+
+        let got: Vec<Symbol> = BytePairTokenizer::new(merges)
+            .tokenize(Cursor::new("abababcab"))
+            .unwrap()
+            .map(|t| t.unwrap())
+            .collect();
+        assert_eq!(
+            got,
+            vec![
+                Symbol(257),
+                Symbol(256),
+                Symbol(u32::from(b'c')),
+                Symbol(256)
+            ]
+        );
+    }
+
+    #[test]
+    fn learn_limits_vocabulary_size() {
+        assert_eq!(Merges::learn(Cursor::new(TEXT), 256).unwrap().0.len(), 0);
+        assert_eq!(Merges::learn(Cursor::new(TEXT), 260).unwrap().0.len(), 4);
+    }
+
+    #[test]
+    fn merges_pack_unpack() {
+        let merges = Merges::learn(Cursor::new(TEXT), 300).unwrap();
+        let mut buf = Vec::<u8>::new();
+        merges.pack(&mut buf).unwrap();
+        assert_eq!(Merges::unpack(Cursor::new(buf)).unwrap(), merges);
+    }
+
+    #[test]
+    fn unpack_rejects_undefined_symbols() {
+        let merges = Merges(vec![(Symbol(1), Symbol(256))]);
+        let mut buf = Vec::<u8>::new();
+        merges.pack(&mut buf).unwrap();
+        assert!(Merges::unpack(Cursor::new(buf)).is_err());
+    }
+}
@@ -36,8 +36,15 @@ impl Token for Byte {
     type Tokenizer = ByteTokenizer;
     type Packer = BytePacker;
 
-    fn bit_count(&self) -> usize {
-        8
+    fn pack<W: std::io::Write>(&self, mut w: W) -> Result<()> {
+        w.write_all(&[self.0])?;
+        Ok(())
+    }
+
+    fn unpack<R: std::io::Read>(mut r: R) -> Result<Self> {
+        let mut buf = [0u8];
+        r.read_exact(&mut buf)?;
+        Ok(Byte(buf[0]))
     }
 }
 
@@ -47,13 +54,19 @@ impl From<u8> for Byte {
     }
 }
 
+impl From<Byte> for u8 {
+    fn from(b: Byte) -> Self {
+        b.0
+    }
+}
+
 pub struct ByteTokenizer;
 
 impl Tokenizer for ByteTokenizer {
     type T = Byte;
     type Iter<R: std::io::Read> = ByteIter<R>;
 
-    fn tokenize<R: std::io::Read>(r: R) -> Result<Self::Iter<R>> {
+    fn tokenize<R: std::io::Read>(&self, r: R) -> Result<Self::Iter<R>> {
         Ok(ByteIter(r))
     }
 }
@@ -82,7 +95,7 @@ pub struct BytePacker;
 impl TokenPacker for BytePacker {
     type T = Byte;
 
-    fn pack<I, W: std::io::Write>(i: I, mut w: W) -> Result<()>
+    fn pack<I, W: std::io::Write>(&self, i: I, mut w: W) -> Result<()>
     where
         I: std::iter::Iterator<Item = Self::T>,
     {
@@ -112,10 +125,10 @@ About my neck was hung.
     #[test]
     fn roundtrip() {
         let mut r = Cursor::new(TEXT);
-        let d = ByteTokenizer::tokenize(&mut r).unwrap();
+        let d = ByteTokenizer.tokenize(&mut r).unwrap();
         let i = d.map(|t| t.unwrap());
         let mut wc: Cursor<Vec<u8>> = Cursor::new(vec![]);
-        BytePacker::pack(i, &mut wc).unwrap();
+        BytePacker.pack(i, &mut wc).unwrap();
         let got = std::str::from_utf8(&wc.get_ref()[..]).unwrap();
         assert_eq!(got, TEXT);
     }
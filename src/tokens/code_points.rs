@@ -0,0 +1,249 @@
+// Copyright 2020 Prathmesh Prabhu
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! code_points module implements tokenization of a string into Unicode code
+//! points (i.e., [`char`]s).
+//!
+//! Unlike [graphemes](super::graphemes), the stream is produced incrementally:
+//! at most one utf-8 sequence worth of bytes is buffered while iterating over
+//! the stream.
+//!
+//! Bytes that are not part of a valid utf-8 sequence are tokenized as
+//! individual raw bytes, so that any input round-trips losslessly.
+
+use crate::tokens::{Token, TokenPacker, Tokenizer};
+use anyhow::{anyhow, Error, Result};
+use std::convert::TryFrom;
+use std::fmt;
+use std::hash::Hash;
+
+/// A [`Token`] consisting of a single Unicode code point.
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum CodePoint {
+    /// A valid Unicode scalar value.
+    Char(char),
+    /// A single byte that is not part of a valid utf-8 sequence.
+    Raw(u8),
+}
+
+impl Default for CodePoint {
+    fn default() -> Self {
+        CodePoint::Char(char::default())
+    }
+}
+
+impl From<char> for CodePoint {
+    fn from(c: char) -> Self {
+        CodePoint::Char(c)
+    }
+}
+
+impl std::fmt::Display for CodePoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CodePoint::Char(c) => c.fmt(f),
+            CodePoint::Raw(b) => write!(f, "{:#04x}", b),
+        }
+    }
+}
+
+const CHAR_MARKER: u8 = 0;
+const RAW_MARKER: u8 = 1;
+
+impl Token for CodePoint {
+    type Tokenizer = CodePointTokenizer;
+    type Packer = CodePointPacker;
+
+    fn pack<W: std::io::Write>(&self, mut w: W) -> Result<()> {
+        match self {
+            CodePoint::Char(c) => {
+                w.write_all(&[CHAR_MARKER])?;
+                w.write_all(&u32::from(*c).to_be_bytes())?;
+            }
+            CodePoint::Raw(b) => w.write_all(&[RAW_MARKER, *b])?,
+        }
+        Ok(())
+    }
+
+    fn unpack<R: std::io::Read>(mut r: R) -> Result<Self> {
+        let mut marker = [0u8];
+        r.read_exact(&mut marker)?;
+        match marker[0] {
+            CHAR_MARKER => {
+                let mut buf = [0u8; 4];
+                r.read_exact(&mut buf)?;
+                Ok(CodePoint::Char(char::try_from(u32::from_be_bytes(buf))?))
+            }
+            RAW_MARKER => {
+                let mut buf = [0u8];
+                r.read_exact(&mut buf)?;
+                Ok(CodePoint::Raw(buf[0]))
+            }
+            m => Err(anyhow!("Unknown code point marker {}", m)),
+        }
+    }
+}
+
+pub struct CodePointTokenizer;
+
+impl Tokenizer for CodePointTokenizer {
+    type T = CodePoint;
+    type Iter<R: std::io::Read> = CodePointIter<R>;
+
+    fn tokenize<R: std::io::Read>(&self, r: R) -> Result<Self::Iter<R>> {
+        Ok(CodePointIter {
+            r,
+            buf: Vec::with_capacity(MAX_UTF8_LEN),
+            eof: false,
+        })
+    }
+}
+
+// The maximum number of bytes in a single utf-8 sequence.
+const MAX_UTF8_LEN: usize = 4;
+
+/// Provides a method to create a [`CodePoint`] stream from text.
+#[derive(Clone, Debug)]
+pub struct CodePointIter<R: std::io::Read> {
+    r: R,
+    // Bytes read ahead from `r`, but not yet tokenized.
+    buf: Vec<u8>,
+    eof: bool,
+}
+
+impl<R: std::io::Read> CodePointIter<R> {
+    // Read ahead until `buf` holds a complete utf-8 sequence (if any).
+    fn fill(&mut self) -> Result<()> {
+        let mut b: [u8; 1] = [0; 1];
+        while !self.eof && self.buf.len() < MAX_UTF8_LEN {
+            match self.r.read(&mut b[..])? {
+                0 => self.eof = true,
+                1 => self.buf.push(b[0]),
+                l => panic!("read {} bytes in 1 byte buffer", l),
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<R: std::io::Read> std::iter::Iterator for CodePointIter<R> {
+    type Item = Result<CodePoint>;
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Err(e) = self.fill() {
+            return Some(Err(e));
+        }
+        let chunk = self.buf.utf8_chunks().next()?;
+        let t = match chunk.valid().chars().next() {
+            Some(c) => {
+                self.buf.drain(..c.len_utf8());
+                CodePoint::Char(c)
+            }
+            None => CodePoint::Raw(self.buf.remove(0)),
+        };
+        Some(Ok(t))
+    }
+}
+
+/// Provides a method to pack a [`CodePoint`] stream to text.
+#[derive(Clone, Debug, Default)]
+pub struct CodePointPacker;
+
+impl TokenPacker for CodePointPacker {
+    type T = CodePoint;
+
+    fn pack<I, W: std::io::Write>(&self, i: I, mut w: W) -> Result<()>
+    where
+        I: std::iter::Iterator<Item = Self::T>,
+    {
+        let mut buf: [u8; MAX_UTF8_LEN] = [0; MAX_UTF8_LEN];
+        for t in i {
+            let data: &[u8] = match &t {
+                CodePoint::Char(c) => c.encode_utf8(&mut buf).as_bytes(),
+                CodePoint::Raw(b) => std::slice::from_ref(b),
+            };
+            if let Err(e) = w.write_all(data) {
+                return Err(Error::new(e));
+            }
+        }
+        w.flush()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    const TEXT: &str = "
+Ah! well a-day! what evil looks
+Had I from old and young!
+Instead of the cross, the Albatross
+About my neck was hung.
+";
+
+    #[test]
+    fn roundtrip() {
+        let mut r = Cursor::new(TEXT);
+        let d = CodePointTokenizer.tokenize(&mut r).unwrap();
+        let i = d.map(|t| t.unwrap());
+        let mut wc: Cursor<Vec<u8>> = Cursor::new(vec![]);
+        CodePointPacker.pack(i, &mut wc).unwrap();
+        let got = std::str::from_utf8(&wc.get_ref()[..]).unwrap();
+        assert_eq!(got, TEXT);
+    }
+
+    #[test]
+    fn multi_byte_chars() {
+        let r = Cursor::new("a€中😀");
+        let got: Vec<CodePoint> = CodePointTokenizer
+            .tokenize(r)
+            .unwrap()
+            .map(|t| t.unwrap())
+            .collect();
+        assert_eq!(
+            got,
+            vec![
+                CodePoint::Char('a'),
+                CodePoint::Char('€'),
+                CodePoint::Char('中'),
+                CodePoint::Char('😀'),
+            ]
+        );
+    }
+
+    #[test]
+    fn invalid_utf8() {
+        // A truncated sequence for '€' followed by a valid sequence for '€'.
+        let data = vec![0xe2u8, 0x82, 0xe2, 0x82, 0xac, 0xff];
+        let got: Vec<CodePoint> = CodePointTokenizer
+            .tokenize(Cursor::new(&data))
+            .unwrap()
+            .map(|t| t.unwrap())
+            .collect();
+        assert_eq!(
+            got,
+            vec![
+                CodePoint::Raw(0xe2),
+                CodePoint::Raw(0x82),
+                CodePoint::Char('€'),
+                CodePoint::Raw(0xff),
+            ]
+        );
+
+        let mut wc: Cursor<Vec<u8>> = Cursor::new(vec![]);
+        CodePointPacker.pack(got.into_iter(), &mut wc).unwrap();
+        assert_eq!(wc.get_ref(), &data);
+    }
+}
@@ -19,27 +19,33 @@
 //!
 //! [Unicode grapheme clusters]: http://www.unicode.org/reports/tr29/
 
-use super::string_parts;
+use super::string_parts::{self, StringPart};
 use crate::tokens::{Token, Tokenizer};
 
 use anyhow::Result;
-use std::convert::{From, Into};
+use std::convert::From;
 use std::fmt;
 use std::hash::Hash;
 
 /// A [`Token`] consisting of a Unicode grapheme cluster.
 #[derive(Clone, Debug, Default, Eq, Hash, Ord, PartialEq, PartialOrd)]
-pub struct Grapheme(String);
+pub struct Grapheme(StringPart);
 
 impl From<String> for Grapheme {
     fn from(s: String) -> Self {
-        Self(s)
+        Self(StringPart::from(s))
     }
 }
 
-impl Into<String> for Grapheme {
-    fn into(self) -> String {
-        self.0
+impl From<StringPart> for Grapheme {
+    fn from(p: StringPart) -> Self {
+        Self(p)
+    }
+}
+
+impl From<Grapheme> for StringPart {
+    fn from(t: Grapheme) -> Self {
+        t.0
     }
 }
 
@@ -53,8 +59,12 @@ impl Token for Grapheme {
     type Tokenizer = GraphemeTokenizer;
     type Packer = GraphemePacker;
 
-    fn bit_count(&self) -> usize {
-        self.0.len() * 8
+    fn pack<W: std::io::Write>(&self, w: W) -> Result<()> {
+        self.0.pack(w)
+    }
+
+    fn unpack<R: std::io::Read>(r: R) -> Result<Self> {
+        Ok(Self(StringPart::unpack(r)?))
     }
 }
 
@@ -64,7 +74,7 @@ impl Tokenizer for GraphemeTokenizer {
     type T = Grapheme;
     type Iter<R: std::io::Read> = GraphemeIter;
 
-    fn tokenize<R: std::io::Read>(r: R) -> Result<Self::Iter<R>> {
+    fn tokenize<R: std::io::Read>(&self, r: R) -> Result<Self::Iter<R>> {
         GraphemeIter::new(r)
     }
 }
@@ -90,11 +100,24 @@ About my neck was hung.
     #[test]
     fn roundtrip() {
         let mut r = Cursor::new(TEXT);
-        let d = GraphemeTokenizer::tokenize(&mut r).unwrap();
+        let d = GraphemeTokenizer.tokenize(&mut r).unwrap();
         let i = d.map(|t| t.unwrap());
         let mut wc: Cursor<Vec<u8>> = Cursor::new(vec![]);
-        GraphemePacker::pack(i, &mut wc).unwrap();
+        GraphemePacker::default().pack(i, &mut wc).unwrap();
         let got = std::str::from_utf8(&wc.get_ref()[..]).unwrap();
         assert_eq!(got, TEXT);
     }
+
+    #[test]
+    fn roundtrip_invalid_utf8() {
+        let mut data = TEXT.as_bytes().to_vec();
+        data.insert(10, 0xe9);
+        data.extend_from_slice(&[0xe2, 0x82]);
+        let mut r = Cursor::new(&data);
+        let d = GraphemeTokenizer.tokenize(&mut r).unwrap();
+        let i = d.map(|t| t.unwrap());
+        let mut wc: Cursor<Vec<u8>> = Cursor::new(vec![]);
+        GraphemePacker::default().pack(i, &mut wc).unwrap();
+        assert_eq!(wc.get_ref(), &data);
+    }
 }
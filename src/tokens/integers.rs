@@ -0,0 +1,728 @@
+// Copyright 2020 Prathmesh Prabhu
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! integers module implements tokenization of binary data into fixed-width
+//! integers.
+//!
+//! The source text is read as a sequence of integers of the chosen
+//! [`IntegerWidth`] and [`Endianness`]. Two optional transforms help with
+//! numeric data:
+//!
+//! - `delta` replaces each value with its (wrapping) difference from the
+//!   previous value. Sorted lists and slowly varying measurements turn into
+//!   a few small, frequently repeated values.
+//! - `zigzag` maps values, read as signed, so that small magnitudes become
+//!   small numbers (0, -1, 1, -2, ... map to 0, 1, 2, 3, ...). Token values
+//!   are stored as variable-length integers in the encoding, so this keeps
+//!   the encoding small when values (or differences) may be negative.
+//!
+//! The bytes left over at the end of the source text when its length is not a
+//! multiple of the width are tokenized as a single [`Integer::Tail`].
+//!
+//! [`pack()`] compresses the tokens in one of two forms, whichever is smaller:
+//!
+//! - whole values: each distinct value is a token. This suits data with few
+//!   distinct values.
+//! - buckets: as in [`lz77`](crate::lz77), each value is coded as a bucket
+//!   (which is entropy coded) followed by raw extra bits that locate the value
+//!   within the bucket. This keeps the dictionary small when most values are
+//!   distinct, e.g. for sorted ids without `delta`.
+
+use crate::encoding::EncodingScheme;
+use crate::lz77::{bucket, bucket_base};
+use crate::stream;
+use crate::tokens::bytes::{Byte, ByteIter, ByteTokenizer};
+use crate::tokens::{Token, TokenPacker, Tokenizer};
+use crate::util::{pack_u64, unpack_bytes};
+use crate::wav::{BitReader, BitWriter};
+use anyhow::{anyhow, Result};
+use std::convert::TryFrom;
+use std::fmt;
+use std::hash::Hash;
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// The type of integers in the source text.
+#[derive(Copy, Clone, Debug, Eq, Hash, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum IntegerWidth {
+    U8,
+    U16,
+    U32,
+    I32,
+    U64,
+}
+
+impl IntegerWidth {
+    fn bytes(&self) -> usize {
+        match self {
+            IntegerWidth::U8 => 1,
+            IntegerWidth::U16 => 2,
+            IntegerWidth::U32 | IntegerWidth::I32 => 4,
+            IntegerWidth::U64 => 8,
+        }
+    }
+
+    fn mask(&self) -> u64 {
+        match self {
+            IntegerWidth::U64 => u64::MAX,
+            _ => (1u64 << (8 * self.bytes())) - 1,
+        }
+    }
+
+    // Reinterpret the (masked) `value` as a signed integer of this width.
+    fn sign_extend(&self, value: u64) -> i64 {
+        let shift = 64 - 8 * self.bytes();
+        ((value << shift) as i64) >> shift
+    }
+}
+
+pub fn pack_integer_width(width: IntegerWidth) -> u8 {
+    match width {
+        IntegerWidth::U8 => 0u8,
+        IntegerWidth::U16 => 1u8,
+        IntegerWidth::U32 => 2u8,
+        IntegerWidth::I32 => 3u8,
+        IntegerWidth::U64 => 4u8,
+    }
+}
+
+pub fn unpack_integer_width(marker: u8) -> Result<IntegerWidth> {
+    match marker {
+        0u8 => Ok(IntegerWidth::U8),
+        1u8 => Ok(IntegerWidth::U16),
+        2u8 => Ok(IntegerWidth::U32),
+        3u8 => Ok(IntegerWidth::I32),
+        4u8 => Ok(IntegerWidth::U64),
+        m => Err(anyhow!("Unknown integer width marker {}", m)),
+    }
+}
+
+/// The byte order of integers in the source text.
+#[derive(Copy, Clone, Debug, Eq, Hash, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Endianness {
+    Little,
+    Big,
+}
+
+pub fn pack_endianness(endianness: Endianness) -> u8 {
+    match endianness {
+        Endianness::Little => 0u8,
+        Endianness::Big => 1u8,
+    }
+}
+
+pub fn unpack_endianness(marker: u8) -> Result<Endianness> {
+    match marker {
+        0u8 => Ok(Endianness::Little),
+        1u8 => Ok(Endianness::Big),
+        m => Err(anyhow!("Unknown endianness marker {}", m)),
+    }
+}
+
+/// The layout of integers in the source text, and the transforms applied to
+/// them ahead of encoding.
+#[derive(Copy, Clone, Debug, Eq, Hash, PartialEq)]
+pub struct IntegerFormat {
+    pub width: IntegerWidth,
+    pub endianness: Endianness,
+    pub delta: bool,
+    pub zigzag: bool,
+}
+
+impl IntegerFormat {
+    fn read(&self, bytes: &[u8]) -> u64 {
+        let mut buf = [0u8; 8];
+        match self.endianness {
+            Endianness::Little => {
+                buf[..bytes.len()].copy_from_slice(bytes);
+                u64::from_le_bytes(buf)
+            }
+            Endianness::Big => {
+                buf[8 - bytes.len()..].copy_from_slice(bytes);
+                u64::from_be_bytes(buf)
+            }
+        }
+    }
+
+    fn write(&self, value: u64) -> Vec<u8> {
+        let n = self.width.bytes();
+        match self.endianness {
+            Endianness::Little => value.to_le_bytes()[..n].to_vec(),
+            Endianness::Big => value.to_be_bytes()[8 - n..].to_vec(),
+        }
+    }
+
+    // Apply the transforms to `value`, given the previous value in the text.
+    fn forward(&self, value: u64, previous: u64) -> u64 {
+        let mask = self.width.mask();
+        let mut v = value;
+        if self.delta {
+            v = v.wrapping_sub(previous) & mask;
+        }
+        if self.zigzag {
+            let s = self.width.sign_extend(v);
+            v = ((s << 1) ^ (s >> 63)) as u64 & mask;
+        }
+        v
+    }
+
+    // Undo [`Self::forward()`], given the previous value in the text.
+    fn inverse(&self, value: u64, previous: u64) -> u64 {
+        let mask = self.width.mask();
+        let mut v = value;
+        if self.zigzag {
+            v = ((v >> 1) as i64 ^ -((v & 1) as i64)) as u64 & mask;
+        }
+        if self.delta {
+            v = previous.wrapping_add(v) & mask;
+        }
+        v
+    }
+}
+
+/// A [`Token`] consisting of a fixed-width integer.
+#[derive(Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum Integer {
+    /// A value read from `width` bytes of source text (after transforms).
+    Value { value: u64, width: u8 },
+    /// The (fewer than `width`) bytes at the end of the source text.
+    Tail(Vec<Byte>),
+}
+
+impl std::fmt::Display for Integer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Integer::Value { value, .. } => write!(f, "{}", value),
+            Integer::Tail(bytes) => {
+                write!(f, "tail:[")?;
+                for (i, b) in bytes.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, " ")?;
+                    }
+                    b.fmt(f)?;
+                }
+                write!(f, "]")
+            }
+        }
+    }
+}
+
+const VALUE_MARKER: u8 = 0;
+const TAIL_MARKER: u8 = 1;
+
+impl Token for Integer {
+    type Tokenizer = IntegerTokenizer;
+    type Packer = IntegerPacker;
+
+    fn pack<W: std::io::Write>(&self, mut w: W) -> Result<()> {
+        match self {
+            Integer::Value { value, width } => {
+                w.write_all(&[VALUE_MARKER, *width])?;
+                // LEB128, so that small values take few bytes.
+                let mut v = *value;
+                loop {
+                    let b = (v & 0x7f) as u8;
+                    v >>= 7;
+                    if v == 0 {
+                        w.write_all(&[b])?;
+                        break;
+                    }
+                    w.write_all(&[b | 0x80])?;
+                }
+            }
+            Integer::Tail(bytes) => {
+                w.write_all(&[TAIL_MARKER, bytes.len() as u8])?;
+                for b in bytes {
+                    b.pack(&mut w)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn unpack<R: std::io::Read>(mut r: R) -> Result<Self> {
+        let mut header = [0u8; 2];
+        r.read_exact(&mut header)?;
+        match header[0] {
+            VALUE_MARKER => {
+                let mut value = 0u64;
+                let mut shift = 0;
+                loop {
+                    let mut buf = [0u8];
+                    r.read_exact(&mut buf)?;
+                    if shift >= 64 {
+                        return Err(anyhow!("Integer value overflows 64 bits"));
+                    }
+                    value |= ((buf[0] & 0x7f) as u64) << shift;
+                    if buf[0] & 0x80 == 0 {
+                        break;
+                    }
+                    shift += 7;
+                }
+                Ok(Integer::Value {
+                    value,
+                    width: header[1],
+                })
+            }
+            TAIL_MARKER => {
+                let mut bytes = Vec::with_capacity(header[1] as usize);
+                for _ in 0..header[1] {
+                    bytes.push(Byte::unpack(&mut r)?);
+                }
+                Ok(Integer::Tail(bytes))
+            }
+            m => Err(anyhow!("Unknown integer marker {}", m)),
+        }
+    }
+}
+
+/// Tokenizes text into [`Integer`]s of the given format.
+pub struct IntegerTokenizer {
+    format: IntegerFormat,
+}
+
+impl IntegerTokenizer {
+    pub fn new(format: IntegerFormat) -> Self {
+        Self { format }
+    }
+}
+
+impl Tokenizer for IntegerTokenizer {
+    type T = Integer;
+    type Iter<R: std::io::Read> = IntegerIter<R>;
+
+    fn tokenize<R: std::io::Read>(&self, r: R) -> Result<Self::Iter<R>> {
+        Ok(IntegerIter {
+            bytes: ByteTokenizer.tokenize(r)?,
+            format: self.format,
+            previous: 0,
+        })
+    }
+}
+
+/// Provides a method to create an [`Integer`] stream from text.
+#[derive(Clone, Debug)]
+pub struct IntegerIter<R: std::io::Read> {
+    bytes: ByteIter<R>,
+    format: IntegerFormat,
+    previous: u64,
+}
+
+impl<R: std::io::Read> std::iter::Iterator for IntegerIter<R> {
+    type Item = Result<Integer>;
+    fn next(&mut self) -> Option<Self::Item> {
+        let n = self.format.width.bytes();
+        let mut bytes = Vec::with_capacity(n);
+        while bytes.len() < n {
+            match self.bytes.next() {
+                Some(Ok(b)) => bytes.push(b),
+                Some(Err(e)) => return Some(Err(e)),
+                None if bytes.is_empty() => return None,
+                None => return Some(Ok(Integer::Tail(bytes))),
+            }
+        }
+        let raw: Vec<u8> = bytes.into_iter().map(u8::from).collect();
+        let value = self.format.read(&raw);
+        let token = Integer::Value {
+            value: self.format.forward(value, self.previous),
+            width: n as u8,
+        };
+        self.previous = value;
+        Some(Ok(token))
+    }
+}
+
+/// Provides a method to pack an [`Integer`] stream to text.
+pub struct IntegerPacker {
+    format: IntegerFormat,
+}
+
+impl IntegerPacker {
+    pub fn new(format: IntegerFormat) -> Self {
+        Self { format }
+    }
+}
+
+impl TokenPacker for IntegerPacker {
+    type T = Integer;
+
+    fn pack<I, W: std::io::Write>(&self, i: I, mut w: W) -> Result<()>
+    where
+        I: std::iter::Iterator<Item = Self::T>,
+    {
+        let mut previous = 0u64;
+        for t in i {
+            match t {
+                Integer::Value { value, .. } => {
+                    let value = self.format.inverse(value, previous);
+                    w.write_all(&self.format.write(value))?;
+                    previous = value;
+                }
+                Integer::Tail(bytes) => {
+                    let raw: Vec<u8> = bytes.into_iter().map(u8::from).collect();
+                    w.write_all(&raw)?;
+                }
+            }
+        }
+        w.flush()?;
+        Ok(())
+    }
+}
+
+const WHOLE: u8 = 0;
+const BUCKETS: u8 = 1;
+
+/// Compress `values` as a stream of buckets, compressed with an encoding
+/// generated with `encoding_scheme`, followed by the raw extra bits of each
+/// value. The output can be decompressed with [`unpack_buckets()`].
+pub fn pack_buckets<W: std::io::Write>(
+    values: &[u64],
+    encoding_scheme: &EncodingScheme,
+    mut w: W,
+) -> Result<()> {
+    let mut buckets = Vec::with_capacity(values.len());
+    let mut bits = BitWriter::default();
+    for v in values {
+        let (code, bit_count, extra) = bucket(usize::try_from(*v)?);
+        buckets.push(Byte::from(code));
+        bits.push(extra as u64, bit_count);
+    }
+    stream::pack(&buckets, encoding_scheme, &mut w)?;
+    w.write_all(&pack_u64(bits.data.len() as u64))?;
+    w.write_all(&bits.data)?;
+    Ok(())
+}
+
+/// Decompress values compressed with [`pack_buckets()`].
+///
+/// Returns an error if any value is larger than `max_value`.
+pub fn unpack_buckets<R: std::io::Read>(mut r: R, max_value: u64) -> Result<Vec<u64>> {
+    let buckets: Vec<Byte> = stream::unpack(&mut r)?;
+    let mut bits = BitReader {
+        data: unpack_bytes(&mut r)?,
+        offset: 0,
+    };
+    let max_value = usize::try_from(max_value)?;
+    let mut values = Vec::with_capacity(buckets.len());
+    for b in buckets {
+        let (base, bit_count) = bucket_base(u8::from(b), max_value)?;
+        let value = base as u64 + bits.read(bit_count)?;
+        if value > max_value as u64 {
+            return Err(anyhow!("Value {} is larger than {}", value, max_value));
+        }
+        values.push(value);
+    }
+    Ok(values)
+}
+
+/// Compress `data`, read as integers of the given `format`.
+///
+/// Each token stream is compressed with an encoding generated with
+/// `encoding_scheme`. The output can be decompressed with [`unpack()`].
+pub fn pack<W: std::io::Write>(
+    data: &[u8],
+    encoding_scheme: &EncodingScheme,
+    format: IntegerFormat,
+    mut w: W,
+) -> Result<()> {
+    let tokens: Vec<Integer> = IntegerTokenizer::new(format)
+        .tokenize(data)?
+        .collect::<Result<_>>()?;
+
+    let mut whole = vec![WHOLE];
+    stream::pack(&tokens, encoding_scheme, &mut whole)?;
+
+    let mut values = Vec::with_capacity(tokens.len());
+    let mut tail = Vec::new();
+    for t in &tokens {
+        match t {
+            Integer::Value { value, .. } => values.push(*value),
+            Integer::Tail(bytes) => tail.extend(bytes.iter().copied().map(u8::from)),
+        }
+    }
+    let mut bucketed = vec![BUCKETS];
+    pack_buckets(&values, encoding_scheme, &mut bucketed)?;
+    bucketed.extend(pack_u64(tail.len() as u64));
+    bucketed.extend(tail);
+
+    if bucketed.len() < whole.len() {
+        w.write_all(&bucketed)?;
+    } else {
+        w.write_all(&whole)?;
+    }
+    w.flush()?;
+    Ok(())
+}
+
+/// Decompress data compressed with [`pack()`] with the same `format`.
+pub fn unpack<R: std::io::Read, W: std::io::Write>(
+    mut r: R,
+    format: IntegerFormat,
+    w: W,
+) -> Result<()> {
+    let mut buf = [0u8];
+    r.read_exact(&mut buf)?;
+    let tokens: Vec<Integer> = match buf[0] {
+        WHOLE => stream::unpack(&mut r)?,
+        BUCKETS => {
+            let values = unpack_buckets(&mut r, format.width.mask())?;
+            let tail = unpack_bytes(&mut r)?;
+            if tail.len() >= format.width.bytes() {
+                return Err(anyhow!("Integer tail of {} bytes is too long", tail.len()));
+            }
+            let mut tokens: Vec<Integer> = values
+                .into_iter()
+                .map(|value| Integer::Value {
+                    value,
+                    width: format.width.bytes() as u8,
+                })
+                .collect();
+            if !tail.is_empty() {
+                tokens.push(Integer::Tail(tail.into_iter().map(Byte::from).collect()));
+            }
+            tokens
+        }
+        m => return Err(anyhow!("Unknown integer form {}", m)),
+    };
+    IntegerPacker::new(format).pack(tokens.into_iter(), w)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    const TEXT: &str = "
+Ah! well a-day! what evil looks
+Had I from old and young!
+Instead of the cross, the Albatross
+About my neck was hung.
+";
+
+    const WIDTHS: [IntegerWidth; 5] = [
+        IntegerWidth::U8,
+        IntegerWidth::U16,
+        IntegerWidth::U32,
+        IntegerWidth::I32,
+        IntegerWidth::U64,
+    ];
+
+    fn formats() -> Vec<IntegerFormat> {
+        let mut formats = Vec::new();
+        for width in WIDTHS {
+            for endianness in [Endianness::Little, Endianness::Big] {
+                for (delta, zigzag) in [(false, false), (true, false), (false, true), (true, true)]
+                {
+                    formats.push(IntegerFormat {
+                        width,
+                        endianness,
+                        delta,
+                        zigzag,
+                    });
+                }
+            }
+        }
+        formats
+    }
+
+    fn tokenize(format: IntegerFormat, data: &[u8]) -> Vec<Integer> {
+        IntegerTokenizer::new(format)
+            .tokenize(data)
+            .unwrap()
+            .map(|t| t.unwrap())
+            .collect()
+    }
+
+    fn values(tokens: &[Integer]) -> Vec<u64> {
+        tokens
+            .iter()
+            .map(|t| match t {
+                Integer::Value { value, .. } => *value,
+                Integer::Tail(_) => panic!("unexpected tail {}", t),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn roundtrip() {
+        for format in formats() {
+            let mut r = Cursor::new(TEXT);
+            let d = IntegerTokenizer::new(format).tokenize(&mut r).unwrap();
+            let i = d.map(|t| t.unwrap());
+            let mut wc: Cursor<Vec<u8>> = Cursor::new(vec![]);
+            IntegerPacker::new(format).pack(i, &mut wc).unwrap();
+            let got = std::str::from_utf8(&wc.get_ref()[..]).unwrap();
+            assert_eq!(got, TEXT, "{:?}", format);
+        }
+    }
+
+    fn pack_unpack_text(format: IntegerFormat, data: &[u8]) -> Vec<u8> {
+        let mut buf = Vec::new();
+        pack(data, &EncodingScheme::Huffman, format, &mut buf).unwrap();
+        let mut got = Vec::new();
+        unpack(Cursor::new(&buf), format, &mut got).unwrap();
+        assert_eq!(got, data, "{:?}", format);
+        buf
+    }
+
+    #[test]
+    fn front_end_roundtrip() {
+        for format in formats() {
+            pack_unpack_text(format, TEXT.as_bytes());
+            pack_unpack_text(format, &[]);
+        }
+    }
+
+    // Sorted ids are all distinct, so they must be bucketed rather than coded
+    // as whole values.
+    #[test]
+    fn distinct_values() {
+        let format = IntegerFormat {
+            width: IntegerWidth::U32,
+            endianness: Endianness::Little,
+            delta: false,
+            zigzag: false,
+        };
+        let data: Vec<u8> = (0..20000u32)
+            .map(|i| 1_000_000 + 3 * i + i % 2)
+            .flat_map(|v| v.to_le_bytes())
+            .collect();
+        let buf = pack_unpack_text(format, &data);
+        assert_eq!(buf[0], BUCKETS);
+        assert!(buf.len() < data.len(), "{} bytes", buf.len());
+    }
+
+    #[test]
+    fn few_values() {
+        let format = IntegerFormat {
+            width: IntegerWidth::U16,
+            endianness: Endianness::Big,
+            delta: false,
+            zigzag: false,
+        };
+        let data: Vec<u8> = (0..5000u16)
+            .map(|i| [1000, 2000, 3000][usize::from(i % 7 % 3)])
+            .flat_map(|v: u16| v.to_be_bytes())
+            .collect();
+        assert_eq!(pack_unpack_text(format, &data)[0], WHOLE);
+    }
+
+    #[test]
+    fn endianness() {
+        let mut format = IntegerFormat {
+            width: IntegerWidth::U16,
+            endianness: Endianness::Little,
+            delta: false,
+            zigzag: false,
+        };
+        assert_eq!(values(&tokenize(format, &[1, 2])), vec![0x0201]);
+        format.endianness = Endianness::Big;
+        assert_eq!(values(&tokenize(format, &[1, 2])), vec![0x0102]);
+    }
+
+    #[test]
+    fn delta() {
+        let format = IntegerFormat {
+            width: IntegerWidth::U8,
+            endianness: Endianness::Little,
+            delta: true,
+            zigzag: false,
+        };
+        assert_eq!(
+            values(&tokenize(format, &[10, 12, 14, 13])),
+            vec![10, 2, 2, 255]
+        );
+    }
+
+    #[test]
+    fn delta_zigzag() {
+        let format = IntegerFormat {
+            width: IntegerWidth::I32,
+            endianness: Endianness::Big,
+            delta: true,
+            zigzag: true,
+        };
+        let data: Vec<u8> = [100i32, 101, 99, 99]
+            .iter()
+            .flat_map(|v| v.to_be_bytes())
+            .collect();
+        assert_eq!(values(&tokenize(format, &data)), vec![200, 2, 3, 0]);
+    }
+
+    #[test]
+    fn tail() {
+        let format = IntegerFormat {
+            width: IntegerWidth::U32,
+            endianness: Endianness::Little,
+            delta: false,
+            zigzag: false,
+        };
+        let got = tokenize(format, b"abcdef");
+        assert_eq!(
+            got,
+            vec![
+                Integer::Value {
+                    value: 0x64636261,
+                    width: 4
+                },
+                Integer::Tail(vec![Byte::from(b'e'), Byte::from(b'f')]),
+            ]
+        );
+    }
+
+    #[test]
+    fn pack_unpack() {
+        for t in [
+            Integer::Value { value: 0, width: 1 },
+            Integer::Value {
+                value: 300,
+                width: 2,
+            },
+            Integer::Value {
+                value: u64::MAX,
+                width: 8,
+            },
+            Integer::Tail(vec![Byte::from(7)]),
+        ] {
+            let mut buf = Vec::new();
+            t.pack(&mut buf).unwrap();
+            assert_eq!(Integer::unpack(&buf[..]).unwrap(), t);
+        }
+    }
+
+    #[test]
+    fn integer_width_markers() {
+        for width in WIDTHS {
+            assert_eq!(
+                unpack_integer_width(pack_integer_width(width)).unwrap(),
+                width
+            );
+        }
+        assert!(unpack_integer_width(5).is_err());
+    }
+
+    #[test]
+    fn endianness_markers() {
+        for endianness in [Endianness::Little, Endianness::Big] {
+            assert_eq!(
+                unpack_endianness(pack_endianness(endianness)).unwrap(),
+                endianness
+            );
+        }
+        assert!(unpack_endianness(2).is_err());
+    }
+}
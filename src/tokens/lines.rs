@@ -0,0 +1,196 @@
+// Copyright 2020 Prathmesh Prabhu
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! lines module implements tokenization of a string into whole lines.
+//!
+//! Each line, including its `\n` terminator, is a single token. This suits
+//! text that repeats whole lines, e.g. heartbeats and stack frames in logs.
+//!
+//! Lines that occur fewer than `min_count` times in the source text would each
+//! add an entry to the encoding without being reused. Such rare lines are
+//! spilled into [Unicode grapheme clusters] instead, which share entries with
+//! all other spilled lines.
+//!
+//! Bytes that are not part of valid utf-8 text are split into individual raw
+//! byte tokens (see [`StringPart`]).
+//!
+//! [Unicode grapheme clusters]: http://www.unicode.org/reports/tr29/
+
+use super::string_parts::{self, StringPart};
+use crate::tokens::{Token, Tokenizer};
+use anyhow::Result;
+use std::collections::{HashMap, HashSet};
+use std::convert::From;
+use std::fmt;
+use std::hash::Hash;
+use unicode_segmentation::UnicodeSegmentation;
+
+/// A [`Token`] consisting of a line, or a grapheme cluster of a rare line.
+#[derive(Clone, Debug, Default, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct Line(StringPart);
+
+impl From<StringPart> for Line {
+    fn from(p: StringPart) -> Self {
+        Self(p)
+    }
+}
+
+impl From<Line> for StringPart {
+    fn from(l: Line) -> Self {
+        l.0
+    }
+}
+
+impl std::fmt::Display for Line {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl Token for Line {
+    type Tokenizer = LineTokenizer;
+    type Packer = LinePacker;
+
+    fn pack<W: std::io::Write>(&self, w: W) -> Result<()> {
+        self.0.pack(w)
+    }
+
+    fn unpack<R: std::io::Read>(r: R) -> Result<Self> {
+        Ok(Self(StringPart::unpack(r)?))
+    }
+}
+
+fn lines(s: &str) -> Vec<&str> {
+    s.split_inclusive('\n').collect()
+}
+
+/// Tokenizes text into [`Line`]s.
+pub struct LineTokenizer {
+    // Lines that are tokenized whole.
+    frequent: HashSet<String>,
+}
+
+impl LineTokenizer {
+    /// Create a tokenizer that keeps lines occurring at least `min_count` times
+    /// in the source text in `r` whole.
+    pub fn learn<R: std::io::Read>(r: R, min_count: u32) -> Result<Self> {
+        let mut counts = HashMap::<String, u32>::new();
+        for p in string_parts::split(r, lines)? {
+            if let StringPart::Text(l) = p {
+                *counts.entry(l).or_default() += 1;
+            }
+        }
+        Ok(Self {
+            frequent: counts
+                .into_iter()
+                .filter(|(_, c)| *c >= min_count)
+                .map(|(l, _)| l)
+                .collect(),
+        })
+    }
+}
+
+impl Tokenizer for LineTokenizer {
+    type T = Line;
+    type Iter<R: std::io::Read> = LineIter;
+
+    fn tokenize<R: std::io::Read>(&self, r: R) -> Result<Self::Iter<R>> {
+        let mut parts = Vec::new();
+        for p in string_parts::split(r, lines)? {
+            match p {
+                StringPart::Text(l) if !self.frequent.contains(&l) => {
+                    for g in l.graphemes(true) {
+                        parts.push(StringPart::Text(g.to_owned()));
+                    }
+                }
+                p => parts.push(p),
+            }
+        }
+        Ok(LineIter::from_parts(parts))
+    }
+}
+
+/// Provides a method to create a [`Line`] stream from text.
+pub type LineIter = string_parts::StringPartsIter<Line>;
+
+/// Provides a method to pack a [`Line`] stream to text.
+pub type LinePacker = string_parts::StringPartsPacker<Line>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tokens::TokenPacker;
+    use std::io::Cursor;
+
+    const TEXT: &str = "heartbeat ok
+heartbeat ok
+request failed
+heartbeat ok
+no newline at end";
+
+    fn tokenize(min_count: u32, text: &str) -> Vec<String> {
+        LineTokenizer::learn(text.as_bytes(), min_count)
+            .unwrap()
+            .tokenize(text.as_bytes())
+            .unwrap()
+            .map(|t| t.unwrap().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn roundtrip() {
+        for min_count in 0..4 {
+            let mut r = Cursor::new(TEXT);
+            let d = LineTokenizer::learn(TEXT.as_bytes(), min_count)
+                .unwrap()
+                .tokenize(&mut r)
+                .unwrap();
+            let i = d.map(|t| t.unwrap());
+            let mut wc: Cursor<Vec<u8>> = Cursor::new(vec![]);
+            LinePacker::default().pack(i, &mut wc).unwrap();
+            let got = std::str::from_utf8(&wc.get_ref()[..]).unwrap();
+            assert_eq!(got, TEXT);
+        }
+    }
+
+    #[test]
+    fn whole_lines() {
+        assert_eq!(
+            tokenize(1, TEXT),
+            vec![
+                "heartbeat ok\n",
+                "heartbeat ok\n",
+                "request failed\n",
+                "heartbeat ok\n",
+                "no newline at end",
+            ]
+        );
+    }
+
+    #[test]
+    fn rare_lines_spill() {
+        let got = tokenize(2, "ok\nok\nbad\n");
+        assert_eq!(got, vec!["ok\n", "ok\n", "b", "a", "d", "\n"]);
+    }
+
+    #[test]
+    fn invalid_utf8() {
+        let data = b"ok\n\xffok\nok\n";
+        let tokenizer = LineTokenizer::learn(&data[..], 2).unwrap();
+        let i = tokenizer.tokenize(&data[..]).unwrap().map(|t| t.unwrap());
+        let mut got = Vec::new();
+        LinePacker::default().pack(i, &mut got).unwrap();
+        assert_eq!(got, data);
+    }
+}
@@ -0,0 +1,223 @@
+// Copyright 2020 Prathmesh Prabhu
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! n_grams module implements tokenization of a string into non-overlapping
+//! byte [n-grams].
+//!
+//! Encoding n-grams amounts to encoding the n-th extension of the byte source.
+//! For correlated data, the per-byte cost of the extended source gets closer
+//! to the entropy rate as `n` grows (at the cost of a larger dictionary).
+//!
+//! The bytes left over at the end of the source text when its length is not a
+//! multiple of `n` are tokenized as a single [`NGram::Tail`].
+//!
+//! [n-grams]: https://en.wikipedia.org/wiki/N-gram
+
+use crate::tokens::bytes::{Byte, ByteIter, BytePacker, ByteTokenizer};
+use crate::tokens::{Token, TokenPacker, Tokenizer};
+use anyhow::{anyhow, Result};
+use std::fmt;
+use std::hash::Hash;
+
+/// A [`Token`] consisting of consecutive bytes of data.
+#[derive(Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum NGram {
+    /// Exactly `n` consecutive bytes.
+    Full(Vec<Byte>),
+    /// The (fewer than `n`) bytes at the end of the source text.
+    Tail(Vec<Byte>),
+}
+
+impl NGram {
+    fn bytes(&self) -> &Vec<Byte> {
+        match self {
+            NGram::Full(b) => b,
+            NGram::Tail(b) => b,
+        }
+    }
+}
+
+impl Default for NGram {
+    fn default() -> Self {
+        NGram::Full(Vec::new())
+    }
+}
+
+impl std::fmt::Display for NGram {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let NGram::Tail(_) = self {
+            write!(f, "tail:")?;
+        }
+        write!(f, "[")?;
+        for (i, b) in self.bytes().iter().enumerate() {
+            if i > 0 {
+                write!(f, " ")?;
+            }
+            b.fmt(f)?;
+        }
+        write!(f, "]")
+    }
+}
+
+const FULL_MARKER: u8 = 0;
+const TAIL_MARKER: u8 = 1;
+
+impl Token for NGram {
+    type Tokenizer = NGramTokenizer;
+    type Packer = NGramPacker;
+
+    fn pack<W: std::io::Write>(&self, mut w: W) -> Result<()> {
+        let marker = match self {
+            NGram::Full(_) => FULL_MARKER,
+            NGram::Tail(_) => TAIL_MARKER,
+        };
+        w.write_all(&[marker, self.bytes().len() as u8])?;
+        for b in self.bytes() {
+            b.pack(&mut w)?;
+        }
+        Ok(())
+    }
+
+    fn unpack<R: std::io::Read>(mut r: R) -> Result<Self> {
+        let mut header = [0u8; 2];
+        r.read_exact(&mut header)?;
+        let mut bytes = Vec::with_capacity(header[1] as usize);
+        for _ in 0..header[1] {
+            bytes.push(Byte::unpack(&mut r)?);
+        }
+        match header[0] {
+            FULL_MARKER => Ok(NGram::Full(bytes)),
+            TAIL_MARKER => Ok(NGram::Tail(bytes)),
+            m => Err(anyhow!("Unknown n-gram marker {}", m)),
+        }
+    }
+}
+
+/// Tokenizes text into [`NGram`]s of a fixed length.
+pub struct NGramTokenizer {
+    n: u8,
+}
+
+impl NGramTokenizer {
+    /// Create a tokenizer for n-grams of length `n`.
+    ///
+    /// Returns an error if `n` is 0.
+    pub fn new(n: u8) -> Result<Self> {
+        if n == 0 {
+            return Err(anyhow!("n-gram length must be positive"));
+        }
+        Ok(Self { n })
+    }
+}
+
+impl Tokenizer for NGramTokenizer {
+    type T = NGram;
+    type Iter<R: std::io::Read> = NGramIter<R>;
+
+    fn tokenize<R: std::io::Read>(&self, r: R) -> Result<Self::Iter<R>> {
+        Ok(NGramIter {
+            bytes: ByteTokenizer.tokenize(r)?,
+            n: self.n as usize,
+        })
+    }
+}
+
+/// Provides a method to create a [`NGram`] stream from text.
+#[derive(Clone, Debug)]
+pub struct NGramIter<R: std::io::Read> {
+    bytes: ByteIter<R>,
+    n: usize,
+}
+
+impl<R: std::io::Read> std::iter::Iterator for NGramIter<R> {
+    type Item = Result<NGram>;
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut bytes = Vec::with_capacity(self.n);
+        while bytes.len() < self.n {
+            match self.bytes.next() {
+                Some(Ok(b)) => bytes.push(b),
+                Some(Err(e)) => return Some(Err(e)),
+                None if bytes.is_empty() => return None,
+                None => return Some(Ok(NGram::Tail(bytes))),
+            }
+        }
+        Some(Ok(NGram::Full(bytes)))
+    }
+}
+
+/// Provides a method to pack a [`NGram`] stream to text.
+#[derive(Clone, Debug, Default)]
+pub struct NGramPacker;
+
+impl TokenPacker for NGramPacker {
+    type T = NGram;
+
+    fn pack<I, W: std::io::Write>(&self, i: I, w: W) -> Result<()>
+    where
+        I: std::iter::Iterator<Item = Self::T>,
+    {
+        BytePacker.pack(i.flat_map(|t| t.bytes().clone()), w)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    const TEXT: &str = "
+Ah! well a-day! what evil looks
+Had I from old and young!
+Instead of the cross, the Albatross
+About my neck was hung.
+";
+
+    #[test]
+    fn roundtrip() {
+        for n in 1..8 {
+            let mut r = Cursor::new(TEXT);
+            let d = NGramTokenizer::new(n).unwrap().tokenize(&mut r).unwrap();
+            let i = d.map(|t| t.unwrap());
+            let mut wc: Cursor<Vec<u8>> = Cursor::new(vec![]);
+            NGramPacker.pack(i, &mut wc).unwrap();
+            let got = std::str::from_utf8(&wc.get_ref()[..]).unwrap();
+            assert_eq!(got, TEXT);
+        }
+    }
+
+    #[test]
+    fn tail() {
+        let r = Cursor::new("abcde");
+        let got: Vec<NGram> = NGramTokenizer::new(2)
+            .unwrap()
+            .tokenize(r)
+            .unwrap()
+            .map(|t| t.unwrap())
+            .collect();
+        let bytes = |s: &str| s.bytes().map(Byte::from).collect::<Vec<Byte>>();
+        assert_eq!(
+            got,
+            vec![
+                NGram::Full(bytes("ab")),
+                NGram::Full(bytes("cd")),
+                NGram::Tail(bytes("e")),
+            ]
+        );
+    }
+
+    #[test]
+    fn zero_length() {
+        assert!(NGramTokenizer::new(0).is_err());
+    }
+}
@@ -0,0 +1,217 @@
+// Copyright 2020 Prathmesh Prabhu
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! rules module implements tokenization of a string with a user-supplied list
+//! of [regular expression] rules.
+//!
+//! At each position in the source text, the rules are tried in order and the
+//! first rule that matches a non-empty prefix of the remaining text produces
+//! the next token. When no rule matches, the next byte is emitted as a single
+//! byte token. For example, the rules `[A-Za-z_]\w*`, `[0-9]+` and `\s+`
+//! split source code into identifiers, numbers and whitespace, with all other
+//! bytes (mostly punctuation) as single byte tokens.
+//!
+//! Rules are matched against raw bytes, so the source text need not be valid
+//! utf-8 (see [`regex::bytes`] for the supported syntax).
+//!
+//! [regular expression]: https://docs.rs/regex/latest/regex/#syntax
+
+use crate::tokens::{Token, TokenPacker, Tokenizer};
+use crate::util::{pack_u64, unpack_bytes};
+use anyhow::{anyhow, Result};
+use regex::bytes::Regex;
+use std::fmt;
+use std::hash::Hash;
+
+/// A [`Token`] consisting of the text matched by a rule, or a single byte.
+#[derive(Clone, Debug, Default, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct Lexeme(Vec<u8>);
+
+impl From<Vec<u8>> for Lexeme {
+    fn from(v: Vec<u8>) -> Self {
+        Self(v)
+    }
+}
+
+impl From<Lexeme> for Vec<u8> {
+    fn from(l: Lexeme) -> Self {
+        l.0
+    }
+}
+
+impl std::fmt::Display for Lexeme {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", String::from_utf8_lossy(&self.0))
+    }
+}
+
+impl Token for Lexeme {
+    type Tokenizer = RuleTokenizer;
+    type Packer = LexemePacker;
+
+    fn pack<W: std::io::Write>(&self, mut w: W) -> Result<()> {
+        w.write_all(&pack_u64(self.0.len() as u64))?;
+        w.write_all(&self.0)?;
+        Ok(())
+    }
+
+    fn unpack<R: std::io::Read>(r: R) -> Result<Self> {
+        Ok(Lexeme(unpack_bytes(r)?))
+    }
+}
+
+/// Tokenizes text into [`Lexeme`]s with a list of rules.
+pub struct RuleTokenizer {
+    rules: Vec<Regex>,
+}
+
+impl RuleTokenizer {
+    /// Create a tokenizer from a list of regular expressions.
+    ///
+    /// Returns an error if any of the `patterns` is not a valid regular
+    /// expression.
+    pub fn new<S: AsRef<str>>(patterns: &[S]) -> Result<Self> {
+        let rules = patterns
+            .iter()
+            .map(|p| {
+                // Only match at the start of the remaining text.
+                Regex::new(&format!(r"\A(?:{})", p.as_ref()))
+                    .map_err(|e| anyhow!("Invalid rule {:?}: {}", p.as_ref(), e))
+            })
+            .collect::<Result<_>>()?;
+        Ok(Self { rules })
+    }
+}
+
+impl Tokenizer for RuleTokenizer {
+    type T = Lexeme;
+    type Iter<R: std::io::Read> = LexemeIter;
+
+    fn tokenize<R: std::io::Read>(&self, mut r: R) -> Result<Self::Iter<R>> {
+        let mut data = Vec::new();
+        r.read_to_end(&mut data)?;
+        let mut lexemes = Vec::new();
+        let mut rest = &data[..];
+        while !rest.is_empty() {
+            let len = self
+                .rules
+                .iter()
+                .filter_map(|rule| rule.find(rest))
+                .map(|m| m.end())
+                .find(|len| *len > 0)
+                .unwrap_or(1);
+            let (lexeme, tail) = rest.split_at(len);
+            lexemes.push(Lexeme(lexeme.to_vec()));
+            rest = tail;
+        }
+        Ok(LexemeIter(lexemes.into_iter()))
+    }
+}
+
+/// Provides a method to create a [`Lexeme`] stream from text.
+#[derive(Clone, Debug)]
+pub struct LexemeIter(std::vec::IntoIter<Lexeme>);
+
+impl std::iter::Iterator for LexemeIter {
+    type Item = Result<Lexeme>;
+    fn next(&mut self) -> Option<Self::Item> {
+        self.0.next().map(Ok)
+    }
+}
+
+/// Provides a method to pack a [`Lexeme`] stream to text.
+#[derive(Clone, Debug, Default)]
+pub struct LexemePacker;
+
+impl TokenPacker for LexemePacker {
+    type T = Lexeme;
+
+    fn pack<I, W: std::io::Write>(&self, i: I, mut w: W) -> Result<()>
+    where
+        I: std::iter::Iterator<Item = Self::T>,
+    {
+        for t in i {
+            w.write_all(&t.0)?;
+        }
+        w.flush()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    const TEXT: &str = "
+Ah! well a-day! what evil looks
+Had I from old and young!
+Instead of the cross, the Albatross
+About my neck was hung.
+";
+
+    const RULES: [&str; 3] = [r"[A-Za-z_]\w*", "[0-9]+", r"\s+"];
+
+    fn tokenize(patterns: &[&str], data: &[u8]) -> Vec<String> {
+        RuleTokenizer::new(patterns)
+            .unwrap()
+            .tokenize(data)
+            .unwrap()
+            .map(|t| t.unwrap().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn roundtrip() {
+        let mut r = Cursor::new(TEXT);
+        let d = RuleTokenizer::new(&RULES)
+            .unwrap()
+            .tokenize(&mut r)
+            .unwrap();
+        let i = d.map(|t| t.unwrap());
+        let mut wc: Cursor<Vec<u8>> = Cursor::new(vec![]);
+        LexemePacker.pack(i, &mut wc).unwrap();
+        let got = std::str::from_utf8(&wc.get_ref()[..]).unwrap();
+        assert_eq!(got, TEXT);
+    }
+
+    #[test]
+    fn rules_and_fallback() {
+        assert_eq!(
+            tokenize(&RULES, b"x1 = 42;"),
+            vec!["x1", " ", "=", " ", "42", ";"]
+        );
+    }
+
+    #[test]
+    fn first_rule_wins() {
+        assert_eq!(tokenize(&["a", "ab"], b"ab"), vec!["a", "b"]);
+        assert_eq!(tokenize(&["ab", "a"], b"ab"), vec!["ab"]);
+    }
+
+    #[test]
+    fn empty_matches_fall_back() {
+        assert_eq!(tokenize(&["x*"], b"yxx"), vec!["y", "xx"]);
+    }
+
+    #[test]
+    fn no_rules() {
+        assert_eq!(tokenize(&[], b"ab"), vec!["a", "b"]);
+    }
+
+    #[test]
+    fn invalid_rule() {
+        assert!(RuleTokenizer::new(&["("]).is_err());
+    }
+}
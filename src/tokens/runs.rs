@@ -0,0 +1,217 @@
+// Copyright 2020 Prathmesh Prabhu
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! runs module implements [run-length] tokenization of a string.
+//!
+//! Each token is a (value, length) pair for a run of identical bytes. Runs
+//! longer than [`MAX_RUN_LENGTH`] are split into several tokens, so that the
+//! number of distinct tokens (and hence the size of the encoding) stays
+//! bounded. A long run is then coded as repeats of the same maximal token,
+//! each of which gets a short code.
+//!
+//! [run-length]: https://en.wikipedia.org/wiki/Run-length_encoding
+
+use crate::tokens::bytes::{Byte, ByteIter, ByteTokenizer};
+use crate::tokens::{Token, TokenPacker, Tokenizer};
+use anyhow::{anyhow, Result};
+use std::fmt;
+use std::hash::Hash;
+
+/// The maximum number of bytes in a single [`Run`].
+pub const MAX_RUN_LENGTH: u8 = u8::MAX;
+
+/// A [`Token`] consisting of a run of identical bytes.
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct Run {
+    value: Byte,
+    length: u8,
+}
+
+impl Run {
+    /// Create a run of `length` copies of `value`.
+    ///
+    /// Returns an error if `length` is 0.
+    pub fn new(value: u8, length: u8) -> Result<Self> {
+        if length == 0 {
+            return Err(anyhow!("Run length must be positive"));
+        }
+        Ok(Self {
+            value: Byte::from(value),
+            length,
+        })
+    }
+}
+
+impl Default for Run {
+    fn default() -> Self {
+        Self {
+            value: Byte::default(),
+            length: 1,
+        }
+    }
+}
+
+impl std::fmt::Display for Run {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}x{}", self.value, self.length)
+    }
+}
+
+impl Token for Run {
+    type Tokenizer = RunTokenizer;
+    type Packer = RunPacker;
+
+    fn pack<W: std::io::Write>(&self, mut w: W) -> Result<()> {
+        w.write_all(&[u8::from(self.value), self.length])?;
+        Ok(())
+    }
+
+    fn unpack<R: std::io::Read>(mut r: R) -> Result<Self> {
+        let mut buf = [0u8; 2];
+        r.read_exact(&mut buf)?;
+        Run::new(buf[0], buf[1])
+    }
+}
+
+/// Tokenizes text into [`Run`]s.
+pub struct RunTokenizer;
+
+impl Tokenizer for RunTokenizer {
+    type T = Run;
+    type Iter<R: std::io::Read> = RunIter<R>;
+
+    fn tokenize<R: std::io::Read>(&self, r: R) -> Result<Self::Iter<R>> {
+        Ok(RunIter {
+            bytes: ByteTokenizer.tokenize(r)?,
+            next: None,
+        })
+    }
+}
+
+/// Provides a method to create a [`Run`] stream from text.
+#[derive(Clone, Debug)]
+pub struct RunIter<R: std::io::Read> {
+    bytes: ByteIter<R>,
+    // The first byte of the next run, already read from `bytes`.
+    next: Option<Byte>,
+}
+
+impl<R: std::io::Read> std::iter::Iterator for RunIter<R> {
+    type Item = Result<Run>;
+    fn next(&mut self) -> Option<Self::Item> {
+        let value = match self.next.take() {
+            Some(b) => b,
+            None => match self.bytes.next()? {
+                Ok(b) => b,
+                Err(e) => return Some(Err(e)),
+            },
+        };
+        let mut length = 1u8;
+        while length < MAX_RUN_LENGTH {
+            match self.bytes.next() {
+                Some(Ok(b)) if b == value => length += 1,
+                Some(Ok(b)) => {
+                    self.next = Some(b);
+                    break;
+                }
+                Some(Err(e)) => return Some(Err(e)),
+                None => break,
+            }
+        }
+        Some(Ok(Run { value, length }))
+    }
+}
+
+/// Provides a method to pack a [`Run`] stream to text.
+#[derive(Clone, Debug, Default)]
+pub struct RunPacker;
+
+impl TokenPacker for RunPacker {
+    type T = Run;
+
+    fn pack<I, W: std::io::Write>(&self, i: I, mut w: W) -> Result<()>
+    where
+        I: std::iter::Iterator<Item = Self::T>,
+    {
+        for t in i {
+            w.write_all(&vec![u8::from(t.value); t.length as usize])?;
+        }
+        w.flush()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    const TEXT: &str = "
+Ah! well a-day! what evil looks
+Had I from old and young!
+Instead of the cross, the Albatross
+About my neck was hung.
+";
+
+    fn tokenize(data: &[u8]) -> Vec<Run> {
+        RunTokenizer
+            .tokenize(data)
+            .unwrap()
+            .map(|t| t.unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn roundtrip() {
+        let mut r = Cursor::new(TEXT);
+        let d = RunTokenizer.tokenize(&mut r).unwrap();
+        let i = d.map(|t| t.unwrap());
+        let mut wc: Cursor<Vec<u8>> = Cursor::new(vec![]);
+        RunPacker.pack(i, &mut wc).unwrap();
+        let got = std::str::from_utf8(&wc.get_ref()[..]).unwrap();
+        assert_eq!(got, TEXT);
+    }
+
+    #[test]
+    fn runs() {
+        assert_eq!(
+            tokenize(b"aaabcc"),
+            vec![
+                Run::new(b'a', 3).unwrap(),
+                Run::new(b'b', 1).unwrap(),
+                Run::new(b'c', 2).unwrap(),
+            ]
+        );
+        assert_eq!(tokenize(b""), vec![]);
+    }
+
+    #[test]
+    fn long_run() {
+        let data = vec![0u8; 600];
+        assert_eq!(
+            tokenize(&data),
+            vec![
+                Run::new(0, 255).unwrap(),
+                Run::new(0, 255).unwrap(),
+                Run::new(0, 90).unwrap(),
+            ]
+        );
+    }
+
+    #[test]
+    fn zero_length() {
+        assert!(Run::new(0, 0).is_err());
+        assert!(Run::unpack(&[7u8, 0][..]).is_err());
+    }
+}
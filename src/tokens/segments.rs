@@ -0,0 +1,351 @@
+// Copyright 2020 Prathmesh Prabhu
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! segments module implements tokenization of a string into [`Segment`]s that
+//! (approximately) minimize the length of the encoded text.
+//!
+//! Greedy tokenization (e.g. longest match first) is not optimal once tokens
+//! have different code lengths. Instead, given the cost in bits of each
+//! candidate token, the cheapest segmentation of the source text is found by
+//! dynamic programming.
+//!
+//! The cost of a token depends on the [`Encoding`](crate::encoding::Encoding),
+//! which in turn depends on the segmentation. [`SegmentTokenizer::learn()`]
+//! alternates between the two for a few rounds:
+//!
+//! ```ascii-art
+//! Candidates --> Segmentation --> Model --> Encoding --> Costs -+
+//!                      ^                                        |
+//!                      +----------------------------------------+
+//! ```
+//!
+//! Candidate tokens are the words, the byte n-grams that occur more than once,
+//! and single bytes (so that any source text can be segmented).
+
+use crate::encoding::{new_encoder, EncodingScheme};
+use crate::model;
+use crate::tokens::{Token, TokenPacker, Tokenizer};
+use crate::util::{pack_u64, unpack_bytes};
+use anyhow::{Error, Result};
+use std::collections::HashMap;
+use std::fmt;
+use std::hash::Hash;
+
+/// A [`Token`] consisting of an arbitrary sequence of bytes.
+#[derive(Clone, Debug, Default, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct Segment(Vec<u8>);
+
+impl From<&[u8]> for Segment {
+    fn from(data: &[u8]) -> Self {
+        Self(data.to_vec())
+    }
+}
+
+impl std::fmt::Display for Segment {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        String::from_utf8_lossy(&self.0).fmt(f)
+    }
+}
+
+impl Token for Segment {
+    type Tokenizer = SegmentTokenizer;
+    type Packer = SegmentPacker;
+
+    fn pack<W: std::io::Write>(&self, mut w: W) -> Result<()> {
+        w.write_all(&pack_u64(self.0.len() as u64))?;
+        w.write_all(&self.0)?;
+        Ok(())
+    }
+
+    fn unpack<R: std::io::Read>(r: R) -> Result<Self> {
+        Ok(Self(unpack_bytes(r)?))
+    }
+}
+
+// Longest word considered as a candidate token.
+const MAX_WORD_LEN: usize = 32;
+// Longest byte n-gram considered as a candidate token.
+const MAX_N_GRAM_LEN: usize = 4;
+// Cost of a code for every candidate before the first round.
+const INITIAL_CODE_COST: u64 = 8;
+// Approximate overhead of adding a token to the encoding dictionary: the token
+// length and letter length are each serialized as a u64, along with the
+// letter itself.
+const DICTIONARY_ENTRY_COST: u64 = 2 * 64 + 16;
+
+// Approximate cost of the dictionary entry for `segment`, shared among its
+// `uses` occurrences in the text.
+fn amortized_entry_cost(segment: &[u8], uses: usize) -> u64 {
+    (DICTIONARY_ENTRY_COST + 8 * segment.len() as u64) / std::cmp::max(uses, 1) as u64
+}
+
+/// Tokenizes text into the cheapest sequence of [`Segment`]s, given the cost
+/// of each candidate segment.
+pub struct SegmentTokenizer {
+    // Cost in bits of each candidate segment.
+    costs: HashMap<Vec<u8>, u64>,
+    // Length of the longest candidate segment.
+    max_len: usize,
+}
+
+impl SegmentTokenizer {
+    /// Learn segment costs from the given text.
+    ///
+    /// Each of the `rounds` rounds segments the text using the costs from the
+    /// previous round, and derives new costs from the code lengths of the
+    /// resulting encoding (generated with `encoding_scheme`).
+    pub fn learn<R: std::io::Read>(
+        mut r: R,
+        encoding_scheme: &EncodingScheme,
+        rounds: u8,
+    ) -> Result<Self> {
+        let mut data = Vec::<u8>::new();
+        r.read_to_end(&mut data)?;
+        let mut tokenizer = Self::with_costs(candidates(&data).into_iter().map(|(c, count)| {
+            let cost = INITIAL_CODE_COST + amortized_entry_cost(&c, count);
+            (c, cost)
+        }));
+        let mut previous = None;
+        for _ in 0..rounds {
+            let segments = tokenizer.segment(&data);
+            if segments.is_empty() || previous.as_ref() == Some(&segments) {
+                break;
+            }
+            let mut uses: HashMap<&[u8], usize> = HashMap::new();
+            for s in segments.iter() {
+                *uses.entry(&s.0).or_insert(0) += 1;
+            }
+            let encoding = new_encoder(encoding_scheme, model::from(segments.iter().cloned()))?;
+            let max_code_len = encoding
+                .map()
+                .values()
+                .map(|l| l.bit_count())
+                .max()
+                .unwrap_or(0);
+            // Candidates missing from the encoding get a pessimistic estimate
+            // of their cost, so that they are only used when that pays off.
+            let costs: Vec<(Vec<u8>, u64)> = tokenizer
+                .costs
+                .keys()
+                .map(|c| {
+                    let cost = match encoding.map().get(&Segment(c.clone())) {
+                        Some(l) => l.bit_count() + amortized_entry_cost(c, uses[c.as_slice()]),
+                        None => max_code_len + 1 + amortized_entry_cost(c, 1),
+                    };
+                    (c.clone(), cost)
+                })
+                .collect();
+            tokenizer = Self::with_costs(costs.into_iter());
+            previous = Some(segments);
+        }
+        Ok(tokenizer)
+    }
+
+    fn with_costs<I: Iterator<Item = (Vec<u8>, u64)>>(costs: I) -> Self {
+        let costs: HashMap<Vec<u8>, u64> = costs.collect();
+        let max_len = costs.keys().map(|c| c.len()).max().unwrap_or(1);
+        Self { costs, max_len }
+    }
+
+    // Find the cheapest segmentation of `data`.
+    //
+    // Single bytes are always valid segments, so that a segmentation exists
+    // for any input. Bytes without a known cost are assumed to be expensive.
+    fn segment(&self, data: &[u8]) -> Vec<Segment> {
+        // best[i] is the (cost, length of the last segment) of the cheapest
+        // segmentation of data[..i].
+        let mut best: Vec<(u64, usize)> = vec![(u64::MAX, 0); data.len() + 1];
+        best[0] = (0, 0);
+        for i in 1..=data.len() {
+            for len in 1..=std::cmp::min(i, self.max_len) {
+                let cost = match self.costs.get(&data[i - len..i]) {
+                    Some(c) => *c,
+                    None if len == 1 => INITIAL_CODE_COST + amortized_entry_cost(&[0], 1),
+                    None => continue,
+                };
+                let total = best[i - len].0.saturating_add(cost);
+                if total < best[i].0 {
+                    best[i] = (total, len);
+                }
+            }
+        }
+
+        let mut segments = Vec::new();
+        let mut i = data.len();
+        while i > 0 {
+            let len = best[i].1;
+            segments.push(Segment::from(&data[i - len..i]));
+            i -= len;
+        }
+        segments.reverse();
+        segments
+    }
+}
+
+// Words are maximal runs of ascii alphanumeric characters or non-ascii bytes
+// (i.e. parts of multi-byte utf-8 sequences).
+fn is_word_byte(b: u8) -> bool {
+    b.is_ascii_alphanumeric() || b == b'_' || b >= 0x80
+}
+
+// Returns the candidate segments, along with the number of (possibly
+// overlapping) occurrences of each in `data`.
+fn candidates(data: &[u8]) -> HashMap<Vec<u8>, usize> {
+    let mut counts: HashMap<&[u8], usize> = HashMap::new();
+    for w in data
+        .split(|b| !is_word_byte(*b))
+        .filter(|w| w.len() > 1 && w.len() <= MAX_WORD_LEN)
+    {
+        *counts.entry(w).or_insert(0) += 1;
+    }
+    for n in 2..=MAX_N_GRAM_LEN {
+        for g in data.windows(n) {
+            *counts.entry(g).or_insert(0) += 1;
+        }
+    }
+
+    for b in data.windows(1) {
+        *counts.entry(b).or_insert(0) += 1;
+    }
+
+    counts
+        .into_iter()
+        .filter(|(c, count)| c.len() == 1 || *count > 1)
+        .map(|(c, count)| (c.to_vec(), count))
+        .collect()
+}
+
+impl Tokenizer for SegmentTokenizer {
+    type T = Segment;
+    type Iter<R: std::io::Read> = SegmentIter;
+
+    fn tokenize<R: std::io::Read>(&self, mut r: R) -> Result<Self::Iter<R>> {
+        let mut data = Vec::<u8>::new();
+        r.read_to_end(&mut data)?;
+        Ok(SegmentIter(self.segment(&data).into_iter()))
+    }
+}
+
+/// Provides a method to create a [`Segment`] stream from text.
+#[derive(Clone, Debug)]
+pub struct SegmentIter(std::vec::IntoIter<Segment>);
+
+impl std::iter::Iterator for SegmentIter {
+    type Item = Result<Segment>;
+    fn next(&mut self) -> Option<Self::Item> {
+        self.0.next().map(Ok)
+    }
+}
+
+/// Provides a method to pack a [`Segment`] stream to text.
+#[derive(Clone, Debug, Default)]
+pub struct SegmentPacker;
+
+impl TokenPacker for SegmentPacker {
+    type T = Segment;
+
+    fn pack<I, W: std::io::Write>(&self, i: I, mut w: W) -> Result<()>
+    where
+        I: std::iter::Iterator<Item = Self::T>,
+    {
+        for s in i {
+            if let Err(e) = w.write_all(&s.0) {
+                return Err(Error::new(e));
+            }
+        }
+        w.flush()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    const TEXT: &str = "
+Ah! well a-day! what evil looks
+Had I from old and young!
+Instead of the cross, the Albatross
+About my neck was hung.
+";
+
+    #[test]
+    fn roundtrip() {
+        let tokenizer =
+            SegmentTokenizer::learn(Cursor::new(TEXT), &EncodingScheme::Huffman, 3).unwrap();
+        let d = tokenizer.tokenize(Cursor::new(TEXT)).unwrap();
+        let i = d.map(|t| t.unwrap());
+        let mut wc: Cursor<Vec<u8>> = Cursor::new(vec![]);
+        SegmentPacker.pack(i, &mut wc).unwrap();
+        let got = std::str::from_utf8(&wc.get_ref()[..]).unwrap();
+        assert_eq!(got, TEXT);
+    }
+
+    #[test]
+    fn empty() {
+        let tokenizer =
+            SegmentTokenizer::learn(Cursor::new(""), &EncodingScheme::Huffman, 3).unwrap();
+        assert_eq!(tokenizer.tokenize(Cursor::new("")).unwrap().count(), 0);
+    }
+
+    #[test]
+    fn segment_minimizes_cost() {
+        let tokenizer = SegmentTokenizer::with_costs(
+            vec![
+                (b"a".to_vec(), 4),
+                (b"b".to_vec(), 4),
+                (b"c".to_vec(), 4),
+                (b"ab".to_vec(), 6),
+                (b"bc".to_vec(), 1),
+            ]
+            .into_iter(),
+        );
+        // Greedy longest match would pick "ab" + "c" for a cost of 10.
+        assert_eq!(
+            tokenizer.segment(b"abc"),
+            vec![Segment::from(&b"a"[..]), Segment::from(&b"bc"[..])]
+        );
+    }
+
+    #[test]
+    fn segment_unknown_bytes() {
+        let tokenizer = SegmentTokenizer::with_costs(vec![(b"ab".to_vec(), 1)].into_iter());
+        assert_eq!(
+            tokenizer.segment(b"abx"),
+            vec![Segment::from(&b"ab"[..]), Segment::from(&b"x"[..])]
+        );
+    }
+
+    #[test]
+    fn learn_uses_repeated_segments() {
+        let text = "the cat and the hat and the bat";
+        let tokenizer =
+            SegmentTokenizer::learn(Cursor::new(text), &EncodingScheme::Huffman, 3).unwrap();
+        let segments: Vec<Segment> = tokenizer
+            .tokenize(Cursor::new(text))
+            .unwrap()
+            .map(|t| t.unwrap())
+            .collect();
+        // Each repeated word is a single segment.
+        let count = |word: &[u8]| {
+            segments
+                .iter()
+                .filter(|s| **s == Segment::from(word))
+                .count()
+        };
+        assert_eq!(count(b"the"), 3);
+        assert_eq!(count(b"and"), 2);
+    }
+}
@@ -13,42 +13,146 @@
 // limitations under the License.
 
 //! The stream makes zero copies internally while iterating over the stream.
+//!
+//! Source text is assumed to be utf-8 encoded, but invalid utf-8 sequences do
+//! not cause tokenization to fail. Each byte that is not part of valid utf-8
+//! text is emitted as a [`StringPart::Raw`] part instead, so that any input
+//! round-trips losslessly.
 
 use crate::tokens::{Token, TokenPacker};
+use crate::util::{pack_u64, unpack_bytes};
 use unicode_segmentation::{self, UnicodeSegmentation};
 
-use anyhow::{Error, Result};
+use anyhow::{anyhow, Error, Result};
 use log::{log_enabled, trace, Level};
 use std::convert::{From, Into};
+use std::fmt;
 use std::marker::PhantomData;
 
+/// A single part of the source text.
+#[derive(Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum StringPart {
+    /// A part of valid utf-8 text.
+    Text(String),
+    /// A single byte that is not part of valid utf-8 text.
+    Raw(u8),
+}
+
+impl StringPart {
+    /// The source text contained in this part.
+    pub fn as_bytes(&self) -> &[u8] {
+        match self {
+            StringPart::Text(s) => s.as_bytes(),
+            StringPart::Raw(b) => std::slice::from_ref(b),
+        }
+    }
+
+    /// Write this part such that it can be read back with [`Self::unpack()`].
+    pub fn pack<W: std::io::Write>(&self, mut w: W) -> Result<()> {
+        match self {
+            StringPart::Text(s) => {
+                w.write_all(&[TEXT_MARKER])?;
+                w.write_all(&pack_u64(s.len() as u64))?;
+                w.write_all(s.as_bytes())?;
+            }
+            StringPart::Raw(b) => w.write_all(&[RAW_MARKER, *b])?,
+        }
+        Ok(())
+    }
+
+    /// Read a single part previously written with [`Self::pack()`].
+    pub fn unpack<R: std::io::Read>(mut r: R) -> Result<Self> {
+        let mut marker = [0u8];
+        r.read_exact(&mut marker)?;
+        match marker[0] {
+            TEXT_MARKER => Ok(StringPart::Text(String::from_utf8(unpack_bytes(&mut r)?)?)),
+            RAW_MARKER => {
+                let mut buf = [0u8];
+                r.read_exact(&mut buf)?;
+                Ok(StringPart::Raw(buf[0]))
+            }
+            m => Err(anyhow!("Unknown string part marker {}", m)),
+        }
+    }
+}
+
+const TEXT_MARKER: u8 = 0;
+const RAW_MARKER: u8 = 1;
+
+impl Default for StringPart {
+    fn default() -> Self {
+        StringPart::Text(String::new())
+    }
+}
+
+impl From<String> for StringPart {
+    fn from(s: String) -> Self {
+        StringPart::Text(s)
+    }
+}
+
+impl fmt::Display for StringPart {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StringPart::Text(s) => s.fmt(f),
+            StringPart::Raw(b) => write!(f, "{:#04x}", b),
+        }
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct StringPartsIter<S>(Option<std::vec::IntoIter<S>>)
 where
-    S: From<String> + Token;
+    S: From<StringPart> + Token;
 
 impl<S> StringPartsIter<S>
 where
-    S: From<String> + Token,
+    S: From<StringPart> + Token,
 {
-    pub fn new<R>(mut r: R) -> Result<Self>
+    pub fn new<R>(r: R) -> Result<Self>
     where
         R: std::io::Read,
     {
-        let mut data = Vec::<u8>::new();
-        r.read_to_end(&mut data)?;
-        let s = std::str::from_utf8(&data)?;
-        let mut parts = Vec::<S>::new();
-        for g in s.graphemes(true) {
-            parts.push(S::from(g.to_owned()));
+        Ok(Self::from_parts(split(r, |s| s.graphemes(true).collect())?))
+    }
+
+    /// Create a stream over already split `parts`.
+    pub fn from_parts(parts: Vec<StringPart>) -> Self {
+        Self(Some(
+            parts
+                .into_iter()
+                .map(S::from)
+                .collect::<Vec<S>>()
+                .into_iter(),
+        ))
+    }
+}
+
+/// Split the source text into [`StringPart`]s.
+///
+/// Valid utf-8 text is split with `splitter`, and each byte that is not part of
+/// valid utf-8 text becomes a separate [`StringPart::Raw`] part.
+pub fn split<R>(mut r: R, splitter: fn(&str) -> Vec<&str>) -> Result<Vec<StringPart>>
+where
+    R: std::io::Read,
+{
+    let mut data = Vec::<u8>::new();
+    r.read_to_end(&mut data)?;
+    let mut parts = Vec::<StringPart>::new();
+    for chunk in data.utf8_chunks() {
+        for p in splitter(chunk.valid()) {
+            parts.push(StringPart::Text(p.to_owned()));
+        }
+        for b in chunk.invalid() {
+            parts.push(StringPart::Raw(*b));
         }
-        Ok(Self(Some(parts.into_iter())))
     }
+    Ok(parts)
 }
 
 impl<S> std::iter::Iterator for StringPartsIter<S>
 where
-    S: From<String> + Token,
+    S: From<StringPart> + Token,
 {
     type Item = Result<S>;
 
@@ -77,22 +181,22 @@ where
 #[derive(Clone, Copy, Debug, Default)]
 pub struct StringPartsPacker<S>(PhantomData<S>)
 where
-    S: Into<String> + Token;
+    S: Into<StringPart> + Token;
 
 impl<S> TokenPacker for StringPartsPacker<S>
 where
-    S: Into<String> + Token,
+    S: Into<StringPart> + Token,
 {
     type T = S;
 
-    fn pack<I, W: std::io::Write>(i: I, mut w: W) -> Result<()>
+    fn pack<I, W: std::io::Write>(&self, i: I, mut w: W) -> Result<()>
     where
         I: std::iter::Iterator<Item = Self::T>,
     {
         for s in i {
-            let buf: String = s.into();
-            trace!("pack: |{}|", &buf);
-            if let Err(e) = w.write_all(buf.as_bytes()) {
+            let part: StringPart = s.into();
+            trace!("pack: |{}|", &part);
+            if let Err(e) = w.write_all(part.as_bytes()) {
                 return Err(Error::new(e));
             }
         }
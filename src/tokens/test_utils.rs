@@ -17,7 +17,7 @@
 use crate::tokens::{TokenPacker, Tokenizer};
 
 use super::Token;
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 use std::fmt;
 
 /// A [`Token`] that wraps i32 values.
@@ -38,8 +38,15 @@ impl Token for I32Token {
     type Tokenizer = I32Tokenizer;
     type Packer = I32TokenPacker;
 
-    fn bit_count(&self) -> usize {
-        4
+    fn pack<W: std::io::Write>(&self, mut w: W) -> Result<()> {
+        w.write_all(&self.0.to_be_bytes())?;
+        Ok(())
+    }
+
+    fn unpack<R: std::io::Read>(mut r: R) -> Result<Self> {
+        let mut buf = [0u8; 4];
+        r.read_exact(&mut buf)?;
+        Ok(I32Token(i32::from_be_bytes(buf)))
     }
 }
 
@@ -49,7 +56,7 @@ impl Tokenizer for I32Tokenizer {
     type T = I32Token;
     type Iter<R: std::io::Read> = I32TokenIter<R>;
 
-    fn tokenize<R: std::io::Read>(r: R) -> Result<Self::Iter<R>> {
+    fn tokenize<R: std::io::Read>(&self, r: R) -> Result<Self::Iter<R>> {
         Ok(I32TokenIter(r))
     }
 }
@@ -59,7 +66,17 @@ pub struct I32TokenIter<R: std::io::Read>(R);
 impl<R: std::io::Read> std::iter::Iterator for I32TokenIter<R> {
     type Item = Result<I32Token>;
     fn next(&mut self) -> Option<Self::Item> {
-        panic!("Not implemented!");
+        let mut buf = [0u8; 4];
+        let mut read = 0;
+        while read < buf.len() {
+            match self.0.read(&mut buf[read..]) {
+                Ok(0) if read == 0 => return None,
+                Ok(0) => return Some(Err(anyhow!("Truncated I32Token"))),
+                Ok(n) => read += n,
+                Err(e) => return Some(Err(e.into())),
+            }
+        }
+        Some(Ok(I32Token(i32::from_be_bytes(buf))))
     }
 }
 
@@ -68,10 +85,13 @@ pub struct I32TokenPacker;
 impl TokenPacker for I32TokenPacker {
     type T = I32Token;
 
-    fn pack<I, W: std::io::Write>(_i: I, _w: W) -> Result<()>
+    fn pack<I, W: std::io::Write>(&self, i: I, mut w: W) -> Result<()>
     where
         I: std::iter::Iterator<Item = Self::T>,
     {
-        panic!("Not implemented!")
+        for t in i {
+            w.write_all(&t.0.to_be_bytes())?;
+        }
+        Ok(())
     }
 }
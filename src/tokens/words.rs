@@ -21,11 +21,11 @@
 //!
 //! [Unicode words]: http://www.unicode.org/reports/tr29/
 
-use super::string_parts;
+use super::string_parts::{self, StringPart};
 use crate::tokens::{Token, Tokenizer};
 
 use anyhow::Result;
-use std::convert::{From, Into};
+use std::convert::From;
 use std::fmt;
 use std::hash::Hash;
 
@@ -34,17 +34,23 @@ use std::hash::Hash;
 /// Tokenizing to `Word` is lossy because non-word characters (e.g.
 /// punctuations) are lost.
 #[derive(Clone, Debug, Default, Eq, Hash, Ord, PartialEq, PartialOrd)]
-pub struct Word(String);
+pub struct Word(StringPart);
 
 impl From<String> for Word {
     fn from(s: String) -> Self {
-        Self(s)
+        Self(StringPart::from(s))
     }
 }
 
-impl Into<String> for Word {
-    fn into(self) -> String {
-        self.0
+impl From<StringPart> for Word {
+    fn from(p: StringPart) -> Self {
+        Self(p)
+    }
+}
+
+impl From<Word> for StringPart {
+    fn from(t: Word) -> Self {
+        t.0
     }
 }
 
@@ -58,8 +64,12 @@ impl Token for Word {
     type Tokenizer = WordTokenizer;
     type Packer = WordPacker;
 
-    fn bit_count(&self) -> usize {
-        self.0.len() * 8
+    fn pack<W: std::io::Write>(&self, w: W) -> Result<()> {
+        self.0.pack(w)
+    }
+
+    fn unpack<R: std::io::Read>(r: R) -> Result<Self> {
+        Ok(Self(StringPart::unpack(r)?))
     }
 }
 
@@ -69,7 +79,7 @@ impl Tokenizer for WordTokenizer {
     type T = Word;
     type Iter<R: std::io::Read> = WordIter;
 
-    fn tokenize<R: std::io::Read>(r: R) -> Result<Self::Iter<R>> {
+    fn tokenize<R: std::io::Read>(&self, r: R) -> Result<Self::Iter<R>> {
         WordIter::new(r)
     }
 }
@@ -95,13 +105,13 @@ About my neck was hung.
     #[test]
     fn roundtrip() {
         let mut r = Cursor::new(TEXT);
-        let d = WordTokenizer::tokenize(&mut r).unwrap();
+        let d = WordTokenizer.tokenize(&mut r).unwrap();
         let i = d.map(|i| match i {
             Err(e) => panic!("{}", e),
             Ok(b) => b,
         });
         let mut wc: Cursor<Vec<u8>> = Cursor::new(vec![]);
-        WordPacker::pack(i, &mut wc).unwrap();
+        WordPacker::default().pack(i, &mut wc).unwrap();
         let got = std::str::from_utf8(&wc.get_ref()[..]).unwrap();
         assert_eq!(got, TEXT);
     }
@@ -0,0 +1,210 @@
+// Copyright 2020 Prathmesh Prabhu
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! This module provides reversible transforms applied to the source text
+//! ahead of tokenization.
+//!
+//! A transform does not compress the text by itself. Instead, it rearranges
+//! the text so that the tokenization and encoding schemes that follow compress
+//! it better.
+//!
+//! The [`Transform`] enum lists the supported transforms. The building blocks
+//! are exported from sub-modules: [bocu], [bwt], [move_to_front] and
+//! [zero_runs].
+//!
+//! [bocu]: bocu/index.html
+//! [bwt]: bwt/index.html
+//! [move_to_front]: move_to_front/index.html
+//! [zero_runs]: zero_runs/index.html
+
+use crate::util::{pack_u64, unpack_u64};
+use anyhow::{anyhow, Result};
+use std::convert::TryFrom;
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+pub mod bocu;
+pub mod bwt;
+pub mod move_to_front;
+pub mod zero_runs;
+
+/// Reversible transforms of the source text supported by this library.
+#[derive(Copy, Clone, Debug, Default, Eq, Hash, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Transform {
+    /// Leave the source text unchanged.
+    #[default]
+    None,
+    /// Transform blocks of the source text as in [bzip2].
+    ///
+    /// Each block of (up to) `block_size` bytes is transformed with the
+    /// Burrows-Wheeler transform, followed by the move-to-front transform and
+    /// run-length coding of 0s. The output consists mostly of small byte
+    /// values, and is best compressed with the `Byte` tokenization scheme.
+    ///
+    /// [bzip2]: https://en.wikipedia.org/wiki/Bzip2
+    Bwt {
+        /// The number of source text bytes in each block. Must be positive.
+        block_size: u32,
+    },
+    /// Code each code point of UTF-8 text as its difference from the block
+    /// of the previous character, in the manner of [BOCU-1].
+    ///
+    /// Text in small non-Latin alphabets (e.g. Cyrillic or Devanagari) takes
+    /// one byte per character. The output is best compressed with the `Byte`
+    /// tokenization scheme. Text that is not valid UTF-8 is left unchanged.
+    ///
+    /// [BOCU-1]: https://www.unicode.org/notes/tn6/
+    Bocu,
+}
+
+impl Transform {
+    /// Transform the source text.
+    pub fn forward(&self, data: Vec<u8>) -> Result<Vec<u8>> {
+        match self {
+            Transform::None => Ok(data),
+            Transform::Bwt { block_size } => {
+                if *block_size == 0 {
+                    return Err(anyhow!("Block size must be positive"));
+                }
+                let mut out = Vec::new();
+                for block in data.chunks(*block_size as usize) {
+                    let (transformed, primary) = bwt::forward(block);
+                    let coded = zero_runs::forward(&move_to_front::forward(&transformed));
+                    out.extend_from_slice(&pack_u64(coded.len() as u64));
+                    out.extend_from_slice(&pack_u64(primary as u64));
+                    out.extend_from_slice(&coded);
+                }
+                Ok(out)
+            }
+            Transform::Bocu => Ok(bocu::forward(&data)),
+        }
+    }
+
+    /// Recover the source text from the output of [`Self::forward()`].
+    pub fn inverse(&self, data: Vec<u8>) -> Result<Vec<u8>> {
+        match self {
+            Transform::None => Ok(data),
+            Transform::Bwt { .. } => {
+                let mut r = &data[..];
+                let mut out = Vec::new();
+                while !r.is_empty() {
+                    let len = usize::try_from(unpack_u64(&mut r)?)?;
+                    let primary = usize::try_from(unpack_u64(&mut r)?)?;
+                    if len > r.len() {
+                        return Err(anyhow!("Truncated block of {} bytes", len));
+                    }
+                    let (coded, rest) = r.split_at(len);
+                    r = rest;
+                    let transformed = move_to_front::inverse(&zero_runs::inverse(coded)?);
+                    out.extend(bwt::inverse(&transformed, primary)?);
+                }
+                Ok(out)
+            }
+            Transform::Bocu => bocu::inverse(&data),
+        }
+    }
+}
+
+pub fn pack_transform<W: std::io::Write>(transform: Transform, mut w: W) -> Result<()> {
+    match transform {
+        Transform::None => w.write_all(&[0u8])?,
+        Transform::Bwt { block_size } => {
+            w.write_all(&[1u8])?;
+            w.write_all(&block_size.to_be_bytes())?;
+        }
+        Transform::Bocu => w.write_all(&[2u8])?,
+    }
+    Ok(())
+}
+
+pub fn unpack_transform<R: std::io::Read>(mut r: R) -> Result<Transform> {
+    let mut buf = [0u8];
+    r.read_exact(&mut buf)?;
+    match buf[0] {
+        0u8 => Ok(Transform::None),
+        1u8 => {
+            let mut buf = [0u8; 4];
+            r.read_exact(&mut buf)?;
+            Ok(Transform::Bwt {
+                block_size: u32::from_be_bytes(buf),
+            })
+        }
+        2u8 => Ok(Transform::Bocu),
+        m => Err(anyhow!("Unknown transform marker {}", m)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TEXT: &str = "
+Ah! well a-day! what evil looks
+Had I from old and young!
+Instead of the cross, the Albatross
+About my neck was hung.
+";
+
+    #[test]
+    fn roundtrip() {
+        for transform in [
+            Transform::None,
+            Transform::Bwt { block_size: 1 },
+            Transform::Bwt { block_size: 7 },
+            Transform::Bwt {
+                block_size: 1 << 20,
+            },
+            Transform::Bocu,
+        ] {
+            let data = transform.forward(TEXT.as_bytes().to_vec()).unwrap();
+            let got = transform.inverse(data).unwrap();
+            assert_eq!(std::str::from_utf8(&got).unwrap(), TEXT);
+        }
+    }
+
+    #[test]
+    fn bwt_makes_small_values() {
+        let text = TEXT.repeat(10);
+        let transform = Transform::Bwt {
+            block_size: 1 << 20,
+        };
+        let data = transform.forward(text.as_bytes().to_vec()).unwrap();
+        assert!(data.len() < text.len() / 2);
+        let small = data.iter().filter(|b| **b < 4).count();
+        assert!(small > data.len() / 2);
+    }
+
+    #[test]
+    fn zero_block_size() {
+        let transform = Transform::Bwt { block_size: 0 };
+        assert!(transform.forward(TEXT.as_bytes().to_vec()).is_err());
+    }
+
+    #[test]
+    fn pack_unpack() {
+        for transform in [
+            Transform::None,
+            Transform::Bwt {
+                block_size: 900_000,
+            },
+            Transform::Bocu,
+        ] {
+            let mut buf = Vec::new();
+            pack_transform(transform, &mut buf).unwrap();
+            assert_eq!(unpack_transform(&buf[..]).unwrap(), transform);
+        }
+    }
+}
@@ -0,0 +1,264 @@
+// Copyright 2020 Prathmesh Prabhu
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! bocu module implements a code point difference transform of UTF-8 text,
+//! in the manner of [BOCU-1].
+//!
+//! Each code point is coded as its difference from a base, the middle of the
+//! block of 128 code points (or of the CJK or Hangul ranges) of the previous
+//! character. Text in a small alphabet (e.g. Cyrillic or Devanagari) takes 2
+//! or 3 bytes per character in UTF-8, but only 1 byte per character here, and
+//! each letter is always coded as the same byte.
+//!
+//! Differences are coded with a lead byte, followed by up to 3 trail bytes:
+//!
+//! | lead          | difference                   |
+//! | ------------- | ---------------------------- |
+//! | `0x00..=0x20` | none, the code point itself  |
+//! | `0x21`        | 4 bytes, negative            |
+//! | `0x22..=0x27` | 3 bytes, negative            |
+//! | `0x28..=0x4f` | 2 bytes, negative            |
+//! | `0x50..=0x8f` | 1 byte, -64 to -1            |
+//! | `0x90..=0xcf` | 1 byte, 0 to 63              |
+//! | `0xd0..=0xf7` | 2 bytes, positive            |
+//! | `0xf8..=0xfe` | 3 bytes, positive            |
+//! | `0xff`        | 4 bytes, positive            |
+//!
+//! Code points up to `0x20` (controls and space) are coded as themselves and
+//! leave the base unchanged, so that words of a script separated by spaces
+//! stay in the same block.
+//!
+//! The output starts with a flag byte that records whether the source text is
+//! valid UTF-8. Other text is passed through unchanged.
+//!
+//! [BOCU-1]: https://www.unicode.org/notes/tn6/
+
+use anyhow::{anyhow, Result};
+use std::convert::TryFrom;
+
+// Flags for the mode of the output.
+const RAW: u8 = 0;
+const CODED: u8 = 1;
+
+const INITIAL_BASE: i64 = 0x40;
+const MAX_DIRECT: u32 = 0x20;
+const MIDDLE: i64 = 0x90;
+const SINGLE: i64 = 64;
+
+// The number of lead bytes for each length of positive and negative
+// differences, and the number of differences they cover.
+const POSITIVE_LEADS: [(i64, i64); 3] = [(0xd0, 40), (0xf8, 7), (0xff, 1)];
+const NEGATIVE_LEADS: [(i64, i64); 3] = [(0x4f, 40), (0x27, 6), (0x21, 1)];
+
+// The base for the code point after `c`.
+fn next_base(c: u32) -> i64 {
+    match c {
+        0x3040..=0x309f => 0x3070,
+        0x4e00..=0x9fa5 => 0x7711,
+        0xac00..=0xd7a3 => 0xc1d1,
+        _ => (c & !0x7f) as i64 + 0x40,
+    }
+}
+
+fn encode_difference(diff: i64, out: &mut Vec<u8>) {
+    if (-SINGLE..SINGLE).contains(&diff) {
+        out.push((MIDDLE + diff) as u8);
+        return;
+    }
+    // Offset of the difference from the end of the 1-byte range.
+    let (mut m, leads, sign) = if diff > 0 {
+        (diff - SINGLE, POSITIVE_LEADS, 1)
+    } else {
+        (-diff - 1 - SINGLE, NEGATIVE_LEADS, -1)
+    };
+    for (trail_count, (first, count)) in leads.iter().enumerate() {
+        let trail_count = trail_count as u32 + 1;
+        let span = 1i64 << (8 * trail_count);
+        if m < count * span || trail_count == 3 {
+            out.push((first + sign * (m / span)) as u8);
+            out.extend_from_slice(&(m % span).to_be_bytes()[8 - trail_count as usize..]);
+            return;
+        }
+        m -= count * span;
+    }
+}
+
+/// Transform UTF-8 text.
+pub fn forward(data: &[u8]) -> Vec<u8> {
+    let text = match std::str::from_utf8(data) {
+        Ok(text) => text,
+        Err(_) => {
+            let mut out = vec![RAW];
+            out.extend_from_slice(data);
+            return out;
+        }
+    };
+    let mut out = vec![CODED];
+    let mut base = INITIAL_BASE;
+    for c in text.chars() {
+        let c = c as u32;
+        if c <= MAX_DIRECT {
+            out.push(c as u8);
+            continue;
+        }
+        encode_difference(c as i64 - base, &mut out);
+        base = next_base(c);
+    }
+    out
+}
+
+/// Recover the source text from the output of [`forward()`].
+pub fn inverse(data: &[u8]) -> Result<Vec<u8>> {
+    let (flag, data) = match data.split_first() {
+        Some(split) => split,
+        None => return Err(anyhow!("Missing BOCU flag")),
+    };
+    match *flag {
+        RAW => return Ok(data.to_vec()),
+        CODED => (),
+        f => return Err(anyhow!("Unknown BOCU flag {}", f)),
+    }
+
+    let mut out = String::with_capacity(data.len());
+    let mut base = INITIAL_BASE;
+    let mut bytes = data.iter().map(|b| *b as i64);
+    while let Some(lead) = bytes.next() {
+        if lead <= MAX_DIRECT as i64 {
+            out.push(lead as u8 as char);
+            continue;
+        }
+        let diff = if (MIDDLE - SINGLE..MIDDLE + SINGLE).contains(&lead) {
+            lead - MIDDLE
+        } else {
+            let (leads, sign) = if lead > MIDDLE {
+                (POSITIVE_LEADS, 1)
+            } else {
+                (NEGATIVE_LEADS, -1)
+            };
+            // Offset of the difference from the end of the 1-byte range.
+            let mut m = 0;
+            let mut found = false;
+            for (trail_count, (first, count)) in leads.iter().enumerate() {
+                let trail_count = trail_count as u32 + 1;
+                let span = 1i64 << (8 * trail_count);
+                let i = sign * (lead - first);
+                if (0..*count).contains(&i) {
+                    m += i * span;
+                    let mut trails = 0;
+                    for _ in 0..trail_count {
+                        let trail = bytes
+                            .next()
+                            .ok_or_else(|| anyhow!("Truncated BOCU sequence"))?;
+                        trails = trails << 8 | trail;
+                    }
+                    m += trails;
+                    found = true;
+                    break;
+                }
+                m += count * span;
+            }
+            if !found {
+                return Err(anyhow!("Invalid BOCU lead byte {:#x}", lead));
+            }
+            if sign > 0 {
+                m + SINGLE
+            } else {
+                -m - 1 - SINGLE
+            }
+        };
+        let c = u32::try_from(base + diff)
+            .ok()
+            .and_then(char::from_u32)
+            .filter(|c| *c as u32 > MAX_DIRECT)
+            .ok_or_else(|| anyhow!("Invalid BOCU code point {}", base + diff))?;
+        out.push(c);
+        base = next_base(c as u32);
+    }
+    Ok(out.into_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TEXT: &str = "\
+Ah! well a-day! what evil looks
+Мне не спится, нет огня;
+मेरा नाम लाल है।
+ひらがな 漢字 한국어 😀
+";
+
+    #[test]
+    fn roundtrip() {
+        for text in [
+            "",
+            TEXT,
+            "\u{10ffff}\u{0}\u{21}\u{10ffff}",
+            "\u{7f}\u{80}\u{e000}",
+        ] {
+            let data = forward(text.as_bytes());
+            assert_eq!(inverse(&data).unwrap(), text.as_bytes());
+        }
+    }
+
+    #[test]
+    fn roundtrip_invalid_utf8() {
+        let data = b"\xff\xfe not utf-8";
+        assert_eq!(forward(data)[0], RAW);
+        assert_eq!(inverse(&forward(data)).unwrap(), data);
+    }
+
+    #[test]
+    fn roundtrip_differences() {
+        let mut out = Vec::new();
+        for diff in [
+            -0x10ffff, -0x10000, -10305, -10304, -65, -64, 0, 63, 64, 10303, 10304, 0x10ffff,
+        ] {
+            out.clear();
+            encode_difference(diff, &mut out);
+            let c = INITIAL_BASE + diff;
+            if (0x21..=0x10ffff).contains(&c) && char::from_u32(c as u32).is_some() {
+                let mut data = vec![CODED];
+                data.extend_from_slice(&out);
+                let got = inverse(&data).unwrap();
+                assert_eq!(
+                    got,
+                    char::from_u32(c as u32).unwrap().to_string().into_bytes()
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn small_alphabets_take_one_byte() {
+        for text in ["сине море", "मेरा नाम"] {
+            // The flag, and 2 bytes for the first jump away from ASCII.
+            let chars = text.chars().count();
+            assert_eq!(forward(text.as_bytes()).len(), 2 + chars);
+        }
+    }
+
+    #[test]
+    fn invalid_input() {
+        for data in [
+            &b""[..],
+            b"\x02",
+            b"\x01\xd0",
+            b"\x01\x21\x00\x00",
+            b"\x01\xff\xff\xff\xff",
+        ] {
+            assert!(inverse(data).is_err(), "{:?}", data);
+        }
+    }
+}
@@ -0,0 +1,177 @@
+// Copyright 2020 Prathmesh Prabhu
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! bwt module implements the [Burrows-Wheeler transform].
+//!
+//! The transform sorts all rotations of the text (terminated by a virtual
+//! end-of-text symbol that sorts before all bytes) and outputs the last byte
+//! of each rotation. Bytes that precede similar contexts end up next to each
+//! other, so the output has long runs of repeated bytes.
+//!
+//! The rotations are sorted via the suffix array of the text. The row of the
+//! end-of-text symbol (the "primary index") is returned separately, and is
+//! needed to invert the transform.
+//!
+//! [Burrows-Wheeler transform]: https://en.wikipedia.org/wiki/Burrows%E2%80%93Wheeler_transform
+
+use anyhow::{anyhow, Result};
+
+/// Returns the transformed `data` and the primary index.
+pub fn forward(data: &[u8]) -> (Vec<u8>, usize) {
+    let n = data.len();
+    let mut out = Vec::with_capacity(n);
+    // The rotation starting with the end-of-text symbol sorts first. Its last
+    // byte is the last byte of the text.
+    if let Some(b) = data.last() {
+        out.push(*b);
+    }
+    let mut primary = 0;
+    for (i, &s) in suffix_array(data).iter().enumerate() {
+        if s == 0 {
+            primary = i + 1;
+        } else {
+            out.push(data[s - 1]);
+        }
+    }
+    (out, primary)
+}
+
+/// Inverts [`forward()`].
+///
+/// Returns an error if `primary` is not a valid primary index for `data`.
+pub fn inverse(data: &[u8], primary: usize) -> Result<Vec<u8>> {
+    let n = data.len();
+    if (n == 0 && primary != 0) || (n > 0 && (primary == 0 || primary > n)) {
+        return Err(anyhow!(
+            "Invalid primary index {} for a block of {} bytes",
+            primary,
+            n
+        ));
+    }
+
+    // Rows of the sorted rotations are indexed 0..=n, where row `primary`
+    // ends with the end-of-text symbol.
+    let byte_at = |row: usize| {
+        if row < primary {
+            data[row]
+        } else {
+            data[row - 1]
+        }
+    };
+
+    // first[b] is the first row that starts with byte b. The first row starts
+    // with the end-of-text symbol.
+    let mut counts = [0usize; 256];
+    for &b in data {
+        counts[usize::from(b)] += 1;
+    }
+    let mut first = [0usize; 256];
+    let mut total = 1;
+    for b in 0..256 {
+        first[b] = total;
+        total += counts[b];
+    }
+
+    // next[row] is the row of the rotation one step to the left.
+    let mut seen = [0usize; 256];
+    let mut next = vec![0usize; n + 1];
+    for (row, item) in next.iter_mut().enumerate() {
+        if row == primary {
+            continue;
+        }
+        let b = usize::from(byte_at(row));
+        *item = first[b] + seen[b];
+        seen[b] += 1;
+    }
+
+    let mut out = vec![0u8; n];
+    let mut row = 0;
+    for i in (0..n).rev() {
+        out[i] = byte_at(row);
+        row = next[row];
+    }
+    Ok(out)
+}
+
+// Returns the start of each suffix of `data`, in sorted order.
+//
+// Sorts by prefix doubling: after the round for `k`, suffixes are ranked by
+// their first `2k` bytes. A suffix that is a prefix of another sorts first.
+fn suffix_array(data: &[u8]) -> Vec<usize> {
+    let n = data.len();
+    let mut sa: Vec<usize> = (0..n).collect();
+    let mut rank: Vec<usize> = data.iter().map(|b| usize::from(*b) + 1).collect();
+    let mut next_rank = vec![0usize; n];
+    let mut k = 1;
+    while k < n {
+        let key = |i: usize| (rank[i], if i + k < n { rank[i + k] } else { 0 });
+        sa.sort_unstable_by_key(|&i| key(i));
+        next_rank[sa[0]] = 1;
+        for j in 1..n {
+            next_rank[sa[j]] = next_rank[sa[j - 1]] + usize::from(key(sa[j - 1]) != key(sa[j]));
+        }
+        std::mem::swap(&mut rank, &mut next_rank);
+        if rank[sa[n - 1]] == n {
+            break;
+        }
+        k *= 2;
+    }
+    sa
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn banana() {
+        // Rotations of "banana$", sorted:
+        //   $banana, a$banan, ana$ban, anana$b, banana$, na$bana, nana$ba
+        let (got, primary) = forward(b"banana");
+        assert_eq!(got, b"annbaa");
+        assert_eq!(primary, 4);
+        assert_eq!(inverse(&got, primary).unwrap(), b"banana");
+    }
+
+    #[test]
+    fn roundtrip() {
+        for data in [
+            &b""[..],
+            &b"a"[..],
+            &b"aaaaaaaa"[..],
+            &b"abababab"[..],
+            &b"mississippi"[..],
+            &[0u8, 255, 0, 255, 1][..],
+        ] {
+            let (got, primary) = forward(data);
+            assert_eq!(inverse(&got, primary).unwrap(), data);
+        }
+    }
+
+    #[test]
+    fn suffix_array_sorts_suffixes() {
+        let data = b"abracadabra";
+        let sa = suffix_array(data);
+        for w in sa.windows(2) {
+            assert!(data[w[0]..] < data[w[1]..]);
+        }
+    }
+
+    #[test]
+    fn invalid_primary_index() {
+        assert!(inverse(b"annbaa", 0).is_err());
+        assert!(inverse(b"annbaa", 7).is_err());
+        assert!(inverse(b"", 1).is_err());
+    }
+}
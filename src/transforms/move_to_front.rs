@@ -0,0 +1,68 @@
+// Copyright 2020 Prathmesh Prabhu
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! move_to_front module implements the [move-to-front transform].
+//!
+//! Each byte is replaced by its position in a list of all byte values, and
+//! then moved to the front of the list. Runs of a repeated byte turn into runs
+//! of 0s, and recently seen bytes get small values.
+//!
+//! [move-to-front transform]: https://en.wikipedia.org/wiki/Move-to-front_transform
+
+fn initial_order() -> Vec<u8> {
+    (0..=255).collect()
+}
+
+pub fn forward(data: &[u8]) -> Vec<u8> {
+    let mut order = initial_order();
+    data.iter()
+        .map(|b| {
+            let i = order.iter().position(|o| o == b).unwrap();
+            order.remove(i);
+            order.insert(0, *b);
+            i as u8
+        })
+        .collect()
+}
+
+pub fn inverse(data: &[u8]) -> Vec<u8> {
+    let mut order = initial_order();
+    data.iter()
+        .map(|i| {
+            let b = order.remove(usize::from(*i));
+            order.insert(0, b);
+            b
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn runs_become_zeros() {
+        assert_eq!(forward(b"aaabbba"), vec![97, 0, 0, 98, 0, 0, 1]);
+    }
+
+    #[test]
+    fn roundtrip() {
+        let data: Vec<u8> = b"bananaaa"
+            .iter()
+            .chain([0u8, 255, 255].iter())
+            .cloned()
+            .collect();
+        assert_eq!(inverse(&forward(&data)), data);
+    }
+}
@@ -0,0 +1,131 @@
+// Copyright 2020 Prathmesh Prabhu
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! zero_runs module implements run-length coding of 0s, as in bzip2.
+//!
+//! A run of `r` 0s is written as the digits of `r` in bijective base 2, least
+//! significant digit first, using the symbols `RUN_A` (1) and `RUN_B` (2). A run
+//! of a million 0s takes just 20 symbols.
+//!
+//! So that the output is still a byte string, other values are shifted up to
+//! make room for the two run symbols. The two largest values do not fit and are
+//! written as an `ESCAPE` byte followed by a byte for the value.
+
+use anyhow::{anyhow, Result};
+
+const RUN_A: u8 = 0;
+const RUN_B: u8 = 1;
+const ESCAPE: u8 = 255;
+// Values larger than this are escaped.
+const MAX_SHIFTED: u8 = ESCAPE - 2;
+
+pub fn forward(data: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(data.len());
+    let mut run = 0usize;
+    for &b in data {
+        if b == 0 {
+            run += 1;
+            continue;
+        }
+        write_run(run, &mut out);
+        run = 0;
+        if b <= MAX_SHIFTED {
+            out.push(b + 1);
+        } else {
+            out.push(ESCAPE);
+            out.push(b - MAX_SHIFTED - 1);
+        }
+    }
+    write_run(run, &mut out);
+    out
+}
+
+fn write_run(mut run: usize, out: &mut Vec<u8>) {
+    while run > 0 {
+        if run % 2 == 1 {
+            out.push(RUN_A);
+            run = (run - 1) / 2;
+        } else {
+            out.push(RUN_B);
+            run = (run - 2) / 2;
+        }
+    }
+}
+
+/// Inverts [`forward()`].
+///
+/// Returns an error if the data ends in the middle of an escaped value.
+pub fn inverse(data: &[u8]) -> Result<Vec<u8>> {
+    let mut out = Vec::with_capacity(data.len());
+    let mut run = 0usize;
+    let mut weight = 1usize;
+    let mut bytes = data.iter();
+    while let Some(&b) = bytes.next() {
+        match b {
+            RUN_A | RUN_B => {
+                run += weight * usize::from(b - RUN_A + 1);
+                weight *= 2;
+                continue;
+            }
+            ESCAPE => match bytes.next() {
+                Some(e) if *e < ESCAPE - MAX_SHIFTED => {
+                    flush_run(&mut run, &mut weight, &mut out);
+                    out.push(e + MAX_SHIFTED + 1);
+                }
+                _ => return Err(anyhow!("Invalid escaped value")),
+            },
+            b => {
+                flush_run(&mut run, &mut weight, &mut out);
+                out.push(b - 1);
+            }
+        }
+    }
+    flush_run(&mut run, &mut weight, &mut out);
+    Ok(out)
+}
+
+fn flush_run(run: &mut usize, weight: &mut usize, out: &mut Vec<u8>) {
+    out.resize(out.len() + *run, 0);
+    *run = 0;
+    *weight = 1;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn runs() {
+        // Runs of 1, 2, 3 and 4 zeros.
+        assert_eq!(
+            forward(&[0, 5, 0, 0, 5, 0, 0, 0, 5, 0, 0, 0, 0]),
+            vec![RUN_A, 6, RUN_B, 6, RUN_A, RUN_A, 6, RUN_B, RUN_A]
+        );
+    }
+
+    #[test]
+    fn escapes() {
+        assert_eq!(forward(&[253, 254, 255]), vec![254, ESCAPE, 0, ESCAPE, 1]);
+        assert!(inverse(&[ESCAPE]).is_err());
+        assert!(inverse(&[ESCAPE, 2]).is_err());
+    }
+
+    #[test]
+    fn roundtrip() {
+        let mut data = vec![0u8; 1000];
+        data.extend(0..=255);
+        data.extend(vec![0u8; 37]);
+        assert_eq!(inverse(&forward(&data)).unwrap(), data);
+    }
+}
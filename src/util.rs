@@ -12,5 +12,50 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use anyhow::{anyhow, Result};
+use std::io::Read;
+
 #[cfg(test)]
 pub mod testing;
+
+pub fn pack_u64(s: u64) -> Vec<u8> {
+    s.to_be_bytes().to_vec()
+}
+
+pub fn unpack_u64<R: std::io::Read>(mut r: R) -> Result<u64> {
+    let mut buf: [u8; 8] = [0; 8];
+    r.read_exact(&mut buf)?;
+    Ok(u64::from_be_bytes(buf))
+}
+
+/// Read a byte string written as its length (with [`pack_u64()`]) followed by
+/// its bytes.
+///
+/// The bytes are read as they arrive rather than into a buffer allocated for
+/// the length up front, so that a corrupted length returns an error instead of
+/// exhausting memory.
+pub fn unpack_bytes<R: std::io::Read>(mut r: R) -> Result<Vec<u8>> {
+    let len = unpack_u64(&mut r)?;
+    let mut buf = Vec::new();
+    r.take(len).read_to_end(&mut buf)?;
+    if buf.len() as u64 != len {
+        return Err(anyhow!("Expected {} bytes, found {}", len, buf.len()));
+    }
+    Ok(buf)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bytes() {
+        let mut data = pack_u64(3);
+        data.extend_from_slice(b"abcd");
+        assert_eq!(unpack_bytes(&data[..]).unwrap(), b"abc");
+        assert!(unpack_bytes(&data[..10]).is_err());
+        let mut data = pack_u64(u64::MAX);
+        data.extend_from_slice(b"abc");
+        assert!(unpack_bytes(&data[..]).is_err());
+    }
+}
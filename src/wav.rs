@@ -0,0 +1,778 @@
+// Copyright 2020 Prathmesh Prabhu
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! wav module implements lossless compression of 16 and 24-bit PCM [WAV]
+//! audio with linear prediction, in the manner of [FLAC].
+//!
+//! The samples of each channel are split into frames of [`FRAME_LENGTH`]
+//! samples. Each sample is predicted from the samples before it in the same
+//! channel and frame with one of:
+//!
+//! - fixed polynomial predictors of order 0 to 4. The order with the smallest
+//!   residuals is picked for each frame.
+//! - a linear predictor of a given order, with coefficients computed for each
+//!   frame from the autocorrelation of its samples (with the Levinson-Durbin
+//!   recursion). As in FLAC, the samples are tapered with a (Welch) window
+//!   ahead of the autocorrelation. The coefficients are quantized to a fixed
+//!   precision, so that prediction is exact integer arithmetic. The linear
+//!   predictor is only used for frames where it codes smaller than the best
+//!   fixed predictor (including the cost of its coefficients).
+//!
+//! The difference between each sample and its prediction (the residual) is
+//! then coded either with [Rice codes], with a parameter picked for each
+//! frame, or as in [`lz77`](crate::lz77): a bucket, coded as a token stream
+//! for each channel with its own [`Encoding`](crate::encoding::Encoding),
+//! followed by raw extra bits that locate the residual within the bucket.
+//! Most residuals occur only a few times, so coding them as whole tokens
+//! would give a large dictionary.
+//!
+//! The choice of predictor (and the Rice parameters) for each frame is
+//! included in the compressed output, as are the bytes before and after the
+//! samples (e.g. the RIFF header), so the output is byte-for-byte identical to
+//! the source. Text that is not a PCM WAV file is compressed as a single
+//! stream of bytes instead.
+//!
+//! [WAV]: https://en.wikipedia.org/wiki/WAV
+//! [FLAC]: https://xiph.org/flac/format.html
+//! [Rice codes]: https://en.wikipedia.org/wiki/Golomb_coding#Rice_coding
+
+use crate::encoding::EncodingScheme;
+use crate::lz77::{bucket, bucket_base};
+use crate::stream;
+use crate::tokens::bytes::Byte;
+use crate::util::{pack_u64, unpack_bytes, unpack_u64};
+use anyhow::{anyhow, Result};
+use std::convert::{TryFrom, TryInto};
+use std::io::Read;
+
+/// The number of samples of each channel in a frame.
+pub const FRAME_LENGTH: usize = 4096;
+/// The maximum order of the linear predictor.
+pub const MAX_LPC_ORDER: u8 = 32;
+
+// The number of fractional bits in quantized linear predictor coefficients.
+const SHIFT: u32 = 12;
+// Quantized coefficients are clamped to this magnitude.
+const MAX_COEFFICIENT: i64 = 1 << 20;
+// Rice codes with a quotient of at least this much are written as the escape
+// quotient followed by the value in ESCAPE_BITS bits.
+const ESCAPE_QUOTIENT: u64 = 32;
+const ESCAPE_BITS: u64 = 32;
+const MAX_RICE_PARAMETER: u8 = 30;
+
+// Markers for the mode of the compressed text.
+const RAW: u8 = 0;
+const AUDIO: u8 = 1;
+
+// Marker for a linear predictor in the side information of a frame. Fixed
+// predictors are marked with their order.
+const LPC_FILTER: u8 = 5;
+
+/// The layout of the samples in a PCM WAV file.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Layout {
+    pub channels: usize,
+    /// The number of bytes in each sample, 2 or 3.
+    pub sample_size: usize,
+    /// The offset of the first sample.
+    pub start: usize,
+    /// The number of samples in each channel.
+    pub frames: usize,
+}
+
+fn u16_at(data: &[u8], i: usize) -> Option<u16> {
+    Some(u16::from_le_bytes(data.get(i..i + 2)?.try_into().ok()?))
+}
+
+fn u32_at(data: &[u8], i: usize) -> Option<usize> {
+    Some(u32::from_le_bytes(data.get(i..i + 4)?.try_into().ok()?) as usize)
+}
+
+/// Parse the RIFF header of the PCM WAV file in `data`.
+///
+/// Returns `None` unless `data` is a WAV file with 16 or 24-bit PCM samples,
+/// and a `fmt ` chunk before the `data` chunk.
+pub fn parse(data: &[u8]) -> Option<Layout> {
+    if data.get(0..4)? != b"RIFF" || data.get(8..12)? != b"WAVE" {
+        return None;
+    }
+    let mut format = None;
+    let mut i = 12;
+    loop {
+        let id = data.get(i..i + 4)?;
+        let len = u32_at(data, i + 4)?;
+        let body = i + 8;
+        match id {
+            b"fmt " => {
+                let tag = u16_at(data, body)?;
+                let channels = u16_at(data, body + 2)? as usize;
+                let block_align = u16_at(data, body + 12)? as usize;
+                let bits = u16_at(data, body + 14)?;
+                if !(tag == 1 || tag == 0xfffe) || !(bits == 16 || bits == 24) || channels == 0 {
+                    return None;
+                }
+                let sample_size = bits as usize / 8;
+                if block_align != channels * sample_size {
+                    return None;
+                }
+                format = Some((channels, sample_size));
+            }
+            b"data" => {
+                let (channels, sample_size) = format?;
+                let len = len.min(data.len() - body.min(data.len()));
+                return Some(Layout {
+                    channels,
+                    sample_size,
+                    start: body,
+                    frames: len / (channels * sample_size),
+                });
+            }
+            _ => (),
+        }
+        // Chunks are padded to an even length.
+        i = body.checked_add(len)?.checked_add(len % 2)?;
+    }
+}
+
+fn read_sample(bytes: &[u8]) -> i64 {
+    let mut v = 0i64;
+    for b in bytes.iter().rev() {
+        v = v << 8 | *b as i64;
+    }
+    let bits = 8 * bytes.len();
+    (v << (64 - bits)) >> (64 - bits)
+}
+
+fn write_sample(v: i64, size: usize, out: &mut Vec<u8>) {
+    out.extend_from_slice(&v.to_le_bytes()[..size]);
+}
+
+// Predicts each sample of a frame from the samples before it.
+#[derive(Clone, Debug, Eq, PartialEq)]
+enum Filter {
+    // A polynomial predictor of order 0 to 4.
+    Fixed(u8),
+    // A linear predictor with the given quantized coefficients.
+    Lpc(Vec<i64>),
+}
+
+// The fixed polynomial prediction of order `order` for `x[i]`, using as high
+// an order as the samples before `x[i]` allow.
+fn fixed(order: usize, x: &[i64], i: usize) -> i64 {
+    match order.min(i) {
+        0 => 0,
+        1 => x[i - 1],
+        2 => 2 * x[i - 1] - x[i - 2],
+        3 => 3 * x[i - 1] - 3 * x[i - 2] + x[i - 3],
+        _ => 4 * x[i - 1] - 6 * x[i - 2] + 4 * x[i - 3] - x[i - 4],
+    }
+}
+
+impl Filter {
+    // The prediction for `x[i]`, clamped to the range of a sample of `size`
+    // bytes.
+    fn predict(&self, x: &[i64], i: usize, size: usize) -> i64 {
+        let p = match self {
+            Filter::Fixed(order) => fixed(*order as usize, x, i),
+            Filter::Lpc(c) if i < c.len() => fixed(1, x, i),
+            Filter::Lpc(c) => {
+                let sum: i64 = c.iter().enumerate().map(|(j, c)| c * x[i - 1 - j]).sum();
+                sum >> SHIFT
+            }
+        };
+        let max = (1i64 << (8 * size - 1)) - 1;
+        p.clamp(-max - 1, max)
+    }
+
+    fn residuals(&self, x: &[i64], size: usize) -> Vec<i64> {
+        (0..x.len())
+            .map(|i| x[i] - self.predict(x, i, size))
+            .collect()
+    }
+
+    // The fixed predictor that gives the smallest residuals for `x`.
+    fn best_fixed(x: &[i64], size: usize) -> Self {
+        (0..=4)
+            .map(Filter::Fixed)
+            .min_by_key(|f| f.residuals(x, size).iter().map(|r| r.abs()).sum::<i64>())
+            .unwrap()
+    }
+
+    // The linear predictor of `order` for `x`.
+    fn lpc(x: &[i64], order: usize) -> Self {
+        // Welch window.
+        let half = (x.len() as f64 - 1.0) / 2.0;
+        let x: Vec<f64> = x
+            .iter()
+            .enumerate()
+            .map(|(i, v)| {
+                let d = if half > 0.0 {
+                    (i as f64 - half) / half
+                } else {
+                    0.0
+                };
+                *v as f64 * (1.0 - d * d)
+            })
+            .collect();
+        let r: Vec<f64> = (0..=order)
+            .map(|lag| (lag..x.len()).map(|i| x[i] * x[i - lag]).sum())
+            .collect();
+        // Levinson-Durbin recursion: a[j] is the coefficient of x[i - j].
+        let mut a = vec![0f64; order + 1];
+        let mut error = r[0];
+        for i in 1..=order {
+            if error <= 0.0 {
+                break;
+            }
+            let mut acc = r[i];
+            for j in 1..i {
+                acc -= a[j] * r[i - j];
+            }
+            let k = acc / error;
+            let previous = a.clone();
+            a[i] = k;
+            for j in 1..i {
+                a[j] = previous[j] - k * previous[i - j];
+            }
+            error *= 1.0 - k * k;
+        }
+        Filter::Lpc(
+            a[1..]
+                .iter()
+                .map(|c| {
+                    ((c * (1 << SHIFT) as f64).round() as i64)
+                        .clamp(-MAX_COEFFICIENT, MAX_COEFFICIENT)
+                })
+                .collect(),
+        )
+    }
+}
+
+fn zigzag(n: i64) -> u64 {
+    ((n << 1) ^ (n >> 63)) as u64
+}
+
+fn unzigzag(n: u64) -> i64 {
+    (n >> 1) as i64 ^ -((n & 1) as i64)
+}
+
+// The number of bits in the Rice codes of `values` with parameter `k`.
+fn rice_bits(values: &[u64], k: u8) -> u64 {
+    values
+        .iter()
+        .map(|v| match v >> k {
+            q if q >= ESCAPE_QUOTIENT => ESCAPE_QUOTIENT + 1 + ESCAPE_BITS,
+            q => q + 1 + k as u64,
+        })
+        .sum()
+}
+
+// The Rice parameter that codes `values` in the fewest bits.
+fn rice_parameter(values: &[u64]) -> u8 {
+    (0..=MAX_RICE_PARAMETER)
+        .min_by_key(|k| rice_bits(values, *k))
+        .unwrap()
+}
+
+// An estimate of the number of bits to code the frame `x` with `filter`: its
+// side information, and its residuals as Rice codes.
+fn cost(filter: &Filter, x: &[i64], size: usize) -> u64 {
+    let residuals: Vec<u64> = filter.residuals(x, size).into_iter().map(zigzag).collect();
+    let side = match filter {
+        Filter::Fixed(_) => 8,
+        Filter::Lpc(coefficients) => 8 + 32 * coefficients.len() as u64,
+    };
+    side + rice_bits(&residuals, rice_parameter(&residuals))
+}
+
+/// Writes values as a sequence of bits, most significant first.
+#[derive(Debug, Default)]
+pub struct BitWriter {
+    pub data: Vec<u8>,
+    bit_count: u64,
+}
+
+impl BitWriter {
+    pub fn push(&mut self, value: u64, bit_count: u64) {
+        for i in (0..bit_count).rev() {
+            if self.bit_count.is_multiple_of(8) {
+                self.data.push(0);
+            }
+            if value >> i & 1 == 1 {
+                *self.data.last_mut().unwrap() |= 0x80 >> (self.bit_count % 8);
+            }
+            self.bit_count += 1;
+        }
+    }
+
+    fn push_rice(&mut self, value: u64, k: u8) {
+        match value >> k {
+            q if q >= ESCAPE_QUOTIENT => {
+                self.push(0, ESCAPE_QUOTIENT);
+                self.push(1, 1);
+                self.push(value, ESCAPE_BITS);
+            }
+            q => {
+                self.push(0, q);
+                self.push(1, 1);
+                self.push(value, k as u64);
+            }
+        }
+    }
+}
+
+/// Reads back the bits written with a [`BitWriter`].
+pub struct BitReader {
+    pub data: Vec<u8>,
+    // Offset of the next bit to read.
+    pub offset: usize,
+}
+
+impl BitReader {
+    pub fn read(&mut self, bit_count: u64) -> Result<u64> {
+        let mut v = 0;
+        for _ in 0..bit_count {
+            let b = match self.data.get(self.offset / 8) {
+                Some(b) => b,
+                None => return Err(anyhow!("Ran out of bits")),
+            };
+            v = (v << 1) | u64::from((b >> (7 - self.offset % 8)) & 1);
+            self.offset += 1;
+        }
+        Ok(v)
+    }
+
+    fn read_rice(&mut self, k: u8) -> Result<u64> {
+        let mut q = 0;
+        while self.read(1)? == 0 {
+            q += 1;
+            if q > ESCAPE_QUOTIENT {
+                return Err(anyhow!("Invalid Rice code"));
+            }
+        }
+        if q == ESCAPE_QUOTIENT {
+            return self.read(ESCAPE_BITS);
+        }
+        Ok(q << k | self.read(k as u64)?)
+    }
+}
+
+/// Compress `data` with a linear prediction audio front end.
+///
+/// Samples are predicted with fixed polynomial predictors. If `lpc_order` is
+/// not 0, frames are predicted with linear predictors of `lpc_order` instead
+/// where those code smaller. With `rice`, residuals are
+/// Rice coded, else their buckets are compressed with an encoding generated
+/// with `encoding_scheme`. The output can be decompressed with [`unpack()`].
+///
+/// Returns an error if `lpc_order` is larger than [`MAX_LPC_ORDER`].
+pub fn pack<W: std::io::Write>(
+    data: &[u8],
+    encoding_scheme: &EncodingScheme,
+    lpc_order: u8,
+    rice: bool,
+    mut w: W,
+) -> Result<()> {
+    if lpc_order > MAX_LPC_ORDER {
+        return Err(anyhow!(
+            "LPC order must be at most {}, got {}",
+            MAX_LPC_ORDER,
+            lpc_order
+        ));
+    }
+    let layout = match parse(data) {
+        Some(layout) => layout,
+        None => {
+            w.write_all(&[RAW])?;
+            let bytes: Vec<Byte> = data.iter().map(|b| Byte::from(*b)).collect();
+            stream::pack(&bytes, encoding_scheme, &mut w)?;
+            w.flush()?;
+            return Ok(());
+        }
+    };
+
+    // De-interleave the samples.
+    let size = layout.sample_size;
+    let end = layout.start + layout.frames * layout.channels * size;
+    let mut channels = vec![Vec::with_capacity(layout.frames); layout.channels];
+    for (i, s) in data[layout.start..end].chunks(size).enumerate() {
+        channels[i % layout.channels].push(read_sample(s));
+    }
+
+    // Per frame costs are only estimates, so keep the fixed predictors if they
+    // code smaller overall. Their side information is valid for any order.
+    let mut packed = pack_audio(&channels, layout, encoding_scheme, lpc_order, rice)?;
+    if lpc_order > 0 {
+        let fixed = pack_audio(&channels, layout, encoding_scheme, 0, rice)?;
+        if fixed.len() <= packed.len() {
+            packed = fixed;
+        }
+    }
+
+    w.write_all(&[AUDIO, layout.channels as u8, size as u8])?;
+    w.write_all(&pack_u64(layout.start as u64))?;
+    w.write_all(&data[..layout.start])?;
+    w.write_all(&pack_u64(layout.frames as u64))?;
+    w.write_all(&packed)?;
+    w.write_all(&pack_u64((data.len() - end) as u64))?;
+    w.write_all(&data[end..])?;
+    w.flush()?;
+    Ok(())
+}
+
+// The side information and residuals of the de-interleaved `channels`.
+fn pack_audio(
+    channels: &[Vec<i64>],
+    layout: Layout,
+    encoding_scheme: &EncodingScheme,
+    lpc_order: u8,
+    rice: bool,
+) -> Result<Vec<u8>> {
+    let size = layout.sample_size;
+    let mut side = Vec::new();
+    let mut bits = BitWriter::default();
+    let mut tokens = vec![Vec::new(); layout.channels];
+    for frame in 0..layout.frames.div_ceil(FRAME_LENGTH) {
+        let range = frame * FRAME_LENGTH..((frame + 1) * FRAME_LENGTH).min(layout.frames);
+        for (c, samples) in channels.iter().enumerate() {
+            let x = &samples[range.clone()];
+            let mut filter = Filter::best_fixed(x, size);
+            if lpc_order > 0 {
+                let lpc = Filter::lpc(x, lpc_order as usize);
+                if cost(&lpc, x, size) < cost(&filter, x, size) {
+                    filter = lpc;
+                }
+            }
+            match &filter {
+                Filter::Fixed(order) => side.push(*order),
+                Filter::Lpc(coefficients) => {
+                    side.push(LPC_FILTER);
+                    for c in coefficients {
+                        side.extend_from_slice(&(*c as i32).to_be_bytes());
+                    }
+                }
+            }
+            let residuals: Vec<u64> = filter.residuals(x, size).into_iter().map(zigzag).collect();
+            if rice {
+                let k = rice_parameter(&residuals);
+                side.push(k);
+                for r in residuals {
+                    bits.push_rice(r, k);
+                }
+            } else {
+                for r in residuals {
+                    let (code, bit_count, extra) = bucket(r as usize);
+                    tokens[c].push(Byte::from(code));
+                    bits.push(extra as u64, bit_count);
+                }
+            }
+        }
+    }
+
+    let mut w = pack_u64(side.len() as u64);
+    w.extend(&side);
+    if !rice {
+        for t in tokens {
+            stream::pack(&t, encoding_scheme, &mut w)?;
+        }
+    }
+    w.extend(&pack_u64(bits.data.len() as u64));
+    w.extend(&bits.data);
+    Ok(w)
+}
+
+/// Decompress data compressed with [`pack()`].
+///
+/// `lpc_order` and `rice` must be the same as used with [`pack()`].
+pub fn unpack<R: std::io::Read, W: std::io::Write>(
+    mut r: R,
+    lpc_order: u8,
+    rice: bool,
+    mut w: W,
+) -> Result<()> {
+    let mut mode = [0u8];
+    r.read_exact(&mut mode)?;
+    match mode[0] {
+        RAW => {
+            let bytes: Vec<Byte> = stream::unpack(&mut r)?;
+            let data: Vec<u8> = bytes.into_iter().map(u8::from).collect();
+            w.write_all(&data)?;
+            w.flush()?;
+            return Ok(());
+        }
+        AUDIO => (),
+        m => return Err(anyhow!("Unknown audio mode {}", m)),
+    }
+
+    let mut format = [0u8; 2];
+    r.read_exact(&mut format)?;
+    let (channel_count, size) = (format[0] as usize, format[1] as usize);
+    if channel_count == 0 || !(size == 2 || size == 3) {
+        return Err(anyhow!("Invalid audio format {:?}", format));
+    }
+    let header = unpack_bytes(&mut r)?;
+    let frames = usize::try_from(unpack_u64(&mut r)?)?;
+    let mut side = std::io::Cursor::new(unpack_bytes(&mut r)?);
+    let mut tokens = Vec::new();
+    if !rice {
+        for c in 0..channel_count {
+            let t: Vec<Byte> = stream::unpack(&mut r)?;
+            if t.len() != frames {
+                return Err(anyhow!("Wrong number of residuals for channel {}", c));
+            }
+            tokens.push(t.into_iter().map(u8::from));
+        }
+    }
+    let mut bits = BitReader {
+        data: unpack_bytes(&mut r)?,
+        offset: 0,
+    };
+    // The largest zigzag coded residual of a sample and its prediction.
+    let max_residual = (1usize << (8 * size + 1)) - 1;
+    let max_sample = (1i64 << (8 * size - 1)) - 1;
+
+    let mut channels = vec![Vec::new(); channel_count];
+    for frame in 0..frames.div_ceil(FRAME_LENGTH) {
+        let len = FRAME_LENGTH.min(frames - frame * FRAME_LENGTH);
+        for (c, samples) in channels.iter_mut().enumerate() {
+            let mut marker = [0u8];
+            side.read_exact(&mut marker)?;
+            let filter = match marker[0] {
+                order @ 0..=4 => Filter::Fixed(order),
+                LPC_FILTER if lpc_order > 0 => {
+                    let mut coefficients = Vec::new();
+                    for _ in 0..lpc_order {
+                        let mut c = [0u8; 4];
+                        side.read_exact(&mut c)?;
+                        coefficients.push(i32::from_be_bytes(c) as i64);
+                    }
+                    Filter::Lpc(coefficients)
+                }
+                m => return Err(anyhow!("Invalid predictor marker {}", m)),
+            };
+            let mut k = [0u8];
+            if rice {
+                side.read_exact(&mut k)?;
+                if k[0] > MAX_RICE_PARAMETER {
+                    return Err(anyhow!("Invalid Rice parameter {}", k[0]));
+                }
+            }
+            let mut x = Vec::with_capacity(len);
+            for i in 0..len {
+                let residual = if rice {
+                    bits.read_rice(k[0])?
+                } else {
+                    let code = match tokens[c].next() {
+                        Some(code) => code,
+                        None => return Err(anyhow!("Ran out of residuals for channel {}", c)),
+                    };
+                    let (base, bit_count) = bucket_base(code, max_residual)?;
+                    (base + bits.read(bit_count)? as usize) as u64
+                };
+                let sample = filter
+                    .predict(&x, i, size)
+                    .checked_add(unzigzag(residual))
+                    .filter(|s| (-max_sample - 1..=max_sample).contains(s))
+                    .ok_or_else(|| anyhow!("Invalid residual {} for channel {}", residual, c))?;
+                x.push(sample);
+            }
+            samples.extend(x);
+        }
+    }
+
+    let mut out = header;
+    for i in 0..frames {
+        for samples in &channels {
+            write_sample(samples[i], size, &mut out);
+        }
+    }
+    out.extend(unpack_bytes(&mut r)?);
+    w.write_all(&out)?;
+    w.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    // A WAV file with a sine wave (plus a bit of noise) in each channel.
+    fn wav(channels: u16, bits: u16, frames: usize, trailer: &[u8]) -> Vec<u8> {
+        let size = bits as usize / 8;
+        let data_len = frames * channels as usize * size;
+        let mut data = Vec::new();
+        data.extend_from_slice(b"RIFF");
+        data.extend_from_slice(&((36 + data_len) as u32).to_le_bytes());
+        data.extend_from_slice(b"WAVEfmt ");
+        data.extend_from_slice(&16u32.to_le_bytes());
+        data.extend_from_slice(&1u16.to_le_bytes());
+        data.extend_from_slice(&channels.to_le_bytes());
+        data.extend_from_slice(&44100u32.to_le_bytes());
+        data.extend_from_slice(&(44100 * channels as u32 * size as u32).to_le_bytes());
+        data.extend_from_slice(&(channels * size as u16).to_le_bytes());
+        data.extend_from_slice(&bits.to_le_bytes());
+        data.extend_from_slice(b"data");
+        data.extend_from_slice(&(data_len as u32).to_le_bytes());
+        let amplitude = (1i64 << (bits - 2)) as f64;
+        for i in 0..frames {
+            for c in 0..channels {
+                let t = i as f64 / (20.0 + 10.0 * c as f64);
+                let v = (amplitude * t.sin()) as i64 + (i as i64 * 7919) % 13;
+                write_sample(v, size, &mut data);
+            }
+        }
+        data.extend_from_slice(trailer);
+        data
+    }
+
+    fn compress(data: &[u8], lpc_order: u8, rice: bool) -> Vec<u8> {
+        let mut buf = Vec::new();
+        pack(data, &EncodingScheme::Huffman, lpc_order, rice, &mut buf).unwrap();
+        buf
+    }
+
+    fn roundtrip(data: &[u8], lpc_order: u8, rice: bool) -> Vec<u8> {
+        let mut got = Vec::new();
+        unpack(
+            Cursor::new(compress(data, lpc_order, rice)),
+            lpc_order,
+            rice,
+            &mut got,
+        )
+        .unwrap();
+        got
+    }
+
+    #[test]
+    fn roundtrip_audio() {
+        for data in [
+            wav(1, 16, 5000, b""),
+            wav(2, 24, 4097, b"LIST\x04\x00\x00\x00abcd"),
+            wav(2, 16, 0, b""),
+            wav(1, 16, 3, b"\x01"),
+        ] {
+            for (lpc_order, rice) in [(0, false), (0, true), (8, false), (8, true), (1, true)] {
+                assert_eq!(roundtrip(&data, lpc_order, rice), data);
+            }
+        }
+    }
+
+    #[test]
+    fn roundtrip_extremes() {
+        let mut data = wav(1, 16, 64, b"");
+        let start = parse(&data).unwrap().start;
+        for (i, s) in data[start..].chunks_mut(2).enumerate() {
+            s.copy_from_slice(&[[0x00, 0x80], [0xff, 0x7f]][i % 3 % 2]);
+        }
+        for (lpc_order, rice) in [(0, true), (0, false), (MAX_LPC_ORDER, true)] {
+            assert_eq!(roundtrip(&data, lpc_order, rice), data);
+        }
+    }
+
+    #[test]
+    fn roundtrip_other() {
+        let mut truncated = wav(1, 16, 100, b"");
+        truncated.truncate(30);
+        for data in [&b""[..], b"RIFF", &wav(1, 8, 10, b"")[..], &truncated[..]] {
+            assert!(parse(data).is_none());
+            assert_eq!(roundtrip(data, 0, true), data);
+        }
+    }
+
+    #[test]
+    fn layout() {
+        assert_eq!(
+            parse(&wav(2, 24, 10, b"x")),
+            Some(Layout {
+                channels: 2,
+                sample_size: 3,
+                start: 44,
+                frames: 10,
+            })
+        );
+    }
+
+    #[test]
+    fn prediction_helps() {
+        let data = wav(2, 16, 20000, b"");
+        for (lpc_order, rice) in [(0, false), (0, true), (8, false), (8, true)] {
+            assert!(compress(&data, lpc_order, rice).len() < data.len() / 2);
+        }
+    }
+
+    #[test]
+    fn lpc_no_larger_than_fixed() {
+        let mut noise = wav(1, 16, 20000, b"");
+        let mut seed = 1u64;
+        for b in &mut noise[44..] {
+            seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1);
+            *b = (seed >> 33) as u8;
+        }
+        for data in [wav(2, 16, 20000, b""), wav(1, 24, 20000, b""), noise] {
+            for rice in [false, true] {
+                let fixed = compress(&data, 0, rice).len();
+                for lpc_order in [1, 2, 8, MAX_LPC_ORDER] {
+                    assert!(compress(&data, lpc_order, rice).len() <= fixed);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn buckets_match_rice_codes() {
+        for (channels, bits) in [(2, 16), (1, 24)] {
+            let data = wav(channels, bits, 20000, b"");
+            for lpc_order in [0, 8] {
+                let rice = compress(&data, lpc_order, true).len();
+                assert!(compress(&data, lpc_order, false).len() < rice * 11 / 10);
+            }
+        }
+    }
+
+    #[test]
+    fn invalid_residuals() {
+        let buf = compress(&wav(1, 24, 100, b""), 0, true);
+        // Replace the Rice codes with residuals that are out of the range of
+        // samples.
+        let mut r = Cursor::new(&buf[3..]);
+        unpack_bytes(&mut r).unwrap();
+        unpack_u64(&mut r).unwrap();
+        unpack_bytes(&mut r).unwrap();
+        let mut corrupted = buf[..3 + r.position() as usize].to_vec();
+        let mut w = BitWriter::default();
+        for _ in 0..100 {
+            w.push_rice(u32::MAX as u64, 0);
+        }
+        corrupted.extend(pack_u64(w.data.len() as u64));
+        corrupted.extend(w.data);
+        corrupted.extend(pack_u64(0));
+        assert!(unpack(Cursor::new(corrupted), 0, true, Vec::new()).is_err());
+    }
+
+    #[test]
+    fn rice_codes() {
+        let mut w = BitWriter::default();
+        for (v, k) in [(0, 0), (5, 1), (1 << 31, 2), (37, 3)] {
+            w.push_rice(v, k);
+        }
+        let mut r = BitReader {
+            data: w.data,
+            offset: 0,
+        };
+        for (v, k) in [(0, 0), (5, 1), (1 << 31, 2), (37, 3)] {
+            assert_eq!(r.read_rice(k).unwrap(), v);
+        }
+    }
+}
@@ -0,0 +1,100 @@
+// Copyright 2020 Prathmesh Prabhu
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+extern crate test_bin;
+
+use anyhow::Result;
+use cshannon::{
+    register_encoding, run, Args, Charset, Command, CompressArgs, DecompressArgs, EncoderFactory,
+    EncodingScheme, Letter, TokenizationScheme, Transform,
+};
+use std::fs;
+
+const TEXT: &str = "
+Ah! well a-day! what evil looks
+Had I from old and young!
+Instead of the cross, the Albatross
+About my neck was hung.
+";
+
+// Codes the i-th token as the fixed width binary representation of i + 1.
+struct FixedWidth;
+
+impl EncoderFactory for FixedWidth {
+    fn letters(&self, frequencies: &[u64]) -> Result<Vec<Letter>> {
+        let width = 64 - (frequencies.len() as u64).leading_zeros() as u64;
+        Ok((1..=frequencies.len() as u64)
+            .map(|i| Letter::new(&(i << (64 - width)).to_be_bytes(), width))
+            .collect())
+    }
+}
+
+#[test]
+fn custom_encoding() {
+    register_encoding("fixed-width", FixedWidth).unwrap();
+    let work_dir = tempfile::tempdir().unwrap();
+    let input_file = work_dir.path().join("input.txt");
+    let compressed_file = work_dir.path().join("compressed.txt");
+    let decompressed_file = work_dir.path().join("decompressed.txt");
+    fs::write(&input_file, TEXT).unwrap();
+
+    run(Args {
+        command: Command::Compress(CompressArgs {
+            tokenization_scheme: TokenizationScheme::Byte,
+            encoding_scheme: EncodingScheme::Custom {
+                id: "fixed-width".to_owned(),
+            },
+            transform: Transform::None,
+            charset: Charset::Utf8,
+        }),
+        input_file: &input_file,
+        output_file: &compressed_file,
+    })
+    .unwrap();
+    run(Args {
+        command: Command::Decompress(DecompressArgs {}),
+        input_file: &compressed_file,
+        output_file: &decompressed_file,
+    })
+    .unwrap();
+    assert_eq!(fs::read_to_string(&decompressed_file).unwrap(), TEXT);
+}
+
+// The command line tool registers no encodings, so an encoding ID is looked
+// up and rejected.
+#[test]
+fn unknown_encoding_id() {
+    let work_dir = tempfile::tempdir().unwrap();
+    let input_file = work_dir.path().join("input.txt");
+    let compressed_file = work_dir.path().join("compressed.txt");
+    fs::write(&input_file, TEXT).unwrap();
+
+    let output = test_bin::get_test_bin!("cshannon")
+        .args([
+            "-i",
+            input_file.to_str().unwrap(),
+            "-o",
+            compressed_file.to_str().unwrap(),
+            "compress",
+            "-t",
+            "byte",
+            "--encoding-id",
+            "not-registered",
+        ])
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("not-registered"), "{}", stderr);
+}
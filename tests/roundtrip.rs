@@ -32,25 +32,29 @@ About my neck was hung\
 ";
 
 fn roundtrip(text: &str, token: &str, encoding: &str) {
+    roundtrip_with(text, &["compress", "-t", token, "-e", encoding]);
+}
+
+fn roundtrip_with(text: &str, compress_args: &[&str]) {
+    roundtrip_bytes_with(text.as_bytes(), compress_args);
+}
+
+fn roundtrip_bytes_with(data: &[u8], compress_args: &[&str]) {
     // We freely unwrap() here since this is a simplistic integration test.
     let work_dir = tempfile::tempdir().unwrap();
     let input_file = work_dir.path().join("input.txt");
     let compressed_file = work_dir.path().join("compressed.txt");
     let decompressed_file = work_dir.path().join("decompressed.txt");
 
-    fs::write(&input_file, text).unwrap();
+    fs::write(&input_file, data).unwrap();
     assert!(test_bin::get_test_bin!("cshannon")
         .args(&[
             "-i",
             input_file.to_str().unwrap(),
             "-o",
             compressed_file.to_str().unwrap(),
-            "compress",
-            "-t",
-            token,
-            "-e",
-            encoding,
         ])
+        .args(compress_args)
         .status()
         .is_ok());
     assert!(test_bin::get_test_bin!("cshannon")
@@ -63,8 +67,13 @@ fn roundtrip(text: &str, token: &str, encoding: &str) {
         ])
         .status()
         .is_ok());
-    let decompressed_text = fs::read_to_string(&decompressed_file).unwrap();
-    assert_eq!(text, decompressed_text);
+    let decompressed_data = fs::read(&decompressed_file).unwrap();
+    assert_eq!(data, &decompressed_data[..]);
+}
+
+#[test]
+fn bits_balanced_tree() {
+    roundtrip(TEXT, "bits", "balanced-tree");
 }
 
 #[test]
@@ -72,57 +81,688 @@ fn bytes_balanced_tree() {
     roundtrip(TEXT, "byte", "balanced-tree");
 }
 
+#[test]
+fn byte_pairs_balanced_tree() {
+    roundtrip(TEXT, "byte-pair", "balanced-tree");
+}
+
+#[test]
+fn code_points_balanced_tree() {
+    roundtrip(TEXT, "code-point", "balanced-tree");
+}
+
+#[test]
+fn csv_balanced_tree() {
+    roundtrip(TEXT, "csv", "balanced-tree");
+}
+
+#[test]
+fn fasta_balanced_tree() {
+    roundtrip(TEXT, "fasta", "balanced-tree");
+}
+
 #[test]
 fn graphemes_balanced_tree() {
     roundtrip(TEXT, "grapheme", "balanced-tree");
 }
 
+#[test]
+fn integers_balanced_tree() {
+    roundtrip(TEXT, "integer", "balanced-tree");
+}
+
+#[test]
+fn json_balanced_tree() {
+    roundtrip(TEXT, "json", "balanced-tree");
+}
+
+#[test]
+fn lines_balanced_tree() {
+    roundtrip(TEXT, "line", "balanced-tree");
+}
+
+#[test]
+fn log_templates_balanced_tree() {
+    roundtrip(TEXT, "log-template", "balanced-tree");
+}
+
+#[test]
+fn lz77_balanced_tree() {
+    roundtrip(TEXT, "lz77", "balanced-tree");
+}
+
+#[test]
+fn lzw_balanced_tree() {
+    roundtrip(TEXT, "lzw", "balanced-tree");
+}
+
+#[test]
+fn n_grams_balanced_tree() {
+    roundtrip(TEXT, "n-gram", "balanced-tree");
+}
+
+#[test]
+fn pnm_balanced_tree() {
+    roundtrip(TEXT, "pnm", "balanced-tree");
+}
+
+#[test]
+fn wav_balanced_tree() {
+    roundtrip(TEXT, "wav", "balanced-tree");
+}
+
+#[test]
+fn float_balanced_tree() {
+    roundtrip(TEXT, "float", "balanced-tree");
+}
+
+#[test]
+fn strided_balanced_tree() {
+    roundtrip(TEXT, "strided", "balanced-tree");
+}
+
+#[test]
+fn rules_balanced_tree() {
+    roundtrip(TEXT, "rules", "balanced-tree");
+}
+
+#[test]
+fn run_length_balanced_tree() {
+    roundtrip(TEXT, "run-length", "balanced-tree");
+}
+
+#[test]
+fn segments_balanced_tree() {
+    roundtrip(TEXT, "segment", "balanced-tree");
+}
+
 #[test]
 fn words_balanced_tree() {
     roundtrip(TEXT_ONLY_WORDS, "word", "balanced-tree");
 }
 
+#[test]
+fn bits_shannon() {
+    roundtrip(TEXT, "bits", "shannon");
+}
+
 #[test]
 fn bytes_shannon() {
     roundtrip(TEXT, "byte", "shannon");
 }
 
+#[test]
+fn byte_pairs_shannon() {
+    roundtrip(TEXT, "byte-pair", "shannon");
+}
+
+#[test]
+fn code_points_shannon() {
+    roundtrip(TEXT, "code-point", "shannon");
+}
+
+#[test]
+fn csv_shannon() {
+    roundtrip(TEXT, "csv", "shannon");
+}
+
+#[test]
+fn fasta_shannon() {
+    roundtrip(TEXT, "fasta", "shannon");
+}
+
 #[test]
 fn graphemes_shannon() {
     roundtrip(TEXT, "grapheme", "shannon");
 }
 
+#[test]
+fn integers_shannon() {
+    roundtrip(TEXT, "integer", "shannon");
+}
+
+#[test]
+fn json_shannon() {
+    roundtrip(TEXT, "json", "shannon");
+}
+
+#[test]
+fn lines_shannon() {
+    roundtrip(TEXT, "line", "shannon");
+}
+
+#[test]
+fn log_templates_shannon() {
+    roundtrip(TEXT, "log-template", "shannon");
+}
+
+#[test]
+fn lz77_shannon() {
+    roundtrip(TEXT, "lz77", "shannon");
+}
+
+#[test]
+fn lzw_shannon() {
+    roundtrip(TEXT, "lzw", "shannon");
+}
+
+#[test]
+fn n_grams_shannon() {
+    roundtrip(TEXT, "n-gram", "shannon");
+}
+
+#[test]
+fn pnm_shannon() {
+    roundtrip(TEXT, "pnm", "shannon");
+}
+
+#[test]
+fn wav_shannon() {
+    roundtrip(TEXT, "wav", "shannon");
+}
+
+#[test]
+fn float_shannon() {
+    roundtrip(TEXT, "float", "shannon");
+}
+
+#[test]
+fn strided_shannon() {
+    roundtrip(TEXT, "strided", "shannon");
+}
+
+#[test]
+fn rules_shannon() {
+    roundtrip(TEXT, "rules", "shannon");
+}
+
+#[test]
+fn run_length_shannon() {
+    roundtrip(TEXT, "run-length", "shannon");
+}
+
+#[test]
+fn segments_shannon() {
+    roundtrip(TEXT, "segment", "shannon");
+}
+
 #[test]
 fn words_shannon() {
     roundtrip(TEXT_ONLY_WORDS, "word", "shannon");
 }
 
+#[test]
+fn bits_fano() {
+    roundtrip(TEXT, "bits", "fano");
+}
+
 #[test]
 fn bytes_fano() {
     roundtrip(TEXT, "byte", "fano");
 }
 
+#[test]
+fn byte_pairs_fano() {
+    roundtrip(TEXT, "byte-pair", "fano");
+}
+
+#[test]
+fn code_points_fano() {
+    roundtrip(TEXT, "code-point", "fano");
+}
+
+#[test]
+fn csv_fano() {
+    roundtrip(TEXT, "csv", "fano");
+}
+
+#[test]
+fn fasta_fano() {
+    roundtrip(TEXT, "fasta", "fano");
+}
+
 #[test]
 fn graphemes_fano() {
     roundtrip(TEXT, "grapheme", "fano");
 }
 
+#[test]
+fn integers_fano() {
+    roundtrip(TEXT, "integer", "fano");
+}
+
+#[test]
+fn json_fano() {
+    roundtrip(TEXT, "json", "fano");
+}
+
+#[test]
+fn lines_fano() {
+    roundtrip(TEXT, "line", "fano");
+}
+
+#[test]
+fn log_templates_fano() {
+    roundtrip(TEXT, "log-template", "fano");
+}
+
+#[test]
+fn lz77_fano() {
+    roundtrip(TEXT, "lz77", "fano");
+}
+
+#[test]
+fn lzw_fano() {
+    roundtrip(TEXT, "lzw", "fano");
+}
+
+#[test]
+fn n_grams_fano() {
+    roundtrip(TEXT, "n-gram", "fano");
+}
+
+#[test]
+fn pnm_fano() {
+    roundtrip(TEXT, "pnm", "fano");
+}
+
+#[test]
+fn wav_fano() {
+    roundtrip(TEXT, "wav", "fano");
+}
+
+#[test]
+fn float_fano() {
+    roundtrip(TEXT, "float", "fano");
+}
+
+#[test]
+fn strided_fano() {
+    roundtrip(TEXT, "strided", "fano");
+}
+
+#[test]
+fn rules_fano() {
+    roundtrip(TEXT, "rules", "fano");
+}
+
+#[test]
+fn run_length_fano() {
+    roundtrip(TEXT, "run-length", "fano");
+}
+
+#[test]
+fn segments_fano() {
+    roundtrip(TEXT, "segment", "fano");
+}
+
 #[test]
 fn words_fano() {
     roundtrip(TEXT_ONLY_WORDS, "word", "fano");
 }
 
+#[test]
+fn bits_huffman() {
+    roundtrip(TEXT, "bits", "huffman");
+}
+
 #[test]
 fn bytes_huffman() {
     roundtrip(TEXT, "byte", "huffman");
 }
 
+#[test]
+fn byte_pairs_huffman() {
+    roundtrip(TEXT, "byte-pair", "huffman");
+}
+
+#[test]
+fn code_points_huffman() {
+    roundtrip(TEXT, "code-point", "huffman");
+}
+
+#[test]
+fn csv_huffman() {
+    roundtrip(TEXT, "csv", "huffman");
+}
+
+#[test]
+fn fasta_huffman() {
+    roundtrip(TEXT, "fasta", "huffman");
+}
+
 #[test]
 fn graphemes_huffman() {
     roundtrip(TEXT, "grapheme", "huffman");
 }
 
+#[test]
+fn integers_huffman() {
+    roundtrip(TEXT, "integer", "huffman");
+}
+
+#[test]
+fn json_huffman() {
+    roundtrip(TEXT, "json", "huffman");
+}
+
+#[test]
+fn lines_huffman() {
+    roundtrip(TEXT, "line", "huffman");
+}
+
+#[test]
+fn log_templates_huffman() {
+    roundtrip(TEXT, "log-template", "huffman");
+}
+
+#[test]
+fn lz77_huffman() {
+    roundtrip(TEXT, "lz77", "huffman");
+}
+
+#[test]
+fn lzw_huffman() {
+    roundtrip(TEXT, "lzw", "huffman");
+}
+
+#[test]
+fn n_grams_huffman() {
+    roundtrip(TEXT, "n-gram", "huffman");
+}
+
+#[test]
+fn pnm_huffman() {
+    roundtrip(TEXT, "pnm", "huffman");
+}
+
+#[test]
+fn wav_huffman() {
+    roundtrip(TEXT, "wav", "huffman");
+}
+
+#[test]
+fn float_huffman() {
+    roundtrip(TEXT, "float", "huffman");
+}
+
+#[test]
+fn strided_huffman() {
+    roundtrip(TEXT, "strided", "huffman");
+}
+
+#[test]
+fn rules_huffman() {
+    roundtrip(TEXT, "rules", "huffman");
+}
+
+#[test]
+fn run_length_huffman() {
+    roundtrip(TEXT, "run-length", "huffman");
+}
+
+#[test]
+fn segments_huffman() {
+    roundtrip(TEXT, "segment", "huffman");
+}
+
 #[test]
 fn words_huffman() {
     roundtrip(TEXT_ONLY_WORDS, "word", "huffman");
 }
+
+#[test]
+fn unix_compress() {
+    roundtrip_with(TEXT, &["unix-compress"]);
+    roundtrip_with(TEXT, &["unix-compress", "-b", "9"]);
+}
+
+#[test]
+fn bwt_transform() {
+    roundtrip_with(
+        TEXT,
+        &[
+            "compress",
+            "-t",
+            "byte",
+            "-e",
+            "huffman",
+            "--transform",
+            "bwt",
+        ],
+    );
+    roundtrip_with(
+        TEXT,
+        &[
+            "compress",
+            "-t",
+            "lz77",
+            "-e",
+            "huffman",
+            "--transform",
+            "bwt",
+            "--block-size",
+            "100",
+        ],
+    );
+}
+
+#[test]
+fn bocu_transform() {
+    for text in [TEXT, "Мне не спится, нет огня;\nमेरा नाम लाल है।\n"]
+    {
+        roundtrip_with(
+            text,
+            &[
+                "compress",
+                "-t",
+                "byte",
+                "-e",
+                "huffman",
+                "--transform",
+                "bocu",
+            ],
+        );
+    }
+}
+
+#[test]
+fn charsets() {
+    let utf16le: Vec<u8> = "\u{feff}Мне не спится, нет огня;\n"
+        .encode_utf16()
+        .flat_map(u16::to_le_bytes)
+        .collect();
+    let shift_jis = b"\x82\xd0\x82\xe7\x82\xaa\x82\xc8 \x8a\xbf\x8e\x9a\n";
+    for (data, charset) in [
+        (&utf16le[..], "utf16le"),
+        (&utf16le[..], "auto"),
+        (&shift_jis[..], "shift-jis"),
+        (&shift_jis[..], "auto"),
+        (&b"caf\xe9\n"[..], "latin1"),
+        // Not UTF-16, so compressed as is.
+        (&b"odd"[..], "utf16be"),
+        (TEXT.as_bytes(), "auto"),
+        // Not text, so compressed as is.
+        (&b"\x00\x01\x80\xff\xfe"[..], "auto"),
+    ] {
+        for tokenizer in ["word", "grapheme", "byte"] {
+            roundtrip_bytes_with(
+                data,
+                &[
+                    "compress",
+                    "-t",
+                    tokenizer,
+                    "-e",
+                    "huffman",
+                    "--charset",
+                    charset,
+                ],
+            );
+        }
+    }
+}
+
+#[test]
+fn integer_formats() {
+    for format in [
+        &["--width", "u8", "--delta"][..],
+        &["--width", "u16", "--endianness", "big", "--zigzag"],
+        &["--width", "i32", "--delta", "--zigzag"],
+        &["--width", "u64", "--endianness", "big", "--delta"],
+    ] {
+        let mut args = vec!["compress", "-t", "integer", "-e", "huffman"];
+        args.extend_from_slice(format);
+        roundtrip_with(TEXT, &args);
+    }
+}
+
+#[test]
+fn csv_columns() {
+    let text = "\
+id,name,score\r
+1,\"Prabhu, Prathmesh\",-3\r
+2,\"say \"\"hi\"\"\",17\r
+4,,17";
+    roundtrip_with(text, &["compress", "-t", "csv", "-e", "huffman"]);
+    roundtrip_with(text, &["compress", "-t", "csv", "-e", "huffman", "--delta"]);
+}
+
+#[test]
+fn json_lines() {
+    let text = r#"{"level": "info", "msg": "started", "took_ms": 12}
+{"level": "warn", "msg": "slow \"query\"", "took_ms": 1.5e3, "tags": [null, true]}
+"#;
+    roundtrip_with(text, &["compress", "-t", "json", "-e", "huffman"]);
+}
+
+#[test]
+fn fasta_sequences() {
+    let text = "\
+>chr1
+NNNNACGTACGTTTGAcgtacgtaNNACG
+TTGCA
+@read1
+ACGTNACGTT
++
+IIIIIIIII#
+";
+    for k in ["1", "4", "16"] {
+        roundtrip_with(
+            text,
+            &[
+                "compress",
+                "-t",
+                "fasta",
+                "-e",
+                "huffman",
+                "--kmer-length",
+                k,
+            ],
+        );
+    }
+}
+
+#[test]
+fn pnm_predictors() {
+    // Samples are printable, so that the image is valid utf-8.
+    let text = "P5\n# small gradient\n4 3\n255\nABCDBCDECDEF";
+    for predictor in ["left", "up", "average", "paeth", "med"] {
+        roundtrip_with(
+            text,
+            &[
+                "compress",
+                "-t",
+                "pnm",
+                "-e",
+                "huffman",
+                "--predictor",
+                predictor,
+            ],
+        );
+    }
+}
+
+#[test]
+fn wav_predictors() {
+    // Header fields and samples are ASCII, so that the file is valid utf-8.
+    let text = "RIFF<\0\0\0WAVEfmt \x10\0\0\0\x01\0\x01\0AA\0\0BB\0\0\x02\0\x10\0\
+                data\x18\0\0\0A\x01C\x01E\x01G\x01I\x01K\x01J\x01H\x01F\x01D\x01B\x01@\x01";
+    for lpc_order in ["0", "2"] {
+        roundtrip_with(
+            text,
+            &[
+                "compress",
+                "-t",
+                "wav",
+                "-e",
+                "huffman",
+                "--lpc-order",
+                lpc_order,
+            ],
+        );
+        roundtrip_with(
+            text,
+            &[
+                "compress",
+                "-t",
+                "wav",
+                "-e",
+                "huffman",
+                "--lpc-order",
+                lpc_order,
+                "--rice",
+            ],
+        );
+    }
+}
+
+#[test]
+fn float_widths() {
+    for width in ["f32", "f64"] {
+        for endianness in ["little", "big"] {
+            roundtrip_with(
+                TEXT,
+                &[
+                    "compress",
+                    "-t",
+                    "float",
+                    "-e",
+                    "huffman",
+                    "--float-width",
+                    width,
+                    "--endianness",
+                    endianness,
+                ],
+            );
+        }
+    }
+}
+
+#[test]
+fn strided_strides() {
+    for stride in ["0", "1", "3", "1024"] {
+        roundtrip_with(
+            TEXT,
+            &[
+                "compress", "-t", "strided", "-e", "huffman", "--stride", stride,
+            ],
+        );
+    }
+}
+
+#[test]
+fn bits_lengths() {
+    for k in ["1", "3", "12", "32"] {
+        roundtrip_with(TEXT, &["compress", "-t", "bits", "-e", "huffman", "-k", k]);
+    }
+}
+
+#[test]
+fn rules_patterns() {
+    roundtrip_with(
+        TEXT,
+        &[
+            "compress", "-t", "rules", "-e", "huffman", "-p", "[a-z]+", "-p", " ",
+        ],
+    );
+}